@@ -98,6 +98,7 @@ export class GameplayWorkerConstruct extends Construct {
     compiled_bot_s3: s3.Bucket,
     new_games_sqs: sqs.Queue,
     game_results_sqs: sqs.Queue,
+    build_results_sqs: sqs.Queue,
     cluster: ecs.Cluster,
     game_logs_s3: s3.Bucket
   ) {
@@ -123,6 +124,7 @@ export class GameplayWorkerConstruct extends Construct {
       environment: {
         COMPILED_BOT_S3_BUCKET: compiled_bot_s3.bucketName,
         GAME_RESULTS_QUEUE_URL: game_results_sqs.queueUrl,
+        BUILD_RESULTS_QUEUE_URL: build_results_sqs.queueUrl,
         NEW_GAMES_QUEUE_URL: new_games_sqs.queueUrl,
         GAME_LOGS_S3_BUCKET: game_logs_s3.bucketName,
       },
@@ -135,6 +137,7 @@ export class GameplayWorkerConstruct extends Construct {
 
     new_games_sqs.grantConsumeMessages(task.taskRole);
     game_results_sqs.grantSendMessages(task.taskRole);
+    build_results_sqs.grantSendMessages(task.taskRole);
     const service = new ecs.Ec2Service(this, "results-service", {
       cluster,
       taskDefinition: task,
@@ -277,6 +280,8 @@ export class ScalingAPIConstruct extends Construct {
       "backend-service",
       {
         cluster,
+        // Remote bots wait in the website's memory for the relay, so there must only be one
+        desiredCount: 1,
         circuitBreaker: {
           rollback: true,
         },
@@ -496,6 +501,7 @@ export class ResourcesStack extends cdk.Stack {
       compiled_bot_s3,
       new_games_sqs,
       game_results_sqs,
+      build_results_sqs,
       workerCluster,
       game_logs_s3
     );
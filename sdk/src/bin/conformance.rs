@@ -0,0 +1,91 @@
+//! Checks that a bot answers the engine correctly, whatever its language:
+//!
+//! ```sh
+//! cargo run -p pokerbot-sdk --bin conformance -- [--warm-start] [--game-config] [--timeout-ms 1000] "python3 bot.py"
+//! ```
+
+use std::process::ExitCode;
+use std::time::Duration;
+
+use pokerbot_sdk::conformance::{run_transcript, transcripts};
+
+const USAGE: &str =
+    "Usage: conformance [--warm-start] [--game-config] [--timeout-ms <ms>] <command>";
+
+fn main() -> ExitCode {
+    let mut warm_start = false;
+    let mut game_config = false;
+    let mut timeout = Duration::from_secs(1);
+    let mut command = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--warm-start" => warm_start = true,
+            "--game-config" => game_config = true,
+            "--timeout-ms" => match args.next().and_then(|ms| ms.parse().ok()) {
+                Some(ms) => timeout = Duration::from_millis(ms),
+                None => {
+                    eprintln!("{}", USAGE);
+                    return ExitCode::FAILURE;
+                }
+            },
+            _ => command = Some(arg),
+        }
+    }
+    let Some(command) = command else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+
+    let mut failed = 0;
+    let mut latencies = vec![];
+    let transcripts = transcripts(warm_start, game_config);
+    for transcript in &transcripts {
+        let report = run_transcript(&command, transcript, timeout);
+        println!(
+            "{} {}",
+            if report.passed() { "PASSED" } else { "FAILED" },
+            report.name
+        );
+        for answer in &report.answers {
+            println!(
+                "    {:<24} -> {:<8} {:>8.2}ms{}",
+                answer.message,
+                answer.answer.as_deref().unwrap_or("-"),
+                answer.latency.as_secs_f64() * 1000.0,
+                answer
+                    .error
+                    .as_ref()
+                    .map(|e| format!("  {}", e))
+                    .unwrap_or_default()
+            );
+            latencies.push(answer.latency);
+        }
+        if let Some(e) = &report.error {
+            println!("    {}", e);
+        }
+        if !report.passed() {
+            failed += 1;
+        }
+    }
+
+    latencies.sort();
+    if let (Some(median), Some(max)) = (latencies.get(latencies.len() / 2), latencies.last()) {
+        println!(
+            "\nLatency over {} answers: median {:.2}ms, max {:.2}ms",
+            latencies.len(),
+            median.as_secs_f64() * 1000.0,
+            max.as_secs_f64() * 1000.0
+        );
+    }
+    println!(
+        "{}/{} transcripts passed",
+        transcripts.len() - failed,
+        transcripts.len()
+    );
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
@@ -0,0 +1,85 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+
+use shared::poker::game::Action;
+use shared::poker::protocol::render_action;
+
+use crate::event::Event;
+use crate::state::State;
+
+pub trait Bot {
+    /// Choose an action, whenever it is your turn
+    fn act(&mut self, state: &State) -> Action;
+
+    /// Called for every event, after the state has been updated.
+    /// Bots that keep their own state should reset it on [Event::NewMatch].
+    fn observe(&mut self, _event: &Event, _state: &State) {}
+}
+
+/// Play through stdin and stdout
+pub fn run<B: Bot>(bot: B) -> io::Result<()> {
+    play(bot, io::stdin().lock(), io::stdout().lock())
+}
+
+/// Play through the engine socket, for bots with `"transport": "socket"` in
+/// their bot.json. Stdout can then be used for debug output.
+pub fn run_socket<B: Bot>(bot: B) -> io::Result<()> {
+    let path = std::env::var("ENGINE_SOCKET")
+        .map_err(|_| io::Error::new(io::ErrorKind::NotFound, "ENGINE_SOCKET is not set"))?;
+    let stream = UnixStream::connect(path)?;
+    play(bot, BufReader::new(stream.try_clone()?), stream)
+}
+
+/// Answer the engine's messages from `input` on `output`, until `input` is
+/// closed. Every answer is flushed right away. Lines that don't parse are
+/// reported on stderr and otherwise ignored.
+pub fn play<B: Bot, R: BufRead, W: Write>(mut bot: B, input: R, mut output: W) -> io::Result<()> {
+    let mut state = State::default();
+    for line in input.lines() {
+        let line = line?;
+        let event = match line.parse::<Event>() {
+            Ok(event) => event,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+        state.apply(&event);
+        bot.observe(&event, &state);
+        let answer = match event {
+            Event::NewMatch => "READY".to_string(),
+            Event::Stack { .. } => render_action(&bot.act(&state)),
+            _ => continue,
+        };
+        writeln!(output, "{}", answer)?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct RaiseWithAces;
+
+    impl Bot for RaiseWithAces {
+        fn act(&mut self, state: &State) -> Action {
+            match &state.hole_cards {
+                Some(cards) if cards.iter().all(|card| card.value == 1) => Action::Raise(10),
+                _ if state.to_call() == 0 => Action::Raise(0),
+                _ => Action::Fold,
+            }
+        }
+    }
+
+    #[test]
+    fn answers_every_turn() {
+        let input = "START SB\nPREFLOP As Ad\nSTACK 1 500 2 500\nEND FOLD BB\n\
+                     START BB\nPREFLOP 7c 2d\nSTACK 2 500 10 500\nhello\n\
+                     NEWMATCH\nSTACK 2 500 2 500\n";
+        let mut output = vec![];
+        play(RaiseWithAces, input.as_bytes(), &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "R10\nF\nREADY\nC\n");
+    }
+}
@@ -0,0 +1,404 @@
+//! Protocol conformance checks for any bot, whatever its language. Each
+//! transcript is a hand played out on a scripted deck with scripted actions,
+//! rendered exactly as the engine would render it for the bot's seat. The bot
+//! answers every `STACK` (and `NEWMATCH`), and each answer is checked with the
+//! engine's own parser. The transcripts don't depend on the bot's answers.
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use shared::poker::game::{Action, GameState, PlayerPosition, Round};
+use shared::poker::hands::Card;
+use shared::poker::protocol::{parse_action, EngineCommunication};
+use shared::GameConfig;
+
+/// One line sent to the bot
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub line: String,
+    /// Whether the bot has to answer this line
+    pub expects_answer: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Transcript {
+    pub name: String,
+    pub messages: Vec<Message>,
+}
+
+/// How the bot answered one message
+#[derive(Debug, Clone)]
+pub struct Answer {
+    pub message: String,
+    pub answer: Option<String>,
+    pub latency: Duration,
+    /// What was wrong with the answer, if anything
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TranscriptReport {
+    pub name: String,
+    pub answers: Vec<Answer>,
+    /// A failure that stopped the transcript early, e.g. the bot exiting
+    pub error: Option<String>,
+}
+
+impl TranscriptReport {
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.answers.iter().all(|answer| answer.error.is_none())
+    }
+}
+
+fn cards<const N: usize>(cards: [&str; N]) -> [Card; N] {
+    cards.map(|card| card.parse().expect("Invalid card in transcript"))
+}
+
+/// A deck that deals the given hole cards and board, the rest in order
+fn deck(sb: [&str; 2], bb: [&str; 2], board: [&str; 5]) -> Vec<Card> {
+    let (sb, bb, board) = (cards(sb), cards(bb), cards(board));
+    let dealt = [
+        board[4], board[3], board[2], board[1], board[0], bb[1], bb[0], sb[1], sb[0],
+    ];
+    let mut deck = GameState::get_deck();
+    deck.retain(|card| !dealt.contains(card));
+    // GameState deals from the end of the deck
+    deck.extend(dealt);
+    deck
+}
+
+/// Play a hand with scripted actions, calling once the script runs out, and
+/// render everything the engine would send to the player in `seat`
+fn hand(
+    seat: PlayerPosition,
+    stacks: [u32; 2],
+    deck: Vec<Card>,
+    actions: &[Action],
+) -> Vec<Message> {
+    let mut messages = vec![];
+    let mut send = |message: EngineCommunication, expects_answer: bool| {
+        messages.push(Message {
+            line: message.render_for_bot(seat),
+            expects_answer,
+        })
+    };
+    let mut actions = actions.iter().cloned();
+    let mut state = GameState::new(stacks, deck, 100);
+    send(EngineCommunication::StartGame, false);
+    let mut round = None;
+    while !state.round_over() {
+        if round != Some(state.round) {
+            round = Some(state.round);
+            let board = &state.community_cards;
+            send(
+                match state.round {
+                    Round::PreFlop => EngineCommunication::PreFlopCards(
+                        state.player_states[0].hole_cards.clone(),
+                        state.player_states[1].hole_cards.clone(),
+                    ),
+                    Round::Flop => EngineCommunication::FlopCards([board[0], board[1], board[2]]),
+                    Round::Turn => EngineCommunication::TurnCard(board[3]),
+                    Round::River => EngineCommunication::RiverCard(board[4]),
+                    Round::End => unreachable!("The hand is over"),
+                },
+                false,
+            );
+        }
+        if state.whose_turn() == Some(seat) {
+            send(EngineCommunication::get_betting_state(&state), true);
+        }
+        state = state
+            .post_action(actions.next().unwrap_or(Action::Raise(0)))
+            .expect("Scripted actions are valid");
+    }
+    send(EngineCommunication::get_round_end(&state), false);
+    messages
+}
+
+/// Every transcript. The one starting with `NEWMATCH` is only included for
+/// bots that set `warm_start`, and the one starting with `CONFIG` for bots
+/// that set `game_config`.
+pub fn transcripts(warm_start: bool, game_config: bool) -> Vec<Transcript> {
+    use PlayerPosition::{BigBlind, SmallBlind};
+    let stacks = [500, 500];
+    // The small blind wins with aces, the big blind with kings
+    let sb_wins = || deck(["Ah", "Ad"], ["Kc", "Kd"], ["2c", "7d", "9s", "Jh", "3c"]);
+    let bb_wins = || deck(["Kc", "Kd"], ["Ah", "Ad"], ["2c", "7d", "9s", "Jh", "3c"]);
+    // Both players play the board
+    let tie = || deck(["2c", "3d"], ["4h", "5c"], ["As", "Ks", "Qs", "Js", "Ts"]);
+    // Raises on the river: by the small blind, then by the big blind
+    let bets_river = |checks| {
+        let mut actions = vec![Action::Raise(0); checks];
+        actions.push(Action::Raise(10));
+        actions
+    };
+    let (sb_bets_river, bb_bets_river) = (bets_river(7), bets_river(6));
+
+    let mut transcripts = vec![];
+    let mut add = |name: &str, messages: Vec<Message>| {
+        transcripts.push(Transcript {
+            name: name.to_string(),
+            messages,
+        })
+    };
+    for (seat, name) in [(SmallBlind, "small blind"), (BigBlind, "big blind")] {
+        add(
+            &format!("the small blind folds, as the {}", name),
+            hand(seat, stacks, sb_wins(), &[Action::Fold]),
+        );
+        add(
+            &format!("the big blind folds to a raise, as the {}", name),
+            hand(seat, stacks, sb_wins(), &[Action::Raise(10), Action::Fold]),
+        );
+        add(
+            &format!("a checked down tie, as the {}", name),
+            hand(seat, stacks, tie(), &[]),
+        );
+        // Nobody raised, so the big blind counts as the last aggressor
+        add(
+            &format!(
+                "the big blind wins a checked down showdown, as the {}",
+                name
+            ),
+            hand(seat, stacks, bb_wins(), &[]),
+        );
+        add(
+            &format!(
+                "the small blind wins a checked down showdown, as the {}",
+                name
+            ),
+            hand(seat, stacks, sb_wins(), &[]),
+        );
+        add(
+            &format!(
+                "the small blind wins after betting the river, as the {}",
+                name
+            ),
+            hand(seat, stacks, sb_wins(), &sb_bets_river),
+        );
+        add(
+            &format!(
+                "the big blind loses after betting the river, as the {}",
+                name
+            ),
+            hand(seat, stacks, sb_wins(), &bb_bets_river),
+        );
+        add(
+            &format!("an all-in before the flop runs out, as the {}", name),
+            hand(seat, [40, 60], bb_wins(), &[Action::Raise(100)]),
+        );
+        add(
+            &format!("a blind that is all-in from the start, as the {}", name),
+            hand(seat, [1, 60], tie(), &[]),
+        );
+    }
+    if warm_start {
+        let mut messages = vec![Message {
+            line: EngineCommunication::NewMatch.render_for_bot(SmallBlind),
+            expects_answer: true,
+        }];
+        messages.extend(hand(SmallBlind, stacks, sb_wins(), &[]));
+        add("a new match on a warm bot", messages);
+    }
+    if game_config {
+        let mut messages = vec![Message {
+            line: EngineCommunication::Config(GameConfig::default()).render_for_bot(SmallBlind),
+            expects_answer: false,
+        }];
+        messages.extend(hand(SmallBlind, stacks, sb_wins(), &[]));
+        add("a match that starts with its rules", messages);
+    }
+    transcripts
+}
+
+/// Check one answer of the bot to `message`
+fn check_answer(message: &str, answer: &str) -> Option<String> {
+    if message == "NEWMATCH" {
+        return (answer != "READY").then(|| "Expected READY".to_string());
+    }
+    parse_action(answer)
+        .err()
+        .map(|_| "Not an action: expected F, C or R<n>".to_string())
+}
+
+/// A started bot and the lines it has written, with when they arrived
+struct BotUnderTest {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<(String, Instant)>,
+}
+
+impl BotUnderTest {
+    fn start(command: &str) -> std::io::Result<Self> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if sender.send((line, Instant::now())).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(BotUnderTest {
+            child,
+            stdin,
+            lines,
+        })
+    }
+}
+
+impl Drop for BotUnderTest {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+/// Run one transcript against a fresh copy of the bot. Every answer must
+/// arrive within `timeout`, and the bot must not write anything else.
+pub fn run_transcript(
+    command: &str,
+    transcript: &Transcript,
+    timeout: Duration,
+) -> TranscriptReport {
+    let mut report = TranscriptReport {
+        name: transcript.name.clone(),
+        answers: vec![],
+        error: None,
+    };
+    let mut bot = match BotUnderTest::start(command) {
+        Ok(bot) => bot,
+        Err(e) => {
+            report.error = Some(format!("Could not start the bot: {}", e));
+            return report;
+        }
+    };
+    for message in &transcript.messages {
+        if let Ok((line, _)) = bot.lines.try_recv() {
+            report.error = Some(format!(
+                "Unexpected output {:?} before {:?}. Only answer STACK and NEWMATCH.",
+                line, message.line
+            ));
+            return report;
+        }
+        let sent = Instant::now();
+        if writeln!(bot.stdin, "{}", message.line)
+            .and_then(|_| bot.stdin.flush())
+            .is_err()
+        {
+            report.error = Some(format!("The bot exited before {:?}", message.line));
+            return report;
+        }
+        if !message.expects_answer {
+            continue;
+        }
+        match bot.lines.recv_timeout(timeout) {
+            Ok((answer, received)) => {
+                let error = check_answer(&message.line, answer.trim());
+                report.answers.push(Answer {
+                    message: message.line.clone(),
+                    answer: Some(answer),
+                    latency: received.duration_since(sent),
+                    error,
+                });
+            }
+            Err(e) => {
+                report.answers.push(Answer {
+                    message: message.line.clone(),
+                    answer: None,
+                    latency: sent.elapsed(),
+                    error: Some(match e {
+                        RecvTimeoutError::Timeout => format!(
+                            "No answer within {:?}. Make sure the bot flushes stdout after every line.",
+                            timeout
+                        ),
+                        RecvTimeoutError::Disconnected => "The bot exited".to_string(),
+                    }),
+                });
+                return report;
+            }
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn covers_every_message_form() {
+        let lines = transcripts(true, true)
+            .into_iter()
+            .flat_map(|transcript| transcript.messages)
+            .map(|message| message.line)
+            .collect::<Vec<_>>();
+        for form in [
+            "NEWMATCH",
+            "CONFIG ",
+            "START SB",
+            "START BB",
+            "PREFLOP ",
+            "STACK ",
+            "FLOP ",
+            "TURN ",
+            "RIVER ",
+            "END FOLD SB",
+            "END FOLD BB",
+            "END SHOWDOWN TIE ",
+            "END SHOWDOWN WINNER SB HIDDEN",
+            "END SHOWDOWN WINNER BB HIDDEN",
+            "END SHOWDOWN WINNER SB SHOWN ",
+            "END SHOWDOWN WINNER BB SHOWN ",
+        ] {
+            assert!(
+                lines.iter().any(|line| line.starts_with(form)),
+                "No transcript sends {:?}",
+                form
+            );
+        }
+        // Every line parses with the SDK too
+        for line in lines {
+            assert!(line.parse::<crate::Event>().is_ok(), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn passes_a_conforming_bot() {
+        let command =
+            "while read line; do case $line in STACK*) echo C;; NEWMATCH) echo READY;; esac; done";
+        for transcript in transcripts(true, true) {
+            let report = run_transcript(command, &transcript, Duration::from_secs(5));
+            assert!(report.passed(), "{:?}", report);
+        }
+    }
+
+    #[test]
+    fn fails_invalid_and_missing_answers() {
+        let transcript = &transcripts(false, false)[0];
+        let invalid = run_transcript(
+            "while read line; do case $line in STACK*) echo CALL;; esac; done",
+            transcript,
+            Duration::from_secs(5),
+        );
+        assert!(invalid.answers[0].error.is_some());
+        let silent = run_transcript("cat > /dev/null", transcript, Duration::from_millis(200));
+        assert!(!silent.passed());
+        let chatty = run_transcript(
+            "while read line; do echo $line; done",
+            transcript,
+            Duration::from_secs(5),
+        );
+        assert!(!chatty.passed());
+    }
+}
@@ -0,0 +1,220 @@
+use std::{fmt::Display, str::FromStr};
+
+use shared::poker::game::PlayerPosition;
+use shared::poker::hands::Card;
+use shared::GameConfig;
+
+/// A message from the engine
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// `NEWMATCH`: a warm bot is reused for another match, see `warm_start`
+    NewMatch,
+    /// `CONFIG`: the rules of the match, sent before its first hand if the
+    /// bot set `game_config`
+    Config(GameConfig),
+    /// `START SB|BB`: a hand starts, and this is your position in it
+    Start(PlayerPosition),
+    /// `PREFLOP`: your hole cards
+    Preflop([Card; 2]),
+    /// `STACK`: it is your turn. Chips pushed are counted from the start of
+    /// the hand, and stacks include the chips pushed.
+    Stack {
+        pushed: u32,
+        stack: u32,
+        opponent_pushed: u32,
+        opponent_stack: u32,
+    },
+    Flop([Card; 3]),
+    Turn(Card),
+    River(Card),
+    /// `END FOLD`: the player in this position folded
+    Fold(PlayerPosition),
+    /// `END SHOWDOWN`
+    Showdown(Showdown),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Showdown {
+    /// The pot is split. These are the opponent's hole cards.
+    Tie([Card; 2]),
+    /// The opponent's hole cards are only known if they were shown
+    Winner {
+        winner: PlayerPosition,
+        opponent_cards: Option<[Card; 2]>,
+    },
+}
+
+/// A line that isn't a message from the engine
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub line: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Could not parse engine message {:?}", self.line)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl FromStr for Event {
+    type Err = ParseError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let error = || ParseError {
+            line: line.to_string(),
+        };
+        let card = |word: &str| word.parse::<Card>().map_err(|_| error());
+        let number = |word: &str| word.parse::<u32>().map_err(|_| error());
+        let position = |word: &str| match word {
+            "SB" => Ok(PlayerPosition::SmallBlind),
+            "BB" => Ok(PlayerPosition::BigBlind),
+            _ => Err(error()),
+        };
+
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        Ok(match words[..] {
+            ["NEWMATCH"] => Event::NewMatch,
+            ["CONFIG", hands, stack, small_blind, big_blind, limit, clock_ms] => {
+                Event::Config(GameConfig {
+                    hands: hands.parse().map_err(|_| error())?,
+                    stack: number(stack)?,
+                    small_blind: number(small_blind)?,
+                    big_blind: number(big_blind)?,
+                    limit: number(limit)?,
+                    clock_ms: number(clock_ms)?,
+                })
+            }
+            ["START", seat] => Event::Start(position(seat)?),
+            ["PREFLOP", a, b] => Event::Preflop([card(a)?, card(b)?]),
+            ["STACK", pushed, stack, opponent_pushed, opponent_stack] => Event::Stack {
+                pushed: number(pushed)?,
+                stack: number(stack)?,
+                opponent_pushed: number(opponent_pushed)?,
+                opponent_stack: number(opponent_stack)?,
+            },
+            ["FLOP", a, b, c] => Event::Flop([card(a)?, card(b)?, card(c)?]),
+            ["TURN", a] => Event::Turn(card(a)?),
+            ["RIVER", a] => Event::River(card(a)?),
+            ["END", "FOLD", seat] => Event::Fold(position(seat)?),
+            ["END", "SHOWDOWN", "TIE", a, b] => Event::Showdown(Showdown::Tie([card(a)?, card(b)?])),
+            ["END", "SHOWDOWN", "WINNER", seat, "HIDDEN"] => Event::Showdown(Showdown::Winner {
+                winner: position(seat)?,
+                opponent_cards: None,
+            }),
+            ["END", "SHOWDOWN", "WINNER", seat, "SHOWN", a, b] => {
+                Event::Showdown(Showdown::Winner {
+                    winner: position(seat)?,
+                    opponent_cards: Some([card(a)?, card(b)?]),
+                })
+            }
+            _ => return Err(error()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use shared::poker::game::{EndReason, GameState, HoleCards};
+    use shared::poker::protocol::EngineCommunication;
+
+    use super::*;
+
+    fn cards<const N: usize>(cards: [&str; N]) -> [Card; N] {
+        cards.map(|card| card.parse().unwrap())
+    }
+
+    fn end(end_reason: EndReason, last_aggressor: PlayerPosition) -> EngineCommunication {
+        EngineCommunication::EndGame {
+            end_reason,
+            last_aggressor,
+            sb_hole_cards: HoleCards(cards(["As", "Ad"])),
+            bb_hole_cards: HoleCards(cards(["7c", "2d"])),
+        }
+    }
+
+    /// Parse what the engine sends to the player in `position`
+    fn parse(message: EngineCommunication, position: PlayerPosition) -> Event {
+        message.render_for_bot(position).parse().unwrap()
+    }
+
+    #[test]
+    fn parses_every_message_the_engine_sends() {
+        let sb = PlayerPosition::SmallBlind;
+        let bb = PlayerPosition::BigBlind;
+        assert_eq!(parse(EngineCommunication::NewMatch, sb), Event::NewMatch);
+        let config = shared::GameConfig {
+            hands: 200,
+            small_blind: 5,
+            big_blind: 10,
+            ..Default::default()
+        };
+        assert_eq!(
+            parse(EngineCommunication::Config(config), bb),
+            Event::Config(config)
+        );
+        assert_eq!(parse(EngineCommunication::StartGame, bb), Event::Start(bb));
+        let hole_cards = EngineCommunication::PreFlopCards(
+            HoleCards(cards(["As", "Ad"])),
+            HoleCards(cards(["7c", "2d"])),
+        );
+        assert_eq!(parse(hole_cards, bb), Event::Preflop(cards(["7c", "2d"])));
+        let state = GameState::new([500, 400], GameState::get_deck(), 100);
+        assert_eq!(
+            parse(EngineCommunication::get_betting_state(&state), bb),
+            Event::Stack {
+                pushed: 2,
+                stack: 400,
+                opponent_pushed: 1,
+                opponent_stack: 500,
+            }
+        );
+        assert_eq!(
+            parse(EngineCommunication::FlopCards(cards(["Tc", "Jd", "Qh"])), sb),
+            Event::Flop(cards(["Tc", "Jd", "Qh"]))
+        );
+        assert_eq!(
+            parse(EngineCommunication::TurnCard(cards(["9s"])[0]), sb),
+            Event::Turn(cards(["9s"])[0])
+        );
+        assert_eq!(
+            parse(EngineCommunication::RiverCard(cards(["2h"])[0]), sb),
+            Event::River(cards(["2h"])[0])
+        );
+        assert_eq!(parse(end(EndReason::LastToAct(sb), sb), bb), Event::Fold(bb));
+        assert_eq!(parse(end(EndReason::LastToAct(bb), bb), sb), Event::Fold(sb));
+        assert_eq!(
+            parse(end(EndReason::Tie, sb), sb),
+            Event::Showdown(Showdown::Tie(cards(["7c", "2d"])))
+        );
+        assert_eq!(
+            parse(end(EndReason::WonShowdown(sb), sb), sb),
+            Event::Showdown(Showdown::Winner {
+                winner: sb,
+                opponent_cards: None,
+            })
+        );
+        assert_eq!(
+            parse(end(EndReason::WonShowdown(sb), bb), sb),
+            Event::Showdown(Showdown::Winner {
+                winner: sb,
+                opponent_cards: Some(cards(["7c", "2d"])),
+            })
+        );
+        assert_eq!(
+            parse(end(EndReason::WonShowdown(sb), sb), bb),
+            Event::Showdown(Showdown::Winner {
+                winner: sb,
+                opponent_cards: Some(cards(["As", "Ad"])),
+            })
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_messages() {
+        for line in ["", "START", "START XX", "CONFIG 1 2 3", "CONFIG 1 2 3 4 5 x", "PREFLOP As", "STACK 1 2 3", "END SHOWDOWN"] {
+            assert!(line.parse::<Event>().is_err(), "{:?} should not parse", line);
+        }
+    }
+}
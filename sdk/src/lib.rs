@@ -0,0 +1,35 @@
+//! A starting point for bots written in Rust. The SDK parses the engine's
+//! messages into [Event]s, keeps track of the [State] of the match, and asks
+//! your [Bot] for an action whenever it is your turn:
+//!
+//! ```no_run
+//! use pokerbot_sdk::{Action, Bot, State};
+//!
+//! struct CheckOrCall;
+//!
+//! impl Bot for CheckOrCall {
+//!     fn act(&mut self, _state: &State) -> Action {
+//!         Action::Raise(0)
+//!     }
+//! }
+//!
+//! fn main() -> std::io::Result<()> {
+//!     pokerbot_sdk::run(CheckOrCall)
+//! }
+//! ```
+//!
+//! [SelfPlay] plays two bots against each other without the engine, and the
+//! `conformance` binary checks that any bot speaks the protocol correctly.
+
+mod bot;
+pub mod conformance;
+mod event;
+mod self_play;
+mod state;
+
+pub use bot::{play, run, run_socket, Bot};
+pub use event::{Event, ParseError, Showdown};
+pub use self_play::{SelfPlay, SelfPlayError, SelfPlayResult};
+pub use shared::poker::game::{Action, PlayerPosition, Round};
+pub use shared::poker::hands::{Card, Hand, Suite};
+pub use state::State;
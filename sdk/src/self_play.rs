@@ -0,0 +1,212 @@
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+use rand::{rngs::StdRng, SeedableRng};
+use shared::poker::game::{Action, GameState, PlayerPosition, Round};
+use shared::poker::protocol::{parse_action, render_action, EngineCommunication};
+
+use crate::bot::Bot;
+use crate::event::Event;
+use crate::state::State;
+
+/// Plays two bots against each other offline, the way the engine does:
+/// every message is rendered and parsed again, the blinds alternate, and the
+/// match ends early once a bot has no chips left.
+#[derive(Debug, Clone)]
+pub struct SelfPlay {
+    pub hands: usize,
+    pub starting_stack: u32,
+    /// The most a player can push in one hand
+    pub limit: u32,
+    /// Seed for shuffling, so that matches can be replayed
+    pub seed: u64,
+}
+
+impl Default for SelfPlay {
+    fn default() -> Self {
+        SelfPlay {
+            hands: 1000,
+            starting_stack: 500,
+            limit: 100,
+            seed: 0,
+        }
+    }
+}
+
+/// How an offline match went, from the first bot's point of view
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelfPlayResult {
+    pub hands: usize,
+    /// The chips the first bot won, which the second bot lost
+    pub score: i64,
+    /// Time spent in [Bot::act] by the first and the second bot
+    pub thinking: [Duration; 2],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelfPlayError {
+    /// A bot's action was rejected by the engine. `bot` is 0 for the first bot.
+    InvalidAction { bot: usize, action: Action },
+}
+
+impl Display for SelfPlayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SelfPlayError::InvalidAction { bot, action } => {
+                write!(f, "Bot {} made the invalid action {:?}", bot, action)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SelfPlayError {}
+
+/// A bot and what it has been told
+struct Seat<'a> {
+    bot: &'a mut dyn Bot,
+    state: State,
+    thinking: Duration,
+}
+
+impl Seat<'_> {
+    fn send(&mut self, message: &EngineCommunication, position: PlayerPosition) {
+        let event: Event = message
+            .render_for_bot(position)
+            .parse()
+            .expect("The SDK can't parse a message the engine sends");
+        self.state.apply(&event);
+        self.bot.observe(&event, &self.state);
+    }
+}
+
+impl SelfPlay {
+    pub fn play<'a>(
+        &self,
+        first: &'a mut dyn Bot,
+        second: &'a mut dyn Bot,
+    ) -> Result<SelfPlayResult, SelfPlayError> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut seats = [first, second].map(|bot| Seat {
+            bot,
+            state: State::default(),
+            thinking: Duration::ZERO,
+        });
+        let mut stacks = [self.starting_stack; 2];
+        let mut hands = 0;
+        while hands < self.hands && stacks.iter().all(|stack| *stack > 0) {
+            // The first bot is the small blind in even hands
+            let sb = hands % 2;
+            let seat_of = |position: PlayerPosition| match position {
+                PlayerPosition::SmallBlind => sb,
+                PlayerPosition::BigBlind => 1 - sb,
+            };
+            let position_of = |seat: usize| {
+                if seat == sb {
+                    PlayerPosition::SmallBlind
+                } else {
+                    PlayerPosition::BigBlind
+                }
+            };
+            let send_both = |seats: &mut [Seat; 2], message: EngineCommunication| {
+                for (i, seat) in seats.iter_mut().enumerate() {
+                    seat.send(&message, position_of(i));
+                }
+            };
+
+            let mut state = GameState::new(
+                [stacks[sb], stacks[1 - sb]],
+                GameState::get_shuffled_deck(&mut rng),
+                self.limit,
+            );
+            send_both(&mut seats, EngineCommunication::StartGame);
+            let mut round = None;
+            while !state.round_over() {
+                if round != Some(state.round) {
+                    round = Some(state.round);
+                    let cards = &state.community_cards;
+                    send_both(
+                        &mut seats,
+                        match state.round {
+                            Round::PreFlop => EngineCommunication::PreFlopCards(
+                                state.player_states[0].hole_cards.clone(),
+                                state.player_states[1].hole_cards.clone(),
+                            ),
+                            Round::Flop => {
+                                EngineCommunication::FlopCards([cards[0], cards[1], cards[2]])
+                            }
+                            Round::Turn => EngineCommunication::TurnCard(cards[3]),
+                            Round::River => EngineCommunication::RiverCard(cards[4]),
+                            Round::End => unreachable!("The hand is over"),
+                        },
+                    );
+                }
+                let position = state.whose_turn().expect("Someone has to act");
+                let seat = &mut seats[seat_of(position)];
+                seat.send(&EngineCommunication::get_betting_state(&state), position);
+                let start = Instant::now();
+                let action = seat.bot.act(&seat.state);
+                seat.thinking += start.elapsed();
+                let invalid = || SelfPlayError::InvalidAction {
+                    bot: seat_of(position),
+                    action: action.clone(),
+                };
+                let parsed = parse_action(render_action(&action)).map_err(|_| invalid())?;
+                state = state.post_action(parsed).map_err(|_| invalid())?;
+            }
+            send_both(&mut seats, EngineCommunication::get_round_end(&state));
+            stacks[sb] = state.player_states[0].stack;
+            stacks[1 - sb] = state.player_states[1].stack;
+            hands += 1;
+        }
+        Ok(SelfPlayResult {
+            hands,
+            score: stacks[0] as i64 - self.starting_stack as i64,
+            thinking: [seats[0].thinking, seats[1].thinking],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct CheckOrCall;
+
+    impl Bot for CheckOrCall {
+        fn act(&mut self, _state: &State) -> Action {
+            Action::Raise(0)
+        }
+    }
+
+    /// Raises every time, and checks that the state it is given adds up
+    struct Aggressive;
+
+    impl Bot for Aggressive {
+        fn act(&mut self, state: &State) -> Action {
+            assert!(state.hole_cards.is_some());
+            assert_eq!(
+                state.board.len(),
+                match state.round {
+                    Round::PreFlop => 0,
+                    Round::Flop => 3,
+                    Round::Turn => 4,
+                    Round::River => 5,
+                    Round::End => panic!("Asked to act after the hand ended"),
+                }
+            );
+            Action::Raise(state.pot())
+        }
+    }
+
+    #[test]
+    fn plays_a_match() {
+        let self_play = SelfPlay {
+            hands: 200,
+            ..Default::default()
+        };
+        let result = self_play.play(&mut Aggressive, &mut CheckOrCall).unwrap();
+        assert!(result.hands > 0 && result.hands <= 200);
+        let replayed = self_play.play(&mut Aggressive, &mut CheckOrCall).unwrap();
+        assert_eq!(result.score, replayed.score);
+    }
+}
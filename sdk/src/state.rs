@@ -0,0 +1,175 @@
+use shared::poker::game::{CommunityCards, HoleCards, PlayerPosition, Round};
+use shared::poker::hands::{hand_eval, Hand};
+use shared::GameConfig;
+
+use crate::event::{Event, Showdown};
+
+/// What a bot knows about the match, updated from the engine's events
+#[derive(Debug, Clone)]
+pub struct State {
+    /// Your position in the current hand
+    pub position: PlayerPosition,
+    pub round: Round,
+    pub hole_cards: Option<HoleCards>,
+    pub board: CommunityCards,
+    /// Chips pushed since the start of the hand, as of your last turn
+    pub pushed: u32,
+    /// Your stack, including the chips pushed
+    pub stack: u32,
+    pub opponent_pushed: u32,
+    pub opponent_stack: u32,
+    /// Hands started in this match
+    pub hands: u32,
+    /// The opponent's hole cards, once they are shown at a showdown
+    pub opponent_cards: Option<HoleCards>,
+    /// The rules of the match. These are the engine's defaults unless the
+    /// bot set `game_config` and was sent `CONFIG`.
+    pub config: GameConfig,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            position: PlayerPosition::SmallBlind,
+            round: Round::End,
+            hole_cards: None,
+            board: CommunityCards(vec![]),
+            pushed: 0,
+            stack: 0,
+            opponent_pushed: 0,
+            opponent_stack: 0,
+            hands: 0,
+            opponent_cards: None,
+            config: GameConfig::default(),
+        }
+    }
+}
+
+impl State {
+    pub fn apply(&mut self, event: &Event) {
+        match event {
+            Event::NewMatch => *self = State::default(),
+            Event::Config(config) => self.config = *config,
+            Event::Start(position) => {
+                self.position = *position;
+                self.round = Round::PreFlop;
+                self.hole_cards = None;
+                self.board = CommunityCards(vec![]);
+                self.opponent_cards = None;
+                // The blinds, until the first STACK message of the hand
+                (self.pushed, self.opponent_pushed) = match position {
+                    PlayerPosition::SmallBlind => {
+                        (self.config.small_blind, self.config.big_blind)
+                    }
+                    PlayerPosition::BigBlind => (self.config.big_blind, self.config.small_blind),
+                };
+                self.hands += 1;
+            }
+            Event::Preflop(cards) => self.hole_cards = Some(HoleCards(*cards)),
+            Event::Stack {
+                pushed,
+                stack,
+                opponent_pushed,
+                opponent_stack,
+            } => {
+                self.pushed = *pushed;
+                self.stack = *stack;
+                self.opponent_pushed = *opponent_pushed;
+                self.opponent_stack = *opponent_stack;
+            }
+            Event::Flop(cards) => {
+                self.round = Round::Flop;
+                self.board.extend(cards);
+            }
+            Event::Turn(card) => {
+                self.round = Round::Turn;
+                self.board.push(*card);
+            }
+            Event::River(card) => {
+                self.round = Round::River;
+                self.board.push(*card);
+            }
+            Event::Fold(_) => self.round = Round::End,
+            Event::Showdown(showdown) => {
+                self.round = Round::End;
+                self.opponent_cards = match showdown {
+                    Showdown::Tie(cards) => Some(HoleCards(*cards)),
+                    Showdown::Winner { opponent_cards, .. } => opponent_cards.map(HoleCards),
+                };
+            }
+        }
+    }
+
+    /// The chips you have to add to call
+    pub fn to_call(&self) -> u32 {
+        self.opponent_pushed.saturating_sub(self.pushed)
+    }
+
+    pub fn pot(&self) -> u32 {
+        self.pushed + self.opponent_pushed
+    }
+
+    /// Your best five cards, once the flop is out
+    pub fn best_hand(&self) -> Option<Hand> {
+        let hole_cards = self.hole_cards.as_ref()?;
+        if self.board.len() < 3 {
+            return None;
+        }
+        let mut cards = self.board.0.clone();
+        cards.extend(hole_cards.0);
+        Some(hand_eval::best5(&cards))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(state: &mut State, lines: &[&str]) {
+        for line in lines {
+            state.apply(&line.parse().unwrap());
+        }
+    }
+
+    #[test]
+    fn tracks_a_hand() {
+        let mut state = State::default();
+        apply(
+            &mut state,
+            &["START BB", "PREFLOP Ah Kh", "STACK 2 500 1 500"],
+        );
+        assert_eq!(state.position, PlayerPosition::BigBlind);
+        assert_eq!(state.to_call(), 0);
+        assert!(state.best_hand().is_none());
+        apply(&mut state, &["FLOP Qh Jh Th", "STACK 10 500 30 500"]);
+        assert_eq!(state.round, Round::Flop);
+        assert_eq!(state.to_call(), 20);
+        assert_eq!(state.pot(), 40);
+        // A royal flush
+        let hand = state.best_hand().unwrap();
+        assert!(hand.cards.iter().all(|card| card.suite == shared::poker::hands::Suite::Hearts));
+        apply(&mut state, &["END SHOWDOWN TIE 2c 3c"]);
+        assert_eq!(state.round, Round::End);
+        assert!(state.opponent_cards.is_some());
+        apply(&mut state, &["START SB"]);
+        assert_eq!(state.hands, 2);
+        assert!(state.hole_cards.is_none() && state.board.is_empty());
+        apply(&mut state, &["NEWMATCH"]);
+        assert_eq!(state.hands, 0);
+    }
+
+    #[test]
+    fn posts_the_configured_blinds() {
+        let mut state = State::default();
+        apply(&mut state, &["START SB"]);
+        assert_eq!((state.pushed, state.opponent_pushed), (1, 2));
+        apply(&mut state, &["CONFIG 100 1000 5 10 200 10000", "START SB"]);
+        assert_eq!((state.pushed, state.opponent_pushed), (5, 10));
+        apply(&mut state, &["END FOLD SB", "START BB"]);
+        assert_eq!((state.pushed, state.opponent_pushed), (10, 5));
+        assert_eq!(state.config.stack, 1000);
+        // A new match starts with the defaults until it is configured
+        apply(&mut state, &["NEWMATCH", "START SB"]);
+        assert_eq!((state.pushed, state.opponent_pushed), (1, 2));
+    }
+}
@@ -0,0 +1,12 @@
+CREATE TABLE bot_status_history (
+    id SERIAL PRIMARY KEY NOT NULL,
+    bot INTEGER NOT NULL REFERENCES bots(id) ON DELETE CASCADE,
+    status INTEGER NOT NULL,
+    error TEXT,
+    created BIGINT NOT NULL DEFAULT extract(epoch from CURRENT_TIMESTAMP)
+);
+CREATE INDEX bot_status_history_bot ON bot_status_history (bot, created);
+
+-- Start every bot's history from its current status
+INSERT INTO bot_status_history (bot, status, created)
+SELECT id, build_status, created FROM bots;
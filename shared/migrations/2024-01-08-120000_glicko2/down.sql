@@ -0,0 +1,2 @@
+ALTER TABLE teams DROP COLUMN rating_volatility;
+ALTER TABLE teams DROP COLUMN rating_deviation;
@@ -0,0 +1,15 @@
+ALTER TABLE teams ADD COLUMN rating_deviation REAL NOT NULL DEFAULT 350;
+ALTER TABLE teams ADD COLUMN rating_volatility REAL NOT NULL DEFAULT 0.06;
+
+-- Teams that have played many rated games already have a settled rating
+UPDATE teams SET rating_deviation = GREATEST(50, 350 / SQRT(1 + played.games / 10.0))
+FROM (
+    SELECT bots.team, COUNT(*) AS games
+    FROM game_results
+    JOIN games ON games.id = game_results.id
+    JOIN bots ON bots.id = games.defender OR bots.id = games.challenger
+    WHERE games.rated
+        AND game_results.error_type IS DISTINCT FROM '"InternalError"'
+    GROUP BY bots.team
+) played
+WHERE played.team = teams.id;
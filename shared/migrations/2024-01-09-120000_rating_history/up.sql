@@ -0,0 +1,29 @@
+-- A team's rating after each game. Rows outlive deleted bots and games so
+-- that the team's history stays complete.
+CREATE TABLE rating_history (
+    id SERIAL PRIMARY KEY NOT NULL,
+    team INTEGER NOT NULL REFERENCES teams(id) ON DELETE CASCADE,
+    bot INTEGER REFERENCES bots(id) ON DELETE SET NULL,
+    game TEXT REFERENCES games(id) ON DELETE SET NULL,
+    rating REAL NOT NULL,
+    rating_deviation REAL,
+    created BIGINT NOT NULL DEFAULT extract(epoch from CURRENT_TIMESTAMP)
+);
+CREATE INDEX rating_history_team ON rating_history (team, created);
+CREATE UNIQUE INDEX rating_history_game_bot ON rating_history (game, bot);
+
+-- Deviations weren't recorded before. Only rated games changed ratings.
+INSERT INTO rating_history (team, bot, game, rating, created)
+SELECT bots.team, bots.id, games.id, game_results.defender_rating, game_results.updated_at
+FROM game_results
+JOIN games ON games.id = game_results.id
+JOIN bots ON bots.id = games.defender
+WHERE games.rated
+    AND game_results.error_type IS DISTINCT FROM '"InternalError"'
+UNION ALL
+SELECT bots.team, bots.id, games.id, game_results.challenger_rating, game_results.updated_at
+FROM game_results
+JOIN games ON games.id = game_results.id
+JOIN bots ON bots.id = games.challenger
+WHERE games.rated
+    AND game_results.error_type IS DISTINCT FROM '"InternalError"';
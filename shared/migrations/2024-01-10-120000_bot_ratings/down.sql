@@ -0,0 +1,4 @@
+ALTER TABLE bots DROP COLUMN games_played;
+ALTER TABLE bots DROP COLUMN rating_volatility;
+ALTER TABLE bots DROP COLUMN rating_deviation;
+ALTER TABLE bots DROP COLUMN rating;
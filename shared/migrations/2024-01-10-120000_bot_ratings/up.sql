@@ -0,0 +1,31 @@
+ALTER TABLE bots ADD COLUMN rating REAL NOT NULL DEFAULT 1000;
+ALTER TABLE bots ADD COLUMN rating_deviation REAL NOT NULL DEFAULT 350;
+ALTER TABLE bots ADD COLUMN rating_volatility REAL NOT NULL DEFAULT 0.06;
+ALTER TABLE bots ADD COLUMN games_played INTEGER NOT NULL DEFAULT 0;
+
+-- Bots start from their team's rating when they were uploaded, if the team had
+-- played a game by then
+UPDATE bots SET rating = seed.rating
+FROM (
+    SELECT DISTINCT ON (bots.id) bots.id, rating_history.rating
+    FROM bots
+    JOIN rating_history ON rating_history.team = bots.team
+        AND rating_history.created <= bots.created
+    ORDER BY bots.id, rating_history.created DESC, rating_history.id DESC
+) seed
+WHERE seed.id = bots.id;
+
+-- Only the rated games that were actually played count
+UPDATE bots SET
+    games_played = played.games,
+    rating_deviation = GREATEST(50, 350 / SQRT(1 + played.games / 10.0))
+FROM (
+    SELECT bots.id, COUNT(*) AS games
+    FROM game_results
+    JOIN games ON games.id = game_results.id
+    JOIN bots ON bots.id = games.defender OR bots.id = games.challenger
+    WHERE games.rated
+        AND game_results.error_type IS DISTINCT FROM '"InternalError"'
+    GROUP BY bots.id
+) played
+WHERE played.id = bots.id;
@@ -0,0 +1,2 @@
+-- The defender's luck-adjusted score, see results::aivat
+ALTER TABLE game_results ADD COLUMN adjusted_score REAL;
@@ -0,0 +1,3 @@
+DROP TABLE tournament_pairings;
+DROP TABLE tournament_entrants;
+DROP TABLE tournaments;
@@ -0,0 +1,36 @@
+CREATE TABLE tournaments (
+    id SERIAL PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL,
+    -- see shared::TournamentFormat
+    format INTEGER NOT NULL,
+    rounds INTEGER NOT NULL,
+    -- 0 until the tournament starts
+    current_round INTEGER NOT NULL DEFAULT 0,
+    created BIGINT NOT NULL DEFAULT extract(epoch from CURRENT_TIMESTAMP),
+    finished BIGINT
+);
+
+-- Entrants play with the bot that was active when the tournament was created,
+-- and their standings are updated at the end of every round
+CREATE TABLE tournament_entrants (
+    tournament INTEGER NOT NULL REFERENCES tournaments(id) ON DELETE CASCADE,
+    team INTEGER NOT NULL REFERENCES teams(id) ON DELETE CASCADE,
+    bot INTEGER NOT NULL REFERENCES bots(id),
+    seed INTEGER NOT NULL,
+    points REAL NOT NULL DEFAULT 0,
+    buchholz REAL NOT NULL DEFAULT 0,
+    sonneborn_berger REAL NOT NULL DEFAULT 0,
+    chips BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (tournament, team)
+);
+
+-- A challenger of NULL is a bye
+CREATE TABLE tournament_pairings (
+    id SERIAL PRIMARY KEY NOT NULL,
+    tournament INTEGER NOT NULL REFERENCES tournaments(id) ON DELETE CASCADE,
+    round INTEGER NOT NULL,
+    defender INTEGER NOT NULL REFERENCES teams(id) ON DELETE CASCADE,
+    challenger INTEGER REFERENCES teams(id) ON DELETE CASCADE,
+    game TEXT REFERENCES games(id) ON DELETE SET NULL
+);
+CREATE INDEX tournament_pairings_round ON tournament_pairings (tournament, round);
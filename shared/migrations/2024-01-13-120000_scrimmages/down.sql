@@ -0,0 +1,2 @@
+DROP TABLE scrimmage_games;
+DROP TABLE scrimmages;
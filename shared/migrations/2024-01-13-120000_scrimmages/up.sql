@@ -0,0 +1,15 @@
+-- A batch of unrated games between two bots of the same team
+CREATE TABLE scrimmages (
+    id SERIAL PRIMARY KEY NOT NULL,
+    team INTEGER NOT NULL REFERENCES teams(id) ON DELETE CASCADE,
+    bot_a INTEGER NOT NULL REFERENCES bots(id),
+    bot_b INTEGER NOT NULL REFERENCES bots(id),
+    created BIGINT NOT NULL DEFAULT extract(epoch from CURRENT_TIMESTAMP)
+);
+CREATE INDEX scrimmages_team ON scrimmages (team, created);
+
+CREATE TABLE scrimmage_games (
+    scrimmage INTEGER NOT NULL REFERENCES scrimmages(id) ON DELETE CASCADE,
+    game TEXT NOT NULL REFERENCES games(id) ON DELETE CASCADE,
+    PRIMARY KEY (scrimmage, game)
+);
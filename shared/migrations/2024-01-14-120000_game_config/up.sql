@@ -0,0 +1,5 @@
+-- The rules each game is played with, as JSON, see shared::GameConfig.
+-- Older games were played with the defaults.
+ALTER TABLE games ADD COLUMN config TEXT NOT NULL
+    DEFAULT '{"hands":1000,"stack":500,"small_blind":1,"big_blind":2,"limit":50,"clock_ms":10000}';
+ALTER TABLE games ALTER COLUMN config DROP DEFAULT;
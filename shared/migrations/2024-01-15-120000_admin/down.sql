@@ -0,0 +1,2 @@
+ALTER TABLE game_results DROP COLUMN voided;
+DROP TABLE audit_log;
@@ -0,0 +1,14 @@
+-- Everything done through the admin API
+CREATE TABLE audit_log (
+    id SERIAL PRIMARY KEY NOT NULL,
+    admin UUID NOT NULL REFERENCES users(id),
+    action TEXT NOT NULL,
+    target TEXT NOT NULL,
+    -- JSON with whatever else the action needs to be understood later
+    details TEXT,
+    created BIGINT NOT NULL DEFAULT extract(epoch from CURRENT_TIMESTAMP)
+);
+CREATE INDEX audit_log_created ON audit_log (created);
+
+-- Voided games keep their result, but their rating changes have been reversed
+ALTER TABLE game_results ADD COLUMN voided BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,4 @@
+ALTER TABLE game_results
+    DROP COLUMN voided_by,
+    DROP COLUMN void_reason,
+    DROP COLUMN voided_at;
@@ -0,0 +1,5 @@
+-- Who voided a game, when and why
+ALTER TABLE game_results
+    ADD COLUMN voided_by UUID REFERENCES users(id),
+    ADD COLUMN void_reason TEXT,
+    ADD COLUMN voided_at BIGINT;
@@ -0,0 +1,3 @@
+DROP TABLE shadow_game_ratings;
+DROP TABLE shadow_bot_ratings;
+DROP TABLE shadow_team_ratings;
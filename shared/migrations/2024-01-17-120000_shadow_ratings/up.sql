@@ -0,0 +1,25 @@
+-- Ratings recomputed from the game history by the results crate's rerate
+-- binary, kept apart from the live ratings until they are promoted
+CREATE TABLE shadow_team_ratings (
+    team INTEGER PRIMARY KEY NOT NULL REFERENCES teams(id) ON DELETE CASCADE,
+    rating REAL NOT NULL,
+    rating_deviation REAL NOT NULL,
+    rating_volatility REAL NOT NULL
+);
+CREATE TABLE shadow_bot_ratings (
+    bot INTEGER PRIMARY KEY NOT NULL REFERENCES bots(id) ON DELETE CASCADE,
+    rating REAL NOT NULL,
+    rating_deviation REAL NOT NULL,
+    rating_volatility REAL NOT NULL,
+    games_played INTEGER NOT NULL
+);
+-- The recomputed team ratings after each game
+CREATE TABLE shadow_game_ratings (
+    game TEXT PRIMARY KEY NOT NULL REFERENCES games(id) ON DELETE CASCADE,
+    defender_rating_change REAL NOT NULL,
+    challenger_rating_change REAL NOT NULL,
+    defender_rating REAL NOT NULL,
+    challenger_rating REAL NOT NULL,
+    defender_rating_deviation REAL NOT NULL,
+    challenger_rating_deviation REAL NOT NULL
+);
@@ -0,0 +1,22 @@
+-- How a bot played the hands of a game, as counts so they can be summed over
+-- games. See `results::hand_stats` for what each one counts.
+CREATE TABLE bot_stats (
+    game TEXT NOT NULL REFERENCES games(id) ON DELETE CASCADE,
+    bot INTEGER NOT NULL REFERENCES bots(id) ON DELETE CASCADE,
+    opponent INTEGER NOT NULL REFERENCES bots(id) ON DELETE CASCADE,
+    hands INTEGER NOT NULL,
+    vpip_hands INTEGER NOT NULL,
+    pfr_hands INTEGER NOT NULL,
+    three_bet_chances INTEGER NOT NULL,
+    three_bets INTEGER NOT NULL,
+    raises_faced INTEGER NOT NULL,
+    folds_to_raise INTEGER NOT NULL,
+    aggressive_actions INTEGER NOT NULL,
+    calls INTEGER NOT NULL,
+    saw_flop INTEGER NOT NULL,
+    showdowns INTEGER NOT NULL,
+    showdowns_won INTEGER NOT NULL,
+    pot_big_blinds DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (game, bot)
+);
+CREATE INDEX bot_stats_bot ON bot_stats (bot, opponent);
@@ -0,0 +1,6 @@
+DELETE FROM bots WHERE house IS NOT NULL;
+UPDATE users SET team = NULL WHERE id = '00000000-0000-0000-0000-000000000001';
+DELETE FROM teams WHERE owner = '00000000-0000-0000-0000-000000000001';
+DELETE FROM users WHERE id = '00000000-0000-0000-0000-000000000001';
+DELETE FROM auth WHERE id = '00000000-0000-0000-0000-000000000001';
+ALTER TABLE bots DROP COLUMN house;
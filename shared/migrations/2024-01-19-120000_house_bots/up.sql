@@ -0,0 +1,29 @@
+-- The house bot a bot stands for, if it is played by the gameplay worker
+ALTER TABLE bots ADD COLUMN house TEXT;
+
+-- House bots belong to a team of their own, owned by an account that can't log in
+WITH house_auth AS (
+    INSERT INTO auth (id, email, email_confirmed)
+    VALUES ('00000000-0000-0000-0000-000000000001', 'house@pokerbots.invalid', TRUE)
+    RETURNING id
+), house_user AS (
+    INSERT INTO users (id, display_name)
+    SELECT id, 'House' FROM house_auth
+    RETURNING id
+), house_team AS (
+    INSERT INTO teams (name, owner)
+    SELECT 'House', id FROM house_user
+    RETURNING id, owner
+)
+INSERT INTO bots (team, name, description, created, uploaded_by, build_status, house)
+SELECT house_team.id, bot.name, bot.description, extract(epoch from CURRENT_TIMESTAMP),
+    house_team.owner, 4, bot.house
+FROM house_team, (VALUES
+    ('random', 'Folds, calls or raises a random amount', '"random"'),
+    ('calling_station', 'Always calls', '"calling_station"'),
+    ('tight_aggressive', 'Plays a preflop chart, then bets its made hands', '"tight_aggressive"'),
+    ('equity', 'Compares its equity against a random hand to the pot odds', '"equity"')
+) AS bot(name, description, house);
+
+UPDATE users SET team = (SELECT id FROM teams WHERE owner = users.id)
+WHERE id = '00000000-0000-0000-0000-000000000001';
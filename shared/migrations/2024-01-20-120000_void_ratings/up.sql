@@ -0,0 +1,14 @@
+-- What a game changed besides the team ratings, so that voiding it can undo
+-- everything. Deviations and volatilities are the ones before the game, and
+-- are unknown for games finished before this migration.
+ALTER TABLE game_results
+    ADD COLUMN defender_deviation_before REAL,
+    ADD COLUMN defender_volatility_before REAL,
+    ADD COLUMN challenger_deviation_before REAL,
+    ADD COLUMN challenger_volatility_before REAL,
+    ADD COLUMN defender_bot_rating_change REAL,
+    ADD COLUMN challenger_bot_rating_change REAL,
+    ADD COLUMN defender_bot_deviation_before REAL,
+    ADD COLUMN defender_bot_volatility_before REAL,
+    ADD COLUMN challenger_bot_deviation_before REAL,
+    ADD COLUMN challenger_bot_volatility_before REAL;
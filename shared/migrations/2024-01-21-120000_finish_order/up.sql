@@ -0,0 +1,18 @@
+-- The order games finished in, for replaying ratings. Timestamps can tie, and
+-- ids are random, so neither orders games that finished in the same second.
+CREATE SEQUENCE game_results_finish_order_seq;
+ALTER TABLE game_results ADD COLUMN finish_order BIGINT;
+UPDATE game_results
+SET finish_order = ordered.finish_order
+FROM (
+    SELECT id, ROW_NUMBER() OVER (ORDER BY updated_at, id) AS finish_order
+    FROM game_results
+) ordered
+WHERE game_results.id = ordered.id;
+SELECT setval('game_results_finish_order_seq', COALESCE(MAX(finish_order), 0) + 1, false)
+FROM game_results;
+ALTER TABLE game_results
+    ALTER COLUMN finish_order SET DEFAULT nextval('game_results_finish_order_seq'),
+    ALTER COLUMN finish_order SET NOT NULL;
+ALTER SEQUENCE game_results_finish_order_seq OWNED BY game_results.finish_order;
+CREATE INDEX game_results_finish_order ON game_results (finish_order);
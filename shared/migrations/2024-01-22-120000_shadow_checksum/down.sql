@@ -0,0 +1,12 @@
+DROP TABLE shadow_ratings_checksum;
+ALTER TABLE shadow_game_ratings
+    DROP COLUMN defender_deviation_before,
+    DROP COLUMN defender_volatility_before,
+    DROP COLUMN challenger_deviation_before,
+    DROP COLUMN challenger_volatility_before,
+    DROP COLUMN defender_bot_rating_change,
+    DROP COLUMN challenger_bot_rating_change,
+    DROP COLUMN defender_bot_deviation_before,
+    DROP COLUMN defender_bot_volatility_before,
+    DROP COLUMN challenger_bot_deviation_before,
+    DROP COLUMN challenger_bot_volatility_before;
@@ -0,0 +1,21 @@
+-- Shadow ratings now carry everything a game's result records about ratings,
+-- so the ones computed before this have to be computed again
+DELETE FROM shadow_game_ratings;
+DELETE FROM shadow_bot_ratings;
+DELETE FROM shadow_team_ratings;
+ALTER TABLE shadow_game_ratings
+    ADD COLUMN defender_deviation_before REAL NOT NULL,
+    ADD COLUMN defender_volatility_before REAL NOT NULL,
+    ADD COLUMN challenger_deviation_before REAL NOT NULL,
+    ADD COLUMN challenger_volatility_before REAL NOT NULL,
+    ADD COLUMN defender_bot_rating_change REAL NOT NULL,
+    ADD COLUMN challenger_bot_rating_change REAL NOT NULL,
+    ADD COLUMN defender_bot_deviation_before REAL NOT NULL,
+    ADD COLUMN defender_bot_volatility_before REAL NOT NULL,
+    ADD COLUMN challenger_bot_deviation_before REAL NOT NULL,
+    ADD COLUMN challenger_bot_volatility_before REAL NOT NULL;
+-- A checksum of the results the shadow ratings were computed from, so they
+-- aren't promoted once any of those results changed
+CREATE TABLE shadow_ratings_checksum (
+    checksum TEXT PRIMARY KEY NOT NULL
+);
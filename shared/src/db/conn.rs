@@ -2,8 +2,9 @@ use lazy_static::lazy_static;
 use std::{env, fs};
 
 use diesel::{
+    connection::{AnsiTransactionManager, TransactionManager},
     r2d2::{ConnectionManager, Pool},
-    PgConnection,
+    PgConnection, QueryResult,
 };
 
 lazy_static! {
@@ -25,3 +26,18 @@ lazy_static! {
             .unwrap()
     };
 }
+
+/// Start a transaction that, unlike `Connection::transaction`, can be held
+/// across awaits. It must be ended with [end_transaction].
+pub fn begin_transaction(conn: &mut PgConnection) -> QueryResult<()> {
+    AnsiTransactionManager::begin_transaction(conn)
+}
+
+/// Commit the transaction started by [begin_transaction] if `result` is Ok,
+/// or roll it back
+pub fn end_transaction<T, E>(conn: &mut PgConnection, result: &Result<T, E>) -> QueryResult<()> {
+    match result {
+        Ok(_) => AnsiTransactionManager::commit_transaction(conn),
+        Err(_) => AnsiTransactionManager::rollback_transaction(conn),
+    }
+}
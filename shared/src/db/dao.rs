@@ -2,5 +2,9 @@ use crate::db::{models::*, schema, schema_aliases};
 use diesel::PgConnection;
 use diesel::{dsl::*, prelude::*};
 
+pub mod admin;
 pub mod bots;
 pub mod games;
+pub mod ratings;
+pub mod scrimmages;
+pub mod tournaments;
@@ -0,0 +1,251 @@
+use super::*;
+use uuid::Uuid;
+
+pub trait AdminDao {
+    fn record_audit(&mut self, entry: NewAuditLogEntry) -> Result<(), diesel::result::Error>;
+    /// The audit log, newest first
+    fn get_audit_log(
+        &mut self,
+        page: i64,
+        page_size: i64,
+    ) -> Result<Vec<AuditLogEntry>, diesel::result::Error>;
+    /// Users whose display name or email contains `search`, with their email
+    /// and whether they are an admin
+    fn search_users(
+        &mut self,
+        search: Option<&str>,
+        page: i64,
+        page_size: i64,
+    ) -> Result<Vec<(User, String, bool)>, diesel::result::Error>;
+    /// Reverse the rating changes of a finished game to its teams and bots and
+    /// mark it voided by `admin`. Deviations and volatilities go back to what
+    /// they were before the game, if they were recorded and the team or bot
+    /// hasn't played a rated game since. Fails with `NotFound` if the game has
+    /// no result or is already voided.
+    fn void_game(
+        &mut self,
+        id: &str,
+        admin: Uuid,
+        reason: &str,
+    ) -> Result<GameResult, diesel::result::Error>;
+    /// Set a team's rating, recording it in the team's rating history
+    fn set_team_rating(&mut self, team: i32, rating: f32) -> Result<Team, diesel::result::Error>;
+}
+
+impl AdminDao for PgConnection {
+    fn record_audit(&mut self, entry: NewAuditLogEntry) -> Result<(), diesel::result::Error> {
+        diesel::insert_into(schema::audit_log::table)
+            .values(&entry)
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn get_audit_log(
+        &mut self,
+        page: i64,
+        page_size: i64,
+    ) -> Result<Vec<AuditLogEntry>, diesel::result::Error> {
+        use schema::audit_log::dsl;
+        dsl::audit_log
+            .order_by((dsl::created.desc(), dsl::id.desc()))
+            .offset(page * page_size)
+            .limit(page_size)
+            .load::<AuditLogEntry>(self)
+    }
+
+    fn search_users(
+        &mut self,
+        search: Option<&str>,
+        page: i64,
+        page_size: i64,
+    ) -> Result<Vec<(User, String, bool)>, diesel::result::Error> {
+        use schema::{auth, users};
+        let mut query = users::table.inner_join(auth::table).into_boxed();
+        if let Some(search) = search {
+            let pattern = format!("%{}%", search);
+            query = query.filter(
+                users::display_name
+                    .ilike(pattern.clone())
+                    .or(auth::email.ilike(pattern)),
+            );
+        }
+        query
+            .order_by(users::display_name.asc())
+            .offset(page * page_size)
+            .limit(page_size)
+            .select((User::as_select(), auth::email, auth::is_admin))
+            .load(self)
+    }
+
+    fn void_game(
+        &mut self,
+        id: &str,
+        admin: Uuid,
+        reason: &str,
+    ) -> Result<GameResult, diesel::result::Error> {
+        use schema::{bots, game_results, games};
+        self.transaction(|conn| {
+            let result: GameResult = diesel::update(
+                game_results::table
+                    .find(id)
+                    .filter(game_results::voided.eq(false)),
+            )
+            .set((
+                game_results::voided.eq(true),
+                game_results::voided_by.eq(Some(admin)),
+                game_results::void_reason.eq(Some(reason)),
+                game_results::voided_at.eq(Some(chrono::Utc::now().timestamp())),
+            ))
+            .get_result(conn)?;
+            let (defender, challenger, rated) = games::table
+                .find(id)
+                .select((games::defender, games::challenger, games::rated))
+                .first::<(i32, i32, bool)>(conn)?;
+            // Unrated games and internal errors never changed anything
+            if !rated || matches!(result.error_type, Some(crate::GameError::InternalError)) {
+                return Ok(result);
+            }
+            for (bot, change, before, bot_change, bot_before) in [
+                (
+                    defender,
+                    result.defender_rating_change,
+                    result
+                        .defender_deviation_before
+                        .zip(result.defender_volatility_before),
+                    result.defender_bot_rating_change,
+                    result
+                        .defender_bot_deviation_before
+                        .zip(result.defender_bot_volatility_before),
+                ),
+                (
+                    challenger,
+                    result.challenger_rating_change,
+                    result
+                        .challenger_deviation_before
+                        .zip(result.challenger_volatility_before),
+                    result.challenger_bot_rating_change,
+                    result
+                        .challenger_bot_deviation_before
+                        .zip(result.challenger_bot_volatility_before),
+                ),
+            ] {
+                let team = bots::table
+                    .find(bot)
+                    .select(bots::team)
+                    .first::<i32>(conn)?;
+                let team_bots = bots::table
+                    .filter(bots::team.eq(team))
+                    .select(bots::id)
+                    .load::<i32>(conn)?;
+                // Later games changed the deviation and volatility again, and
+                // those changes would be lost
+                let before = if rated_since(conn, &result, &team_bots)? {
+                    None
+                } else {
+                    before
+                };
+                let bot_before = if rated_since(conn, &result, &[bot])? {
+                    None
+                } else {
+                    bot_before
+                };
+                if let Some((deviation, volatility)) = before {
+                    diesel::update(schema::teams::table.find(team))
+                        .set((
+                            schema::teams::rating_deviation.eq(deviation),
+                            schema::teams::rating_volatility.eq(volatility),
+                        ))
+                        .execute(conn)?;
+                }
+                change_team_rating(conn, team, -change)?;
+                reverse_bot_rating(conn, bot, bot_change, bot_before)?;
+            }
+            Ok(result)
+        })
+    }
+
+    fn set_team_rating(&mut self, team: i32, rating: f32) -> Result<Team, diesel::result::Error> {
+        let current = schema::teams::table
+            .find(team)
+            .select(schema::teams::rating)
+            .first::<f32>(self)?;
+        change_team_rating(self, team, rating - current)
+    }
+}
+
+/// Whether any of `bots` played a rated game that still counts after `result`
+fn rated_since(
+    conn: &mut PgConnection,
+    result: &GameResult,
+    bots: &[i32],
+) -> Result<bool, diesel::result::Error> {
+    use schema::{game_results, games};
+    let later = game_results::table
+        .inner_join(games::table)
+        .filter(games::rated.eq(true))
+        .filter(game_results::voided.eq(false))
+        .filter(game_results::finish_order.gt(result.finish_order))
+        .filter(
+            games::defender
+                .eq_any(bots)
+                .or(games::challenger.eq_any(bots)),
+        )
+        .select(game_results::error_type)
+        .load::<Option<crate::GameError>>(conn)?;
+    // Internal errors never changed any ratings
+    Ok(later
+        .iter()
+        .any(|error| !matches!(error, Some(crate::GameError::InternalError))))
+}
+
+/// Change a team's rating by `change`, recording the new rating in its
+/// rating history
+pub fn change_team_rating(
+    conn: &mut PgConnection,
+    team: i32,
+    change: f32,
+) -> Result<Team, diesel::result::Error> {
+    use schema::teams::dsl;
+    let team: Team = diesel::update(dsl::teams.find(team))
+        .set(dsl::rating.eq(dsl::rating + change))
+        .get_result(conn)?;
+    diesel::insert_into(schema::rating_history::table)
+        .values(NewRatingHistoryEntry {
+            team: team.id,
+            bot: None,
+            game: None,
+            rating: team.rating,
+            rating_deviation: Some(team.rating_deviation),
+            created: chrono::Utc::now().timestamp(),
+        })
+        .execute(conn)?;
+    Ok(team)
+}
+
+/// Take a rated game off a bot: its rating change, if it was recorded, and
+/// the game from its games played. `before` is the deviation and volatility
+/// to put back, the ones the bot had before the game if it hasn't played a
+/// rated game since.
+pub fn reverse_bot_rating(
+    conn: &mut PgConnection,
+    bot: i32,
+    change: Option<f32>,
+    before: Option<(f32, f32)>,
+) -> Result<(), diesel::result::Error> {
+    use schema::bots::dsl;
+    diesel::update(dsl::bots.find(bot))
+        .set((
+            dsl::rating.eq(dsl::rating - change.unwrap_or(0.0)),
+            dsl::games_played.eq(dsl::games_played - 1),
+        ))
+        .execute(conn)?;
+    if let Some((deviation, volatility)) = before {
+        diesel::update(dsl::bots.find(bot))
+            .set((
+                dsl::rating_deviation.eq(deviation),
+                dsl::rating_volatility.eq(volatility),
+            ))
+            .execute(conn)?;
+    }
+    Ok(())
+}
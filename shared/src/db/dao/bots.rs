@@ -1,7 +1,38 @@
+use crate::BuildStatus;
+
 use super::*;
 
 pub trait BotsDao {
     fn get_bots_with_teams(&mut self, teams: Vec<i32>) -> Vec<BotWithTeam<Team>>;
+    /// Add an event to a bot's status history, and make it the bot's current
+    /// status if it is newer, see [replaces_status].
+    fn record_bot_status(&mut self, event: NewBotStatusEvent) -> Result<(), diesel::result::Error>;
+    fn get_bot_status_history(
+        &mut self,
+        bot: i32,
+    ) -> Result<Vec<BotStatusEvent>, diesel::result::Error>;
+}
+
+/// Whether a status event becomes the bot's current status. Workers report
+/// events through queues that are read concurrently, with timestamps from
+/// different hosts to the second, so the timestamps can't be trusted to order
+/// them. Instead a build only moves forward: `Queued` starts a new build, and
+/// an event from an earlier stage than the current status is only kept in the
+/// history. Timestamps only decide between events of the same stage.
+pub fn replaces_status(
+    current: BuildStatus,
+    latest: Option<i64>,
+    status: BuildStatus,
+    created: i64,
+) -> bool {
+    if matches!(status, BuildStatus::Queued) {
+        return true;
+    }
+    match status.stage().cmp(&current.stage()) {
+        std::cmp::Ordering::Greater => true,
+        std::cmp::Ordering::Equal => latest.map_or(true, |latest| created >= latest),
+        std::cmp::Ordering::Less => false,
+    }
 }
 
 impl BotsDao for PgConnection {
@@ -26,7 +57,102 @@ impl BotsDao for PgConnection {
                 created: bot.created,
                 uploaded_by: user,
                 build_status: bot.build_status,
+                rating: bot.rating,
+                games_played: bot.games_played,
             })
             .collect()
     }
+
+    fn record_bot_status(&mut self, event: NewBotStatusEvent) -> Result<(), diesel::result::Error> {
+        use schema::bot_status_history::dsl as history;
+        self.transaction(|conn| {
+            // Events for the same bot are handled one at a time
+            let current: BuildStatus = schema::bots::dsl::bots
+                .find(event.bot)
+                .select(schema::bots::dsl::build_status)
+                .for_update()
+                .first(conn)?;
+            let latest: Option<i64> = history::bot_status_history
+                .filter(history::bot.eq(event.bot))
+                .select(max(history::created))
+                .first(conn)?;
+            diesel::insert_into(history::bot_status_history)
+                .values(&event)
+                .execute(conn)?;
+            if replaces_status(current, latest, event.status, event.created) {
+                diesel::update(schema::bots::dsl::bots.find(event.bot))
+                    .set(schema::bots::dsl::build_status.eq(event.status))
+                    .execute(conn)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn get_bot_status_history(
+        &mut self,
+        bot: i32,
+    ) -> Result<Vec<BotStatusEvent>, diesel::result::Error> {
+        use schema::bot_status_history::dsl as history;
+        history::bot_status_history
+            .filter(history::bot.eq(bot))
+            .order_by((history::created.asc(), history::id.asc()))
+            .load::<BotStatusEvent>(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn late_events_never_undo_a_finished_build() {
+        // The test game finished, then the builder's Building event arrives
+        // with a skewed timestamp
+        assert!(!replaces_status(
+            BuildStatus::TestGameSucceeded,
+            Some(100),
+            BuildStatus::Building,
+            101
+        ));
+        assert!(!replaces_status(
+            BuildStatus::BuildFailed,
+            Some(100),
+            BuildStatus::PlayingTestGame,
+            100
+        ));
+        assert!(!replaces_status(
+            BuildStatus::PlayingTestGame,
+            Some(100),
+            BuildStatus::Building,
+            100
+        ));
+    }
+
+    #[test]
+    fn builds_move_forward_and_can_be_queued_again() {
+        assert!(replaces_status(
+            BuildStatus::Building,
+            Some(100),
+            BuildStatus::BuildSucceeded,
+            99
+        ));
+        assert!(replaces_status(
+            BuildStatus::TestGameFailed,
+            Some(100),
+            BuildStatus::Queued,
+            100
+        ));
+        assert!(replaces_status(
+            BuildStatus::BuildFailed,
+            Some(100),
+            BuildStatus::BuildTimedOut,
+            101
+        ));
+        assert!(!replaces_status(
+            BuildStatus::BuildTimedOut,
+            Some(100),
+            BuildStatus::BuildFailed,
+            99
+        ));
+    }
 }
@@ -12,7 +12,10 @@ use diesel::{ExpressionMethods, JoinOnDsl, QueryDsl};
 use rand::Rng;
 
 use super::*;
-use crate::{db::models, GameTask, PresignedRequest, WhichBot};
+use crate::{
+    db::models, GameConfig, GameTask, HouseBot, Participant, PresignedRequest, QueuedGame,
+    WhichBot,
+};
 
 #[derive(Serialize, Deserialize, Debug, Clone, TS)]
 #[cfg_attr(feature = "ts-bindings", ts(export))]
@@ -29,6 +32,14 @@ pub struct PageOptions {
     pub page: i32,
 }
 
+/// Where new games are queued, and the buckets their logs are uploaded to
+pub struct GameQueue<'a> {
+    pub game_logs_s3_bucket: &'a str,
+    pub new_games_sqs_queue: &'a str,
+    pub sqs_client: &'a aws_sdk_sqs::Client,
+    pub s3_client: &'a aws_sdk_s3::Client,
+}
+
 #[async_trait]
 pub trait GamesDao {
     async fn count_games(
@@ -45,10 +56,17 @@ pub trait GamesDao {
         defender: &Team,
         challenger: &Team,
         rated: bool,
-        game_logs_s3_bucket: &str,
-        new_games_sqs_queue: &str,
-        sqs_client: &aws_sdk_sqs::Client,
-        s3_client: &aws_sdk_s3::Client,
+        queue: &GameQueue<'_>,
+    ) -> Result<String, Box<dyn std::error::Error>>;
+    /// Like `create_game`, but with the given bots of the two teams instead of
+    /// their active bots, played with `config` instead of the default rules
+    async fn create_game_with_bots(
+        &mut self,
+        defender: (&Team, i32),
+        challenger: (&Team, i32),
+        rated: bool,
+        config: GameConfig,
+        queue: &GameQueue<'_>,
     ) -> Result<String, Box<dyn std::error::Error>>;
 }
 
@@ -187,40 +205,62 @@ impl GamesDao for PgConnection {
         defender_team: &Team,
         challenger_team: &Team,
         rated: bool,
-        game_logs_s3_bucket: &str,
-        new_games_sqs_queue: &str,
-        sqs_client: &aws_sdk_sqs::Client,
-        s3_client: &aws_sdk_s3::Client,
+        queue: &GameQueue<'_>,
     ) -> Result<String, Box<dyn std::error::Error>> {
+        let Some(defender_bot) = defender_team.active_bot else {
+            return Err("Defender team has no active bot".into());
+        };
+        let Some(challenger_bot) = challenger_team.active_bot else {
+            return Err("Challenger team has no active bot".into());
+        };
+        self.create_game_with_bots(
+            (defender_team, defender_bot),
+            (challenger_team, challenger_bot),
+            rated,
+            GameConfig::default(),
+            queue,
+        )
+        .await
+    }
+
+    async fn create_game_with_bots(
+        &mut self,
+        (defender_team, defender_bot): (&Team, i32),
+        (challenger_team, challenger_bot): (&Team, i32),
+        rated: bool,
+        config: GameConfig,
+        queue: &GameQueue<'_>,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        config.validate()?;
         // generate a random code and insert it into the database
         // also push a batch job to the queue
         let id = format!("{:02x}", rand::thread_rng().gen::<u128>());
         let local_id = id.clone();
-        if defender_team.active_bot.is_none() {
-            return Err("Defender team has no active bot".into());
-        }
-        if challenger_team.active_bot.is_none() {
-            return Err("Challenger team has no active bot".into());
-        }
         //self.transaction::<_, anyhow::Error, _>(move |self| {
         log::info!(
             "Creating game {} with defender {} and challenger {}. Current defender rating: {}, current challenger rating: {}, rated: {}",
             id,
-            defender_team.active_bot.unwrap(),
-            challenger_team.active_bot.unwrap(),
+            defender_bot,
+            challenger_bot,
             defender_team.rating,
             challenger_team.rating,
             rated,
         );
+        // House bots are played by the gameplay worker rather than downloaded
+        let (defender, challenger) = (
+            participant(self, defender_bot)?,
+            participant(self, challenger_bot)?,
+        );
         diesel::insert_into(schema::games::dsl::games)
             .values(models::NewGame {
-                defender: defender_team.active_bot.unwrap(),
-                challenger: challenger_team.active_bot.unwrap(),
+                defender: defender_bot,
+                challenger: challenger_bot,
                 id: id.clone(),
                 challenger_rating: challenger_team.rating,
                 defender_rating: defender_team.rating,
                 rated: rated,
-                running: true
+                running: true,
+                config,
             })
             .execute(self)?;
 
@@ -231,24 +271,28 @@ impl GamesDao for PgConnection {
                 PresigningConfig::expires_in(std::time::Duration::from_secs(60 * 60 * 24 * 7))?;
 
             let (game_records, public_logs, defender_logs, challenger_logs) = try_join4(
-                s3_client
+                queue
+                    .s3_client
                     .put_object()
-                    .bucket(game_logs_s3_bucket)
+                    .bucket(queue.game_logs_s3_bucket)
                     .key(format!("game_record/{}", id.clone()))
                     .presigned(presign_config.clone()),
-                s3_client
+                queue
+                    .s3_client
                     .put_object()
-                    .bucket(game_logs_s3_bucket)
+                    .bucket(queue.game_logs_s3_bucket)
                     .key(format!("public/{}", id.clone()))
                     .presigned(presign_config.clone()),
-                s3_client
+                queue
+                    .s3_client
                     .put_object()
-                    .bucket(game_logs_s3_bucket)
+                    .bucket(queue.game_logs_s3_bucket)
                     .key(format!("{}/{}", WhichBot::Defender.to_string(), id))
                     .presigned(presign_config.clone()),
-                s3_client
+                queue
+                    .s3_client
                     .put_object()
-                    .bucket(game_logs_s3_bucket)
+                    .bucket(queue.game_logs_s3_bucket)
                     .key(format!("{}/{}", WhichBot::Challenger.to_string(), id))
                     .presigned(presign_config.clone()),
             )
@@ -283,19 +327,20 @@ impl GamesDao for PgConnection {
                     headers: challenger_logs.headers().into(),
                 },
             );
-            let job = sqs_client
+            let job = queue
+                .sqs_client
                 .send_message()
-                .queue_url(new_games_sqs_queue)
-                .message_body(&serde_json::to_string(&GameTask::Game {
-                    defender: defender_team.active_bot.unwrap(),
-                    challenger: challenger_team.active_bot.unwrap(),
+                .queue_url(queue.new_games_sqs_queue)
+                .message_body(&serde_json::to_string(&GameTask::Game(Box::new(QueuedGame {
+                    defender,
+                    challenger,
                     id: id.clone(),
-                    rounds: 1000,
+                    config,
                     game_record_presigned,
                     public_logs_presigned,
                     defender_logs_presigned,
                     challenger_logs_presigned,
-                })?)
+                })))?)
                 .send()
                 .await?;
             Ok::<(), anyhow::Error>(())
@@ -314,3 +359,12 @@ impl GamesDao for PgConnection {
         Ok(local_id)
     }
 }
+
+/// Who plays `bot`'s side of a game
+fn participant(conn: &mut PgConnection, bot: i32) -> Result<Participant, diesel::result::Error> {
+    let house = schema::bots::table
+        .find(bot)
+        .select(schema::bots::house)
+        .first::<Option<HouseBot>>(conn)?;
+    Ok(house.map_or(Participant::Bot(bot), Participant::House))
+}
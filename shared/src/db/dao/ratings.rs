@@ -0,0 +1,116 @@
+use super::*;
+
+pub trait RatingsDao {
+    /// Record ratings after a game. Recording a game again replaces its entries.
+    fn record_ratings(
+        &mut self,
+        entries: &[NewRatingHistoryEntry],
+    ) -> Result<(), diesel::result::Error>;
+    /// A team's ratings between `from` and `to`, oldest first. Long histories
+    /// are downsampled to at most `points` entries.
+    fn get_rating_history(
+        &mut self,
+        team: i32,
+        from: Option<i64>,
+        to: Option<i64>,
+        points: usize,
+    ) -> Result<Vec<RatingHistoryEntry>, diesel::result::Error>;
+}
+
+impl RatingsDao for PgConnection {
+    fn record_ratings(
+        &mut self,
+        entries: &[NewRatingHistoryEntry],
+    ) -> Result<(), diesel::result::Error> {
+        use schema::rating_history::dsl as history;
+        for entry in entries {
+            diesel::insert_into(history::rating_history)
+                .values(entry)
+                .on_conflict((history::game, history::bot))
+                .do_update()
+                .set(entry)
+                .execute(self)?;
+        }
+        Ok(())
+    }
+
+    fn get_rating_history(
+        &mut self,
+        team: i32,
+        from: Option<i64>,
+        to: Option<i64>,
+        points: usize,
+    ) -> Result<Vec<RatingHistoryEntry>, diesel::result::Error> {
+        use schema::rating_history::dsl as history;
+        let mut query = history::rating_history
+            .filter(history::team.eq(team))
+            .into_boxed();
+        if let Some(from) = from {
+            query = query.filter(history::created.ge(from));
+        }
+        if let Some(to) = to {
+            query = query.filter(history::created.le(to));
+        }
+        let entries = query
+            .order_by((history::created.asc(), history::id.asc()))
+            .load::<RatingHistoryEntry>(self)?;
+        Ok(downsample(entries, points))
+    }
+}
+
+/// Split the time covered by `entries` into equal buckets and keep the last
+/// entry of each, along with the very first entry
+fn downsample(entries: Vec<RatingHistoryEntry>, points: usize) -> Vec<RatingHistoryEntry> {
+    if entries.len() <= points || points < 2 {
+        return entries;
+    }
+    let start = entries[0].created;
+    let span = (entries[entries.len() - 1].created - start).max(1);
+    let buckets = (points - 1) as i64;
+    let bucket =
+        |entry: &RatingHistoryEntry| ((entry.created - start) * buckets / span).min(buckets - 1);
+    let mut sampled = vec![entries[0].clone()];
+    for (i, entry) in entries.iter().enumerate().skip(1) {
+        let last_in_bucket = entries
+            .get(i + 1)
+            .map_or(true, |next| bucket(next) != bucket(entry));
+        if last_in_bucket {
+            sampled.push(entry.clone());
+        }
+    }
+    sampled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(created: i64) -> RatingHistoryEntry {
+        RatingHistoryEntry {
+            id: created as i32,
+            team: 1,
+            bot: Some(1),
+            game: None,
+            rating: created as f32,
+            rating_deviation: None,
+            created,
+        }
+    }
+
+    #[test]
+    fn downsamples_evenly_over_time() {
+        // A burst of games, then a few spread out
+        let entries = (0..100)
+            .chain([1000, 2000, 3000])
+            .map(entry)
+            .collect::<Vec<_>>();
+        let sampled = downsample(entries.clone(), 4);
+        assert!(sampled.len() <= 4);
+        assert_eq!(sampled[0].created, 0);
+        assert_eq!(sampled.last().unwrap().created, 3000);
+        assert!(sampled
+            .windows(2)
+            .all(|pair| pair[0].created < pair[1].created));
+        assert_eq!(downsample(entries[..3].to_vec(), 4).len(), 3);
+    }
+}
@@ -0,0 +1,83 @@
+use super::*;
+
+pub trait ScrimmagesDao {
+    fn create_scrimmage(
+        &mut self,
+        scrimmage: NewScrimmage,
+    ) -> Result<Scrimmage, diesel::result::Error>;
+    fn add_scrimmage_game(
+        &mut self,
+        scrimmage: i32,
+        game: &str,
+    ) -> Result<(), diesel::result::Error>;
+    fn get_scrimmage(&mut self, id: i32) -> Result<Option<Scrimmage>, diesel::result::Error>;
+    /// A team's scrimmages, newest first
+    fn get_scrimmages(&mut self, team: i32) -> Result<Vec<Scrimmage>, diesel::result::Error>;
+    fn get_scrimmage_games(&mut self, id: i32) -> Result<Vec<String>, diesel::result::Error>;
+    /// How many scrimmage games a team has started since `since`
+    fn count_scrimmage_games(
+        &mut self,
+        team: i32,
+        since: i64,
+    ) -> Result<i64, diesel::result::Error>;
+}
+
+impl ScrimmagesDao for PgConnection {
+    fn create_scrimmage(
+        &mut self,
+        scrimmage: NewScrimmage,
+    ) -> Result<Scrimmage, diesel::result::Error> {
+        diesel::insert_into(schema::scrimmages::table)
+            .values(&scrimmage)
+            .get_result(self)
+    }
+
+    fn add_scrimmage_game(
+        &mut self,
+        scrimmage: i32,
+        game: &str,
+    ) -> Result<(), diesel::result::Error> {
+        use schema::scrimmage_games::dsl;
+        diesel::insert_into(dsl::scrimmage_games)
+            .values((dsl::scrimmage.eq(scrimmage), dsl::game.eq(game)))
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn get_scrimmage(&mut self, id: i32) -> Result<Option<Scrimmage>, diesel::result::Error> {
+        schema::scrimmages::table
+            .find(id)
+            .first::<Scrimmage>(self)
+            .optional()
+    }
+
+    fn get_scrimmages(&mut self, team: i32) -> Result<Vec<Scrimmage>, diesel::result::Error> {
+        use schema::scrimmages::dsl;
+        dsl::scrimmages
+            .filter(dsl::team.eq(team))
+            .order_by((dsl::created.desc(), dsl::id.desc()))
+            .load::<Scrimmage>(self)
+    }
+
+    fn get_scrimmage_games(&mut self, id: i32) -> Result<Vec<String>, diesel::result::Error> {
+        use schema::scrimmage_games::dsl;
+        dsl::scrimmage_games
+            .filter(dsl::scrimmage.eq(id))
+            .select(dsl::game)
+            .load::<String>(self)
+    }
+
+    fn count_scrimmage_games(
+        &mut self,
+        team: i32,
+        since: i64,
+    ) -> Result<i64, diesel::result::Error> {
+        use schema::{scrimmage_games, scrimmages};
+        scrimmage_games::table
+            .inner_join(scrimmages::table)
+            .filter(scrimmages::team.eq(team))
+            .filter(scrimmages::created.ge(since))
+            .count()
+            .get_result(self)
+    }
+}
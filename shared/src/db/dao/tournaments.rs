@@ -0,0 +1,146 @@
+use super::*;
+
+pub trait TournamentsDao {
+    /// Create a tournament with its entrants, filling in their `tournament`
+    fn create_tournament(
+        &mut self,
+        tournament: NewTournament,
+        entrants: Vec<TournamentEntrant>,
+    ) -> Result<Tournament, diesel::result::Error>;
+    fn get_tournament(&mut self, id: i32) -> Result<Option<Tournament>, diesel::result::Error>;
+    fn get_tournaments(&mut self) -> Result<Vec<Tournament>, diesel::result::Error>;
+    /// A tournament's entrants, best first
+    fn get_standings(&mut self, id: i32) -> Result<Vec<TournamentEntrant>, diesel::result::Error>;
+    fn update_standings(
+        &mut self,
+        entrants: &[TournamentEntrant],
+    ) -> Result<(), diesel::result::Error>;
+    /// Every pairing of a tournament so far, with the result of its game once
+    /// it has finished
+    fn get_pairings(
+        &mut self,
+        id: i32,
+    ) -> Result<Vec<(TournamentPairing, Option<GameResult>)>, diesel::result::Error>;
+    fn add_pairings(
+        &mut self,
+        pairings: &[NewTournamentPairing],
+    ) -> Result<Vec<TournamentPairing>, diesel::result::Error>;
+    fn set_pairing_game(&mut self, pairing: i32, game: &str) -> Result<(), diesel::result::Error>;
+    fn set_current_round(
+        &mut self,
+        id: i32,
+        round: i32,
+        finished: Option<i64>,
+    ) -> Result<Tournament, diesel::result::Error>;
+}
+
+impl TournamentsDao for PgConnection {
+    fn create_tournament(
+        &mut self,
+        tournament: NewTournament,
+        entrants: Vec<TournamentEntrant>,
+    ) -> Result<Tournament, diesel::result::Error> {
+        self.transaction(|conn| {
+            let tournament: Tournament = diesel::insert_into(schema::tournaments::table)
+                .values(&tournament)
+                .get_result(conn)?;
+            let entrants = entrants
+                .into_iter()
+                .map(|entrant| TournamentEntrant {
+                    tournament: tournament.id,
+                    ..entrant
+                })
+                .collect::<Vec<_>>();
+            diesel::insert_into(schema::tournament_entrants::table)
+                .values(&entrants)
+                .execute(conn)?;
+            Ok(tournament)
+        })
+    }
+
+    fn get_tournament(&mut self, id: i32) -> Result<Option<Tournament>, diesel::result::Error> {
+        schema::tournaments::table
+            .find(id)
+            .first::<Tournament>(self)
+            .optional()
+    }
+
+    fn get_tournaments(&mut self) -> Result<Vec<Tournament>, diesel::result::Error> {
+        schema::tournaments::table
+            .order_by(schema::tournaments::dsl::created.desc())
+            .load::<Tournament>(self)
+    }
+
+    fn get_standings(&mut self, id: i32) -> Result<Vec<TournamentEntrant>, diesel::result::Error> {
+        use schema::tournament_entrants::dsl as entrants;
+        entrants::tournament_entrants
+            .filter(entrants::tournament.eq(id))
+            .order_by((
+                entrants::points.desc(),
+                entrants::buchholz.desc(),
+                entrants::sonneborn_berger.desc(),
+                entrants::chips.desc(),
+                entrants::seed.asc(),
+            ))
+            .load::<TournamentEntrant>(self)
+    }
+
+    fn update_standings(
+        &mut self,
+        entrants: &[TournamentEntrant],
+    ) -> Result<(), diesel::result::Error> {
+        use schema::tournament_entrants::dsl;
+        self.transaction(|conn| {
+            for entrant in entrants {
+                diesel::update(dsl::tournament_entrants.find((entrant.tournament, entrant.team)))
+                    .set(entrant)
+                    .execute(conn)?;
+            }
+            Ok(())
+        })
+    }
+
+    fn get_pairings(
+        &mut self,
+        id: i32,
+    ) -> Result<Vec<(TournamentPairing, Option<GameResult>)>, diesel::result::Error> {
+        use schema::{game_results, tournament_pairings::dsl as pairings};
+        pairings::tournament_pairings
+            .left_join(game_results::table.on(pairings::game.eq(game_results::id.nullable())))
+            .filter(pairings::tournament.eq(id))
+            .order_by((pairings::round.asc(), pairings::id.asc()))
+            .select((
+                TournamentPairing::as_select(),
+                Option::<GameResult>::as_select(),
+            ))
+            .load(self)
+    }
+
+    fn add_pairings(
+        &mut self,
+        pairings: &[NewTournamentPairing],
+    ) -> Result<Vec<TournamentPairing>, diesel::result::Error> {
+        diesel::insert_into(schema::tournament_pairings::table)
+            .values(pairings)
+            .get_results(self)
+    }
+
+    fn set_pairing_game(&mut self, pairing: i32, game: &str) -> Result<(), diesel::result::Error> {
+        diesel::update(schema::tournament_pairings::table.find(pairing))
+            .set(schema::tournament_pairings::dsl::game.eq(game))
+            .execute(self)?;
+        Ok(())
+    }
+
+    fn set_current_round(
+        &mut self,
+        id: i32,
+        round: i32,
+        finished: Option<i64>,
+    ) -> Result<Tournament, diesel::result::Error> {
+        use schema::tournaments::dsl;
+        diesel::update(dsl::tournaments.find(id))
+            .set((dsl::current_round.eq(round), dsl::finished.eq(finished)))
+            .get_result(self)
+    }
+}
@@ -17,10 +17,13 @@ use uuid::Uuid;
 
 use crate::{
     db::schema::{
-        auth, bots, game_results, game_states, games, team_invites, teams, user_profiles, users,
+        audit_log, auth, bot_stats, bot_status_history, bots, game_results, game_states, games,
+        rating_history, scrimmages, shadow_bot_ratings, shadow_game_ratings, shadow_team_ratings,
+        team_invites, teams, tournament_entrants, tournament_pairings, tournaments, user_profiles,
+        users,
     },
     poker::game::{Action, CommunityCards, EndReason, HoleCards, PlayerPosition},
-    BuildStatus, GameError, WhichBot,
+    BuildStatus, GameConfig, GameError, HouseBot, TournamentFormat, WhichBot,
 };
 
 #[derive(Serialize, Deserialize, Queryable, Debug, Selectable, TS)]
@@ -33,6 +36,9 @@ pub struct Team {
     pub active_bot: Option<i32>,
     pub deleted_at: Option<i64>,
     pub rating: f32,
+    /// How uncertain the rating is, see `results::rating`
+    pub rating_deviation: f32,
+    pub rating_volatility: f32,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, TS)]
@@ -46,6 +52,7 @@ pub struct TeamWithMembers<T> {
     pub invites: Option<Vec<TeamInvite>>,
     pub deleted_at: Option<i64>,
     pub rating: f32,
+    pub rating_deviation: f32,
 }
 
 #[derive(Insertable, Debug)]
@@ -99,6 +106,7 @@ pub struct NewGame {
     pub challenger_rating: f32,
     pub rated: bool,
     pub running: bool,
+    pub config: GameConfig,
 }
 
 #[derive(Queryable, Serialize, Deserialize, Debug, Selectable, TS)]
@@ -113,6 +121,7 @@ pub struct Game {
     pub challenger_rating: f32,
     pub rated: bool,
     pub running: bool,
+    pub config: GameConfig,
 }
 
 #[derive(
@@ -131,6 +140,28 @@ pub struct GameResult {
     pub updated_at: i64,
     pub defender_rating: f32,
     pub challenger_rating: f32,
+    /// The defender's luck-adjusted score, once it has been computed
+    pub adjusted_score: Option<f32>,
+    /// Whether an admin voided the game, reversing its rating changes
+    pub voided: bool,
+    /// The admin who voided the game
+    pub voided_by: Option<Uuid>,
+    pub void_reason: Option<String>,
+    pub voided_at: Option<i64>,
+    /// The teams' and bots' deviations and volatilities before the game, and
+    /// the bots' rating changes. These are unknown for older games.
+    pub defender_deviation_before: Option<f32>,
+    pub defender_volatility_before: Option<f32>,
+    pub challenger_deviation_before: Option<f32>,
+    pub challenger_volatility_before: Option<f32>,
+    pub defender_bot_rating_change: Option<f32>,
+    pub challenger_bot_rating_change: Option<f32>,
+    pub defender_bot_deviation_before: Option<f32>,
+    pub defender_bot_volatility_before: Option<f32>,
+    pub challenger_bot_deviation_before: Option<f32>,
+    pub challenger_bot_volatility_before: Option<f32>,
+    /// Counts up as games finish, so results can be replayed in order
+    pub finish_order: i64,
 }
 
 #[derive(Deserialize, Debug, Selectable, Insertable, TS, AsChangeset)]
@@ -145,6 +176,17 @@ pub struct NewGameResult {
     pub error_type: Option<GameError>,
     pub defender_rating: f32,
     pub challenger_rating: f32,
+    pub adjusted_score: Option<f32>,
+    pub defender_deviation_before: Option<f32>,
+    pub defender_volatility_before: Option<f32>,
+    pub challenger_deviation_before: Option<f32>,
+    pub challenger_volatility_before: Option<f32>,
+    pub defender_bot_rating_change: Option<f32>,
+    pub challenger_bot_rating_change: Option<f32>,
+    pub defender_bot_deviation_before: Option<f32>,
+    pub defender_bot_volatility_before: Option<f32>,
+    pub challenger_bot_deviation_before: Option<f32>,
+    pub challenger_bot_volatility_before: Option<f32>,
 }
 
 #[derive(Serialize, TS)]
@@ -187,6 +229,13 @@ pub struct Bot {
     pub uploaded_by: Uuid,
     pub build_status: BuildStatus,
     pub deleted_at: Option<i64>,
+    pub rating: f32,
+    pub rating_deviation: f32,
+    pub rating_volatility: f32,
+    /// Rated games this bot has played
+    pub games_played: i32,
+    /// The house bot this bot stands for, played by the gameplay worker
+    pub house: Option<HouseBot>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Queryable, TS)]
@@ -199,6 +248,8 @@ pub struct BotWithTeam<T> {
     pub created: i64,
     pub uploaded_by: User,
     pub build_status: BuildStatus,
+    pub rating: f32,
+    pub games_played: i32,
 }
 
 impl<T> BotWithTeam<T> {
@@ -211,6 +262,8 @@ impl<T> BotWithTeam<T> {
             created: bot.created,
             uploaded_by: user,
             build_status: bot.build_status,
+            rating: bot.rating,
+            games_played: bot.games_played,
         }
     }
 }
@@ -225,6 +278,162 @@ pub struct GameWithBots<T> {
     pub rated: bool,
 }
 
+/// A change of a bot's build status, as reported by the workers
+#[derive(Serialize, Deserialize, Debug, Queryable, Selectable, TS)]
+#[cfg_attr(feature = "ts-bindings", ts(export))]
+#[diesel(table_name = bot_status_history)]
+pub struct BotStatusEvent {
+    pub id: i32,
+    pub bot: i32,
+    pub status: BuildStatus,
+    pub error: Option<String>,
+    pub created: i64,
+}
+
+#[derive(Debug, diesel::Insertable)]
+#[diesel(table_name = bot_status_history)]
+pub struct NewBotStatusEvent {
+    pub bot: i32,
+    pub status: BuildStatus,
+    pub error: Option<String>,
+    pub created: i64,
+}
+
+/// A team's rating after a game
+#[derive(Serialize, Deserialize, Debug, Clone, Queryable, Selectable, TS)]
+#[cfg_attr(feature = "ts-bindings", ts(export))]
+#[diesel(table_name = rating_history)]
+pub struct RatingHistoryEntry {
+    pub id: i32,
+    pub team: i32,
+    /// The bot that played, unless it has been deleted since
+    pub bot: Option<i32>,
+    pub game: Option<String>,
+    pub rating: f32,
+    /// Not known for games rated before deviations were recorded
+    pub rating_deviation: Option<f32>,
+    pub created: i64,
+}
+
+#[derive(Debug, diesel::Insertable, diesel::AsChangeset)]
+#[diesel(table_name = rating_history)]
+pub struct NewRatingHistoryEntry {
+    pub team: i32,
+    pub bot: Option<i32>,
+    pub game: Option<String>,
+    pub rating: f32,
+    pub rating_deviation: Option<f32>,
+    pub created: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Queryable, Selectable, TS)]
+#[cfg_attr(feature = "ts-bindings", ts(export))]
+#[diesel(table_name = tournaments)]
+pub struct Tournament {
+    pub id: i32,
+    pub name: String,
+    pub format: TournamentFormat,
+    pub rounds: i32,
+    /// The round being played, or 0 before the tournament starts
+    pub current_round: i32,
+    pub created: i64,
+    pub finished: Option<i64>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = tournaments)]
+pub struct NewTournament {
+    pub name: String,
+    pub format: TournamentFormat,
+    pub rounds: i32,
+}
+
+/// A team in a tournament, with its standing after the last finished round
+#[derive(Serialize, Deserialize, Debug, Clone, Queryable, Selectable, Insertable, AsChangeset, TS)]
+#[cfg_attr(feature = "ts-bindings", ts(export))]
+#[diesel(table_name = tournament_entrants)]
+pub struct TournamentEntrant {
+    pub tournament: i32,
+    pub team: i32,
+    /// The team's active bot when it entered, which plays all its games
+    pub bot: i32,
+    /// The team's place in the ratings when it entered, 1 being the best
+    pub seed: i32,
+    pub points: f32,
+    /// The sum of the opponents' points
+    pub buchholz: f32,
+    /// The points of the opponents beaten, plus half of those drawn with
+    pub sonneborn_berger: f32,
+    /// Chips won minus chips lost
+    pub chips: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Queryable, Selectable, TS)]
+#[cfg_attr(feature = "ts-bindings", ts(export))]
+#[diesel(table_name = tournament_pairings)]
+pub struct TournamentPairing {
+    pub id: i32,
+    pub tournament: i32,
+    pub round: i32,
+    pub defender: i32,
+    /// None if the defender has a bye
+    pub challenger: Option<i32>,
+    pub game: Option<String>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = tournament_pairings)]
+pub struct NewTournamentPairing {
+    pub tournament: i32,
+    pub round: i32,
+    pub defender: i32,
+    pub challenger: Option<i32>,
+}
+
+/// A batch of unrated games between two bots of the same team
+#[derive(Serialize, Deserialize, Debug, Clone, Queryable, Selectable, TS)]
+#[cfg_attr(feature = "ts-bindings", ts(export))]
+#[diesel(table_name = scrimmages)]
+pub struct Scrimmage {
+    pub id: i32,
+    pub team: i32,
+    pub bot_a: i32,
+    pub bot_b: i32,
+    pub created: i64,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = scrimmages)]
+pub struct NewScrimmage {
+    pub team: i32,
+    pub bot_a: i32,
+    pub bot_b: i32,
+}
+
+/// Something an admin did through the admin API
+#[derive(Serialize, Deserialize, Debug, Clone, Queryable, Selectable, TS)]
+#[cfg_attr(feature = "ts-bindings", ts(export))]
+#[diesel(table_name = audit_log)]
+pub struct AuditLogEntry {
+    pub id: i32,
+    pub admin: Uuid,
+    pub action: String,
+    /// The id of whatever the action was done to
+    pub target: String,
+    /// JSON, depending on the action
+    pub details: Option<String>,
+    pub created: i64,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = audit_log)]
+pub struct NewAuditLogEntry {
+    pub admin: Uuid,
+    pub action: String,
+    pub target: String,
+    pub details: Option<String>,
+}
+
 #[derive(Debug, diesel::Insertable)]
 #[diesel(table_name = bots)]
 pub struct NewBot {
@@ -233,6 +442,8 @@ pub struct NewBot {
     pub description: Option<String>,
     pub uploaded_by: Uuid,
     pub build_status: BuildStatus,
+    /// The bot's starting rating. New bots start from the default if this is None.
+    pub rating: Option<f32>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Queryable, Selectable, Insertable, AsChangeset)]
@@ -306,6 +517,26 @@ impl FromSql<Integer, pg::Pg> for BuildStatus {
     }
 }
 
+impl ToSql<Integer, pg::Pg> for TournamentFormat {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, pg::Pg>,
+    ) -> diesel::serialize::Result {
+        let val = *self as i32;
+        ToSql::<Integer, pg::Pg>::to_sql(&val, &mut out.reborrow())
+    }
+}
+
+impl FromSql<Integer, pg::Pg> for TournamentFormat {
+    fn from_sql(bytes: PgValue) -> diesel::deserialize::Result<Self> {
+        if let Some(result) = num::FromPrimitive::from_i32(i32::from_sql(bytes)?) {
+            Ok(result)
+        } else {
+            Err("Invalid tournament format".into())
+        }
+    }
+}
+
 impl ToSql<Integer, pg::Pg> for WhichBot {
     fn to_sql<'b>(
         &'b self,
@@ -344,6 +575,42 @@ impl FromSql<Text, pg::Pg> for GameError {
     }
 }
 
+impl ToSql<Text, pg::Pg> for GameConfig {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, diesel::pg::Pg>,
+    ) -> diesel::serialize::Result {
+        out.write_all(serde_json::to_vec(self)?.as_slice())?;
+        Ok(diesel::serialize::IsNull::No)
+    }
+}
+
+impl FromSql<Text, pg::Pg> for GameConfig {
+    fn from_sql(bytes: diesel::pg::PgValue) -> diesel::deserialize::Result<Self> {
+        let s = String::from_sql(bytes)?;
+        serde_json::from_str(&s)
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
+    }
+}
+
+impl ToSql<Text, pg::Pg> for HouseBot {
+    fn to_sql<'b>(
+        &'b self,
+        out: &mut diesel::serialize::Output<'b, '_, diesel::pg::Pg>,
+    ) -> diesel::serialize::Result {
+        out.write_all(serde_json::to_vec(self)?.as_slice())?;
+        Ok(diesel::serialize::IsNull::No)
+    }
+}
+
+impl FromSql<Text, pg::Pg> for HouseBot {
+    fn from_sql(bytes: diesel::pg::PgValue) -> diesel::deserialize::Result<Self> {
+        let s = String::from_sql(bytes)?;
+        serde_json::from_str(&s)
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
+    }
+}
+
 impl ToSql<VarChar, pg::Pg> for HoleCards {
     fn to_sql<'b>(
         &'b self,
@@ -434,4 +701,70 @@ impl FromSql<VarChar, pg::Pg> for EndReason {
         serde_json::from_str(&s)
             .map_err(|e| Box::new(e) as Box<dyn std::error::Error + Send + Sync>)
     }
-}
\ No newline at end of file
+}
+
+/// A team's rating recomputed from the game history, waiting to be promoted
+#[derive(Debug, Clone, Queryable, Selectable, Insertable)]
+#[diesel(table_name = shadow_team_ratings)]
+pub struct ShadowTeamRating {
+    pub team: i32,
+    pub rating: f32,
+    pub rating_deviation: f32,
+    pub rating_volatility: f32,
+}
+
+#[derive(Debug, Clone, Queryable, Selectable, Insertable)]
+#[diesel(table_name = shadow_bot_ratings)]
+pub struct ShadowBotRating {
+    pub bot: i32,
+    pub rating: f32,
+    pub rating_deviation: f32,
+    pub rating_volatility: f32,
+    pub games_played: i32,
+}
+
+/// The recomputed team ratings after a game, and what the game's result
+/// records about the teams' and bots' ratings
+#[derive(Debug, Clone, PartialEq, Queryable, Selectable, Insertable)]
+#[diesel(table_name = shadow_game_ratings)]
+pub struct ShadowGameRating {
+    pub game: String,
+    pub defender_rating_change: f32,
+    pub challenger_rating_change: f32,
+    pub defender_rating: f32,
+    pub challenger_rating: f32,
+    pub defender_rating_deviation: f32,
+    pub challenger_rating_deviation: f32,
+    pub defender_deviation_before: f32,
+    pub defender_volatility_before: f32,
+    pub challenger_deviation_before: f32,
+    pub challenger_volatility_before: f32,
+    pub defender_bot_rating_change: f32,
+    pub challenger_bot_rating_change: f32,
+    pub defender_bot_deviation_before: f32,
+    pub defender_bot_volatility_before: f32,
+    pub challenger_bot_deviation_before: f32,
+    pub challenger_bot_volatility_before: f32,
+}
+
+/// How a bot played the hands of one game, see `results::hand_stats`
+#[derive(Debug, Clone, PartialEq, Queryable, Selectable, Insertable)]
+#[diesel(table_name = bot_stats)]
+pub struct BotStatsRow {
+    pub game: String,
+    pub bot: i32,
+    pub opponent: i32,
+    pub hands: i32,
+    pub vpip_hands: i32,
+    pub pfr_hands: i32,
+    pub three_bet_chances: i32,
+    pub three_bets: i32,
+    pub raises_faced: i32,
+    pub folds_to_raise: i32,
+    pub aggressive_actions: i32,
+    pub calls: i32,
+    pub saw_flop: i32,
+    pub showdowns: i32,
+    pub showdowns_won: i32,
+    pub pot_big_blinds: f64,
+}
@@ -1,5 +1,16 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    audit_log (id) {
+        id -> Int4,
+        admin -> Uuid,
+        action -> Text,
+        target -> Text,
+        details -> Nullable<Text>,
+        created -> Int8,
+    }
+}
+
 diesel::table! {
     auth (id) {
         email -> Text,
@@ -14,6 +25,37 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    bot_stats (game, bot) {
+        game -> Text,
+        bot -> Int4,
+        opponent -> Int4,
+        hands -> Int4,
+        vpip_hands -> Int4,
+        pfr_hands -> Int4,
+        three_bet_chances -> Int4,
+        three_bets -> Int4,
+        raises_faced -> Int4,
+        folds_to_raise -> Int4,
+        aggressive_actions -> Int4,
+        calls -> Int4,
+        saw_flop -> Int4,
+        showdowns -> Int4,
+        showdowns_won -> Int4,
+        pot_big_blinds -> Float8,
+    }
+}
+
+diesel::table! {
+    bot_status_history (id) {
+        id -> Int4,
+        bot -> Int4,
+        status -> Int4,
+        error -> Nullable<Text>,
+        created -> Int8,
+    }
+}
+
 diesel::table! {
     bots (id) {
         id -> Int4,
@@ -24,6 +66,11 @@ diesel::table! {
         uploaded_by -> Uuid,
         build_status -> Int4,
         deleted_at -> Nullable<Int8>,
+        rating -> Float4,
+        rating_deviation -> Float4,
+        rating_volatility -> Float4,
+        games_played -> Int4,
+        house -> Nullable<Text>,
     }
 }
 
@@ -38,6 +85,22 @@ diesel::table! {
         updated_at -> Int8,
         defender_rating -> Float4,
         challenger_rating -> Float4,
+        adjusted_score -> Nullable<Float4>,
+        voided -> Bool,
+        voided_by -> Nullable<Uuid>,
+        void_reason -> Nullable<Text>,
+        voided_at -> Nullable<Int8>,
+        defender_deviation_before -> Nullable<Float4>,
+        defender_volatility_before -> Nullable<Float4>,
+        challenger_deviation_before -> Nullable<Float4>,
+        challenger_volatility_before -> Nullable<Float4>,
+        defender_bot_rating_change -> Nullable<Float4>,
+        challenger_bot_rating_change -> Nullable<Float4>,
+        defender_bot_deviation_before -> Nullable<Float4>,
+        defender_bot_volatility_before -> Nullable<Float4>,
+        challenger_bot_deviation_before -> Nullable<Float4>,
+        challenger_bot_volatility_before -> Nullable<Float4>,
+        finish_order -> Int8,
     }
 }
 
@@ -70,6 +133,83 @@ diesel::table! {
         challenger_rating -> Float4,
         rated -> Bool,
         running -> Bool,
+        config -> Text,
+    }
+}
+
+diesel::table! {
+    rating_history (id) {
+        id -> Int4,
+        team -> Int4,
+        bot -> Nullable<Int4>,
+        game -> Nullable<Text>,
+        rating -> Float4,
+        rating_deviation -> Nullable<Float4>,
+        created -> Int8,
+    }
+}
+
+diesel::table! {
+    scrimmage_games (scrimmage, game) {
+        scrimmage -> Int4,
+        game -> Text,
+    }
+}
+
+diesel::table! {
+    scrimmages (id) {
+        id -> Int4,
+        team -> Int4,
+        bot_a -> Int4,
+        bot_b -> Int4,
+        created -> Int8,
+    }
+}
+
+diesel::table! {
+    shadow_bot_ratings (bot) {
+        bot -> Int4,
+        rating -> Float4,
+        rating_deviation -> Float4,
+        rating_volatility -> Float4,
+        games_played -> Int4,
+    }
+}
+
+diesel::table! {
+    shadow_game_ratings (game) {
+        game -> Text,
+        defender_rating_change -> Float4,
+        challenger_rating_change -> Float4,
+        defender_rating -> Float4,
+        challenger_rating -> Float4,
+        defender_rating_deviation -> Float4,
+        challenger_rating_deviation -> Float4,
+        defender_deviation_before -> Float4,
+        defender_volatility_before -> Float4,
+        challenger_deviation_before -> Float4,
+        challenger_volatility_before -> Float4,
+        defender_bot_rating_change -> Float4,
+        challenger_bot_rating_change -> Float4,
+        defender_bot_deviation_before -> Float4,
+        defender_bot_volatility_before -> Float4,
+        challenger_bot_deviation_before -> Float4,
+        challenger_bot_volatility_before -> Float4,
+    }
+}
+
+diesel::table! {
+    shadow_ratings_checksum (checksum) {
+        checksum -> Text,
+    }
+}
+
+diesel::table! {
+    shadow_team_ratings (team) {
+        team -> Int4,
+        rating -> Float4,
+        rating_deviation -> Float4,
+        rating_volatility -> Float4,
     }
 }
 
@@ -89,6 +229,44 @@ diesel::table! {
         active_bot -> Nullable<Int4>,
         deleted_at -> Nullable<Int8>,
         rating -> Float4,
+        rating_deviation -> Float4,
+        rating_volatility -> Float4,
+    }
+}
+
+diesel::table! {
+    tournament_entrants (tournament, team) {
+        tournament -> Int4,
+        team -> Int4,
+        bot -> Int4,
+        seed -> Int4,
+        points -> Float4,
+        buchholz -> Float4,
+        sonneborn_berger -> Float4,
+        chips -> Int8,
+    }
+}
+
+diesel::table! {
+    tournament_pairings (id) {
+        id -> Int4,
+        tournament -> Int4,
+        round -> Int4,
+        defender -> Int4,
+        challenger -> Nullable<Int4>,
+        game -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    tournaments (id) {
+        id -> Int4,
+        name -> Text,
+        format -> Int4,
+        rounds -> Int4,
+        current_round -> Int4,
+        created -> Int8,
+        finished -> Nullable<Int8>,
     }
 }
 
@@ -112,22 +290,53 @@ diesel::table! {
     }
 }
 
+diesel::joinable!(audit_log -> users (admin));
+diesel::joinable!(bot_stats -> games (game));
+diesel::joinable!(bot_status_history -> bots (bot));
 diesel::joinable!(bots -> auth (uploaded_by));
 diesel::joinable!(game_results -> games (id));
+diesel::joinable!(game_results -> users (voided_by));
 diesel::joinable!(game_states -> games (game_id));
+diesel::joinable!(rating_history -> bots (bot));
+diesel::joinable!(rating_history -> games (game));
+diesel::joinable!(rating_history -> teams (team));
+diesel::joinable!(scrimmage_games -> games (game));
+diesel::joinable!(scrimmage_games -> scrimmages (scrimmage));
+diesel::joinable!(scrimmages -> teams (team));
+diesel::joinable!(shadow_bot_ratings -> bots (bot));
+diesel::joinable!(shadow_game_ratings -> games (game));
+diesel::joinable!(shadow_team_ratings -> teams (team));
 diesel::joinable!(team_invites -> teams (team));
 diesel::joinable!(teams -> bots (active_bot));
+diesel::joinable!(tournament_entrants -> bots (bot));
+diesel::joinable!(tournament_entrants -> teams (team));
+diesel::joinable!(tournament_entrants -> tournaments (tournament));
+diesel::joinable!(tournament_pairings -> games (game));
+diesel::joinable!(tournament_pairings -> tournaments (tournament));
 diesel::joinable!(user_profiles -> auth (id));
 diesel::joinable!(users -> auth (id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    audit_log,
     auth,
+    bot_stats,
+    bot_status_history,
     bots,
     game_results,
     game_states,
     games,
+    rating_history,
+    scrimmage_games,
+    scrimmages,
+    shadow_bot_ratings,
+    shadow_game_ratings,
+    shadow_ratings_checksum,
+    shadow_team_ratings,
     team_invites,
     teams,
+    tournament_entrants,
+    tournament_pairings,
+    tournaments,
     user_profiles,
     users,
 );
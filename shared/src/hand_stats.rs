@@ -0,0 +1,579 @@
+//! Per-hand statistics about how bots play, for spotting leaks: how often a
+//! bot plays a hand (VPIP), raises before the flop (PFR), re-raises (3-bet),
+//! gives up against raises, bets instead of calling after the flop
+//! (aggression factor), goes to showdown (WTSD) and wins there (W$SD).
+//!
+//! Each game is counted once when its result comes in, into `bot_stats`, and
+//! the counts are summed over games when they are asked for.
+
+use std::ops::AddAssign;
+
+use diesel::prelude::*;
+use serde::Serialize;
+use crate::{
+    db::{
+        models::BotStatsRow,
+        schema::{bot_stats, games},
+    },
+    poker::game::{Action, EndReason, GameState, GameStateSQL, PlayerPosition, Round},
+    GameConfig, WhichBot,
+};
+
+/// What a bot did over some hands
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HandCounts {
+    pub hands: i32,
+    /// Hands where the bot put chips in before the flop without having to
+    pub vpip_hands: i32,
+    /// Hands where the bot raised before the flop
+    pub pfr_hands: i32,
+    /// Hands where the bot could re-raise an opening raise before the flop
+    pub three_bet_chances: i32,
+    pub three_bets: i32,
+    /// Times the bot had to act on a raise, on any street
+    pub raises_faced: i32,
+    pub folds_to_raise: i32,
+    /// Bets and raises after the flop
+    pub aggressive_actions: i32,
+    /// Calls after the flop
+    pub calls: i32,
+    pub saw_flop: i32,
+    pub showdowns: i32,
+    pub showdowns_won: i32,
+    /// The pots of all the hands added up, in big blinds
+    pub pot_big_blinds: f64,
+}
+
+impl AddAssign for HandCounts {
+    fn add_assign(&mut self, other: Self) {
+        self.hands += other.hands;
+        self.vpip_hands += other.vpip_hands;
+        self.pfr_hands += other.pfr_hands;
+        self.three_bet_chances += other.three_bet_chances;
+        self.three_bets += other.three_bets;
+        self.raises_faced += other.raises_faced;
+        self.folds_to_raise += other.folds_to_raise;
+        self.aggressive_actions += other.aggressive_actions;
+        self.calls += other.calls;
+        self.saw_flop += other.saw_flop;
+        self.showdowns += other.showdowns;
+        self.showdowns_won += other.showdowns_won;
+        self.pot_big_blinds += other.pot_big_blinds;
+    }
+}
+
+impl From<&BotStatsRow> for HandCounts {
+    fn from(row: &BotStatsRow) -> Self {
+        HandCounts {
+            hands: row.hands,
+            vpip_hands: row.vpip_hands,
+            pfr_hands: row.pfr_hands,
+            three_bet_chances: row.three_bet_chances,
+            three_bets: row.three_bets,
+            raises_faced: row.raises_faced,
+            folds_to_raise: row.folds_to_raise,
+            aggressive_actions: row.aggressive_actions,
+            calls: row.calls,
+            saw_flop: row.saw_flop,
+            showdowns: row.showdowns,
+            showdowns_won: row.showdowns_won,
+            pot_big_blinds: row.pot_big_blinds,
+        }
+    }
+}
+
+/// The usual poker statistics, as fractions between 0 and 1. Any that would
+/// divide by zero are left out.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct HandStats {
+    pub hands: i32,
+    pub vpip: Option<f64>,
+    pub pfr: Option<f64>,
+    pub three_bet: Option<f64>,
+    pub fold_to_raise: Option<f64>,
+    /// Bets and raises per call after the flop, so it can be more than 1
+    pub aggression_factor: Option<f64>,
+    /// Went to showdown, out of the hands that saw a flop
+    pub wtsd: Option<f64>,
+    /// Won at showdown, out of the showdowns
+    pub wsd: Option<f64>,
+    /// In big blinds
+    pub average_pot: Option<f64>,
+}
+
+impl From<HandCounts> for HandStats {
+    fn from(counts: HandCounts) -> Self {
+        let ratio = |a: i32, b: i32| (b > 0).then(|| a as f64 / b as f64);
+        HandStats {
+            hands: counts.hands,
+            vpip: ratio(counts.vpip_hands, counts.hands),
+            pfr: ratio(counts.pfr_hands, counts.hands),
+            three_bet: ratio(counts.three_bets, counts.three_bet_chances),
+            fold_to_raise: ratio(counts.folds_to_raise, counts.raises_faced),
+            aggression_factor: ratio(counts.aggressive_actions, counts.calls),
+            wtsd: ratio(counts.showdowns, counts.saw_flop),
+            wsd: ratio(counts.showdowns_won, counts.showdowns),
+            average_pot: (counts.hands > 0).then(|| counts.pot_big_blinds / counts.hands as f64),
+        }
+    }
+}
+
+/// The stats of a bot, and against each of its opponents
+#[derive(Debug, Clone, Serialize)]
+pub struct BotStats {
+    pub bot: i32,
+    pub stats: HandStats,
+    /// By opponent bot, most hands first
+    pub opponents: Vec<(i32, HandStats)>,
+}
+
+/// Count what the defender and the challenger did in a game, from its states
+/// in order. The recorded states don't say reliably who made each action, so
+/// every hand is played again with the same actions to find out.
+pub fn count_hands(states: &[GameStateSQL], config: &GameConfig) -> [HandCounts; 2] {
+    let mut counts = [HandCounts::default(); 2];
+    let hands = states
+        .split_inclusive(|state| state.end_reason.is_some())
+        .filter(|hand| hand.last().is_some_and(|state| state.end_reason.is_some()));
+    // Both bots start with the same stack, and carry it into the next hand
+    let mut stacks = [config.stack as i32; 2];
+    for hand in hands {
+        let end = hand.last().expect("Hands aren't empty");
+        if stacks[0] > 0 && stacks[1] > 0 {
+            let hand_counts = count_hand(hand, stacks, config);
+            counts[0] += hand_counts[0];
+            counts[1] += hand_counts[1];
+        }
+        stacks = [end.defender_stack, end.challenger_stack];
+    }
+    counts
+}
+
+/// Count one hand that started with the given defender and challenger stacks
+fn count_hand(hand: &[GameStateSQL], stacks: [i32; 2], config: &GameConfig) -> [HandCounts; 2] {
+    let sb = hand[0].sb;
+    let bot_at = |position: PlayerPosition| match position {
+        PlayerPosition::SmallBlind => sb as usize,
+        PlayerPosition::BigBlind => sb.other() as usize,
+    };
+    let position_stacks = match sb {
+        WhichBot::Defender => [stacks[0] as u32, stacks[1] as u32],
+        WhichBot::Challenger => [stacks[1] as u32, stacks[0] as u32],
+    };
+    // The cards don't change who acts, so any deck will do
+    let mut game = GameState::with_blinds(
+        position_stacks,
+        GameState::get_deck(),
+        config.limit,
+        [config.small_blind, config.big_blind],
+    );
+
+    let mut counts = [HandCounts::default(); 2];
+    let mut vpip = [false; 2];
+    let mut pfr = [false; 2];
+    let mut three_bet_chance = [false; 2];
+    let mut three_bet = [false; 2];
+    let mut preflop_raises = 0;
+    let mut last_raiser = None;
+    for state in hand {
+        let Some(actor) = game.whose_turn() else {
+            break;
+        };
+        let bot = bot_at(actor);
+        let round = game.round;
+        let target = game.target_push;
+        let facing = target > game.player_states[actor as usize].pushed;
+        let facing_raise = facing && last_raiser == Some(actor.other());
+        if round == Round::PreFlop && preflop_raises == 1 && last_raiser == Some(actor.other()) {
+            three_bet_chance[bot] = true;
+        }
+        game = match game.post_action(state.action_val.clone()) {
+            Ok(game) => game,
+            Err(_) => break,
+        };
+        let folded = state.action_val == Action::Fold;
+        let raised = !folded && game.player_states[actor as usize].pushed > target;
+
+        if facing_raise {
+            counts[bot].raises_faced += 1;
+            if folded {
+                counts[bot].folds_to_raise += 1;
+            }
+        }
+        if round == Round::PreFlop {
+            vpip[bot] |= raised || (facing && !folded);
+            pfr[bot] |= raised;
+            if raised {
+                three_bet[bot] |= three_bet_chance[bot] && preflop_raises == 1;
+                preflop_raises += 1;
+            }
+        } else if raised {
+            counts[bot].aggressive_actions += 1;
+        } else if facing && !folded {
+            counts[bot].calls += 1;
+        }
+        if raised {
+            last_raiser = Some(actor);
+        }
+    }
+
+    let end = hand.last().expect("Hands aren't empty");
+    let saw_flop = !end.community_cards.is_empty();
+    let pot = (end.defender_pushed + end.challenger_pushed) as f64 / config.big_blind as f64;
+    let winner = match end.end_reason {
+        Some(EndReason::WonShowdown(position)) => Some(bot_at(position)),
+        _ => None,
+    };
+    let showdown = matches!(
+        end.end_reason,
+        Some(EndReason::WonShowdown(_)) | Some(EndReason::Tie)
+    );
+    for (bot, counts) in counts.iter_mut().enumerate() {
+        counts.hands = 1;
+        counts.vpip_hands = vpip[bot] as i32;
+        counts.pfr_hands = pfr[bot] as i32;
+        counts.three_bet_chances = three_bet_chance[bot] as i32;
+        counts.three_bets = three_bet[bot] as i32;
+        counts.saw_flop = saw_flop as i32;
+        counts.showdowns = showdown as i32;
+        counts.showdowns_won = (winner == Some(bot)) as i32;
+        counts.pot_big_blinds = pot;
+    }
+    counts
+}
+
+/// Count the hands of a game and store them, replacing any earlier counts.
+/// Games that aren't in the database, like test games, are skipped.
+pub fn save_hand_stats(
+    conn: &mut PgConnection,
+    id: &str,
+    states: &[GameStateSQL],
+    config: &GameConfig,
+) -> Result<(), diesel::result::Error> {
+    let Some((defender, challenger)) = games::table
+        .find(id)
+        .select((games::defender, games::challenger))
+        .first::<(i32, i32)>(conn)
+        .optional()?
+    else {
+        return Ok(());
+    };
+    let [defender_counts, challenger_counts] = count_hands(states, config);
+    let row = |bot, opponent, counts: HandCounts| BotStatsRow {
+        game: id.to_string(),
+        bot,
+        opponent,
+        hands: counts.hands,
+        vpip_hands: counts.vpip_hands,
+        pfr_hands: counts.pfr_hands,
+        three_bet_chances: counts.three_bet_chances,
+        three_bets: counts.three_bets,
+        raises_faced: counts.raises_faced,
+        folds_to_raise: counts.folds_to_raise,
+        aggressive_actions: counts.aggressive_actions,
+        calls: counts.calls,
+        saw_flop: counts.saw_flop,
+        showdowns: counts.showdowns,
+        showdowns_won: counts.showdowns_won,
+        pot_big_blinds: counts.pot_big_blinds,
+    };
+    let rows = [
+        row(defender, challenger, defender_counts),
+        row(challenger, defender, challenger_counts),
+    ];
+    conn.transaction(|conn| {
+        diesel::delete(bot_stats::table.filter(bot_stats::game.eq(id))).execute(conn)?;
+        diesel::insert_into(bot_stats::table)
+            .values(&rows[..])
+            .execute(conn)?;
+        Ok(())
+    })
+}
+
+/// The stats of `bot` over all its games, or only those against `opponent`
+/// or only the game `game`
+pub fn bot_stats(
+    conn: &mut PgConnection,
+    bot: i32,
+    opponent: Option<i32>,
+    game: Option<&str>,
+) -> Result<BotStats, diesel::result::Error> {
+    let mut query = bot_stats::table
+        .filter(bot_stats::bot.eq(bot))
+        .select(BotStatsRow::as_select())
+        .into_boxed();
+    if let Some(opponent) = opponent {
+        query = query.filter(bot_stats::opponent.eq(opponent));
+    }
+    if let Some(game) = game {
+        query = query.filter(bot_stats::game.eq(game.to_string()));
+    }
+    let rows = query.load(conn)?;
+    Ok(aggregate(bot, &rows))
+}
+
+/// Sum the counts of `bot` over the rows of its games, in total and by opponent
+fn aggregate(bot: i32, rows: &[BotStatsRow]) -> BotStats {
+    let mut total = HandCounts::default();
+    let mut by_opponent = std::collections::BTreeMap::<i32, HandCounts>::new();
+    for row in rows {
+        total += HandCounts::from(row);
+        *by_opponent.entry(row.opponent).or_default() += HandCounts::from(row);
+    }
+    let mut opponents = by_opponent.into_iter().collect::<Vec<_>>();
+    opponents.sort_by_key(|(_, counts)| -counts.hands);
+    BotStats {
+        bot,
+        stats: total.into(),
+        opponents: opponents
+            .into_iter()
+            .map(|(opponent, counts)| (opponent, counts.into()))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::poker::game::{CommunityCards, HoleCards};
+
+    use super::*;
+
+    /// A state after `action`, with the stacks at the end of the hand
+    fn state(
+        action: Action,
+        pushed: [i32; 2],
+        board: usize,
+        end: Option<(EndReason, [i32; 2])>,
+    ) -> GameStateSQL {
+        let deck = GameState::get_deck();
+        let stacks = end.as_ref().map_or([500, 500], |(_, stacks)| *stacks);
+        GameStateSQL {
+            game_id: "game".to_string(),
+            step: 0,
+            challenger_stack: stacks[1],
+            defender_stack: stacks[0],
+            challenger_pushed: pushed[1],
+            defender_pushed: pushed[0],
+            challenger_hand: HoleCards([deck[0], deck[1]]),
+            defender_hand: HoleCards([deck[2], deck[3]]),
+            community_cards: CommunityCards(deck[4..4 + board].to_vec()),
+            sb: WhichBot::Defender,
+            action_time: 0,
+            whose_turn: None,
+            action_val: action,
+            end_reason: end.map(|(reason, _)| reason),
+        }
+    }
+
+    #[test]
+    fn counts_a_three_bet_and_a_showdown() {
+        // The defender is the small blind and raises, the challenger 3-bets
+        // and the defender calls. Then they check it down and the challenger wins.
+        let check = || Action::Raise(0);
+        let mut states = vec![
+            state(Action::Raise(4), [6, 2], 0, None),
+            state(Action::Raise(6), [6, 12], 0, None),
+            state(check(), [12, 12], 3, None),
+        ];
+        for _ in 0..5 {
+            states.push(state(check(), [12, 12], 5, None));
+        }
+        states.push(state(
+            check(),
+            [12, 12],
+            5,
+            Some((EndReason::WonShowdown(PlayerPosition::BigBlind), [488, 512])),
+        ));
+        // In the next hand the challenger is the small blind and folds straight away
+        let mut fold = state(
+            Action::Fold,
+            [2, 1],
+            0,
+            Some((EndReason::LastToAct(PlayerPosition::BigBlind), [489, 511])),
+        );
+        fold.sb = WhichBot::Challenger;
+        states.push(fold);
+
+        let [defender, challenger] = count_hands(&states, &GameConfig::default());
+        assert_eq!(defender.hands, 2);
+        assert_eq!(defender.vpip_hands, 1);
+        assert_eq!(defender.pfr_hands, 1);
+        assert_eq!(defender.raises_faced, 1);
+        assert_eq!(defender.folds_to_raise, 0);
+        assert_eq!(challenger.three_bet_chances, 1);
+        assert_eq!(challenger.three_bets, 1);
+        assert_eq!(challenger.vpip_hands, 1);
+        assert_eq!(challenger.raises_faced, 1);
+        assert_eq!(defender.saw_flop, 1);
+        assert_eq!(defender.showdowns, 1);
+        assert_eq!(defender.showdowns_won, 0);
+        assert_eq!(challenger.showdowns_won, 1);
+        assert_eq!(defender.pot_big_blinds, 12.0 + 1.5);
+
+        let stats = HandStats::from(defender);
+        assert_eq!(stats.vpip, Some(0.5));
+        assert_eq!(stats.wsd, Some(0.0));
+        assert_eq!(stats.aggression_factor, None);
+    }
+
+    #[test]
+    fn counts_bets_raises_and_calls_after_the_flop() {
+        // The defender is the small blind and limps, the challenger checks.
+        // The big blind acts first after the flop.
+        let check = || Action::Raise(0);
+        let states = vec![
+            state(check(), [2, 2], 0, None),
+            state(check(), [2, 2], 3, None),
+            // Flop: the challenger bets and the defender calls
+            state(Action::Raise(6), [2, 8], 3, None),
+            state(check(), [8, 8], 4, None),
+            // Turn: the challenger checks, the defender bets, the challenger
+            // raises and the defender calls
+            state(check(), [8, 8], 4, None),
+            state(Action::Raise(10), [18, 8], 4, None),
+            state(Action::Raise(10), [18, 28], 4, None),
+            state(check(), [28, 28], 5, None),
+            // River: the challenger bets and the defender folds
+            state(Action::Raise(20), [28, 48], 5, None),
+            state(
+                Action::Fold,
+                [28, 48],
+                5,
+                Some((EndReason::LastToAct(PlayerPosition::BigBlind), [472, 528])),
+            ),
+        ];
+
+        let [defender, challenger] = count_hands(&states, &GameConfig::default());
+        assert_eq!(defender.vpip_hands, 1);
+        assert_eq!(challenger.vpip_hands, 0);
+        assert_eq!(defender.pfr_hands + challenger.pfr_hands, 0);
+        assert_eq!(challenger.aggressive_actions, 3);
+        assert_eq!(challenger.calls, 0);
+        assert_eq!(defender.aggressive_actions, 1);
+        assert_eq!(defender.calls, 2);
+        assert_eq!(defender.raises_faced, 3);
+        assert_eq!(defender.folds_to_raise, 1);
+        assert_eq!(challenger.raises_faced, 1);
+        assert_eq!(challenger.folds_to_raise, 0);
+        assert_eq!(defender.showdowns, 0);
+        assert_eq!(defender.pot_big_blinds, 38.0);
+        assert_eq!(HandStats::from(defender).aggression_factor, Some(0.5));
+    }
+
+    #[test]
+    fn only_folds_to_a_raise_count_as_folds_to_raise() {
+        // The defender raises as the small blind and the challenger folds
+        let mut states = vec![
+            state(Action::Raise(4), [6, 2], 0, None),
+            state(
+                Action::Fold,
+                [6, 2],
+                0,
+                Some((EndReason::LastToAct(PlayerPosition::SmallBlind), [502, 498])),
+            ),
+        ];
+        // Then the challenger is the small blind and folds to the big blind
+        let mut fold = state(
+            Action::Fold,
+            [2, 1],
+            0,
+            Some((EndReason::LastToAct(PlayerPosition::BigBlind), [503, 497])),
+        );
+        fold.sb = WhichBot::Challenger;
+        states.push(fold);
+
+        let [defender, challenger] = count_hands(&states, &GameConfig::default());
+        assert_eq!(challenger.hands, 2);
+        assert_eq!(challenger.raises_faced, 1);
+        assert_eq!(challenger.folds_to_raise, 1);
+        assert_eq!(challenger.vpip_hands, 0);
+        assert_eq!(defender.raises_faced, 0);
+        assert_eq!(defender.pfr_hands, 1);
+        assert_eq!(HandStats::from(challenger).fold_to_raise, Some(1.0));
+    }
+
+    #[test]
+    fn stacks_carry_over_and_hands_after_a_bust_are_skipped() {
+        let config = GameConfig {
+            stack: 200,
+            ..GameConfig::default()
+        };
+        // The challenger is left with 2 chips after the first hand
+        let mut states = vec![
+            state(Action::Raise(4), [6, 2], 0, None),
+            state(
+                Action::Fold,
+                [6, 2],
+                0,
+                Some((EndReason::LastToAct(PlayerPosition::SmallBlind), [398, 2])),
+            ),
+        ];
+        // So the defender's raise in the next hand only calls the big blind,
+        // and the challenger loses everything
+        states.push(state(Action::Raise(10), [2, 2], 0, None));
+        states.push(state(
+            Action::Fold,
+            [2, 2],
+            0,
+            Some((EndReason::LastToAct(PlayerPosition::SmallBlind), [400, 0])),
+        ));
+        // A hand recorded after that isn't counted
+        states.push(state(
+            Action::Fold,
+            [1, 2],
+            0,
+            Some((EndReason::LastToAct(PlayerPosition::BigBlind), [399, 1])),
+        ));
+
+        let [defender, challenger] = count_hands(&states, &config);
+        assert_eq!(defender.hands, 2);
+        assert_eq!(challenger.hands, 2);
+        assert_eq!(defender.pfr_hands, 1);
+        assert_eq!(defender.vpip_hands, 2);
+    }
+
+    #[test]
+    fn bot_stats_add_up_games_by_opponent_with_the_most_hands_first() {
+        let row = |game: &str, opponent, hands, vpip_hands| BotStatsRow {
+            game: game.to_string(),
+            bot: 1,
+            opponent,
+            hands,
+            vpip_hands,
+            pfr_hands: 0,
+            three_bet_chances: 0,
+            three_bets: 0,
+            raises_faced: 0,
+            folds_to_raise: 0,
+            aggressive_actions: 0,
+            calls: 0,
+            saw_flop: 0,
+            showdowns: 0,
+            showdowns_won: 0,
+            pot_big_blinds: hands as f64,
+        };
+        let stats = aggregate(
+            1,
+            &[
+                row("a", 3, 10, 5),
+                row("b", 2, 20, 4),
+                row("c", 5, 10, 0),
+                row("d", 2, 10, 2),
+            ],
+        );
+        assert_eq!(stats.bot, 1);
+        assert_eq!(stats.stats.hands, 50);
+        assert_eq!(stats.stats.vpip, Some(11.0 / 50.0));
+        assert_eq!(stats.stats.average_pot, Some(1.0));
+        // Ties keep the lower opponent id first
+        let opponents = stats
+            .opponents
+            .iter()
+            .map(|(opponent, stats)| (*opponent, stats.hands))
+            .collect::<Vec<_>>();
+        assert_eq!(opponents, vec![(2, 30), (3, 10), (5, 10)]);
+        assert_eq!(stats.opponents[0].1.vpip, Some(0.2));
+        assert_eq!(stats.opponents[2].1.vpip, Some(0.0));
+        assert_eq!(aggregate(1, &[]).stats.vpip, None);
+    }
+}
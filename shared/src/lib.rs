@@ -1,13 +1,17 @@
+#[cfg(feature = "aws")]
 pub mod s3;
+#[cfg(feature = "aws")]
 pub mod sqs;
 use std::{
     fmt::Display,
     io::{self, Write},
-    str::FromStr,
 };
 
+#[cfg(feature = "aws")]
 use aws_config::SdkConfig;
+#[cfg(feature = "aws")]
 use aws_sdk_s3::config::Credentials;
+#[cfg(feature = "aws")]
 use reqwest::header::{HeaderMap, HeaderName};
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
@@ -17,12 +21,22 @@ extern crate num_derive;
 
 #[cfg(feature = "db")]
 pub mod db;
+#[cfg(feature = "db")]
+pub mod hand_stats;
+#[cfg(feature = "db")]
+pub mod rating;
+#[cfg(feature = "db")]
+pub mod tournament;
+#[cfg(feature = "db")]
+pub mod void;
 
 pub mod poker;
+pub mod presets;
 
 #[derive(Serialize, Deserialize, Debug, Clone, TS)]
 pub struct SerializableHeaderMap(Vec<(String, String)>);
 
+#[cfg(feature = "aws")]
 impl From<&HeaderMap> for SerializableHeaderMap {
     fn from(map: &HeaderMap) -> Self {
         Self(
@@ -34,11 +48,12 @@ impl From<&HeaderMap> for SerializableHeaderMap {
     }
 }
 
+#[cfg(feature = "aws")]
 impl Into<HeaderMap> for SerializableHeaderMap {
     fn into(self) -> HeaderMap {
         let mut map = HeaderMap::new();
         for (k, v) in self.0 {
-            if let Ok(k) = HeaderName::from_str(&k) {
+            if let Ok(k) = k.parse::<HeaderName>() {
                 if let Ok(v) = v.parse() {
                     map.insert(k, v);
                 }
@@ -74,31 +89,215 @@ pub enum BuildStatus {
     TestGameSucceeded = 4,
     BuildFailed = 5,
     TestGameFailed = 6,
+    BuildTimedOut = 7,
+    BuildOutOfMemory = 8,
+    ArchiveTooLarge = 9,
+    ArchiveTooManyFiles = 10,
 }
 
+impl BuildStatus {
+    /// How far a bot is through its build and test game. A build starts at
+    /// `Queued`, and every status after `PlayingTestGame` ends it.
+    pub fn stage(&self) -> i32 {
+        match self {
+            BuildStatus::Unqueued => -1,
+            BuildStatus::Queued => 0,
+            BuildStatus::Building => 1,
+            BuildStatus::BuildSucceeded => 2,
+            BuildStatus::PlayingTestGame => 3,
+            BuildStatus::TestGameSucceeded
+            | BuildStatus::BuildFailed
+            | BuildStatus::TestGameFailed
+            | BuildStatus::BuildTimedOut
+            | BuildStatus::BuildOutOfMemory
+            | BuildStatus::ArchiveTooLarge
+            | BuildStatus::ArchiveTooManyFiles => 4,
+        }
+    }
+}
+
+/// How the pairings of a tournament are chosen
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, TS, FromPrimitive)]
+#[repr(i32)]
+#[cfg_attr(feature = "ts-bindings", ts(export))]
+#[cfg_attr(feature = "db", derive(diesel::FromSqlRow, diesel::AsExpression))]
+#[cfg_attr(feature="db", diesel(sql_type=diesel::sql_types::Integer))]
+pub enum TournamentFormat {
+    /// Every entrant plays every other entrant once
+    RoundRobin = 0,
+    /// Entrants with the same score play each other, for a fixed number of rounds
+    Swiss = 1,
+}
+
+/// A change of a bot's build status, sent by the builder and the gameplay worker
 #[derive(Serialize, Deserialize, Debug, Clone, TS)]
 pub struct BuildResultMessage {
     pub status: BuildStatus,
     pub bot: i32,
     pub error: Option<String>,
+    /// When the status changed, in seconds since the epoch. Messages sent
+    /// before this was added have none, and are recorded when they are read.
+    pub timestamp: Option<i64>,
+}
+
+impl BuildResultMessage {
+    pub fn new(bot: i32, status: BuildStatus, error: Option<String>) -> Self {
+        BuildResultMessage {
+            status,
+            bot,
+            error,
+            timestamp: Some(chrono::offset::Utc::now().timestamp()),
+        }
+    }
+}
+
+/// A bot built into the gameplay worker. House bots play without a sandbox,
+/// as baseline opponents for benchmarks and regression checks.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, TS)]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(feature = "ts-bindings", ts(export))]
+#[cfg_attr(feature = "db", derive(diesel::AsExpression, diesel::FromSqlRow))]
+#[cfg_attr(feature = "db", diesel(sql_type = diesel::sql_types::Text))]
+pub enum HouseBot {
+    /// Folds, calls or raises a random amount
+    Random,
+    /// Always calls
+    CallingStation,
+    /// Plays a preflop chart, then bets its made hands
+    TightAggressive,
+    /// Compares its equity against a random hand to the pot odds
+    Equity,
+}
+
+/// Who plays one side of a game
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, TS)]
+#[serde(untagged)]
+pub enum Participant {
+    /// An uploaded bot, by id
+    Bot(i32),
+    House(HouseBot),
+}
+
+impl From<i32> for Participant {
+    fn from(bot: i32) -> Self {
+        Participant::Bot(bot)
+    }
+}
+
+/// The rules a game is played with, chosen when it is created
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, TS)]
+#[serde(default)]
+#[cfg_attr(feature = "ts-bindings", ts(export))]
+#[cfg_attr(feature = "db", derive(diesel::AsExpression, diesel::FromSqlRow))]
+#[cfg_attr(feature = "db", diesel(sql_type = diesel::sql_types::Text))]
+pub struct GameConfig {
+    /// Hands to play, unless a bot runs out of chips first
+    pub hands: usize,
+    /// Chips each bot starts with
+    pub stack: u32,
+    pub small_blind: u32,
+    pub big_blind: u32,
+    /// The most chips a bot can put into the pot in one hand
+    pub limit: u32,
+    /// The total time each bot has to act over the whole game, in milliseconds
+    pub clock_ms: u32,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            hands: 1000,
+            stack: 500,
+            small_blind: 1,
+            big_blind: 2,
+            limit: 50,
+            clock_ms: 10_000,
+        }
+    }
+}
+
+impl GameConfig {
+    /// Checks that a game can be played with these rules
+    pub fn validate(&self) -> Result<(), InvalidConfig> {
+        if self.hands == 0 {
+            Err(InvalidConfig::NoHands)
+        } else if self.stack == 0 {
+            Err(InvalidConfig::NoStack)
+        } else if self.big_blind <= self.small_blind {
+            Err(InvalidConfig::BlindsOutOfOrder)
+        } else if self.stack < self.big_blind {
+            Err(InvalidConfig::StackBelowBigBlind)
+        } else if self.limit < self.big_blind {
+            Err(InvalidConfig::LimitBelowBigBlind)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Why a `GameConfig` can't be played
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InvalidConfig {
+    NoHands,
+    NoStack,
+    BlindsOutOfOrder,
+    StackBelowBigBlind,
+    LimitBelowBigBlind,
+}
+
+impl Display for InvalidConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvalidConfig::NoHands => write!(f, "A game needs at least one hand"),
+            InvalidConfig::NoStack => write!(f, "Bots need chips to play"),
+            InvalidConfig::BlindsOutOfOrder => {
+                write!(f, "The big blind must be more than the small blind")
+            }
+            InvalidConfig::StackBelowBigBlind => {
+                write!(f, "Bots need enough chips to post the big blind")
+            }
+            InvalidConfig::LimitBelowBigBlind => {
+                write!(f, "The limit must allow the big blind to be posted")
+            }
+        }
+    }
 }
 
+impl std::error::Error for InvalidConfig {}
+
 #[derive(Serialize, Deserialize, Debug, Clone, TS)]
 pub enum GameTask {
-    Game {
-        defender: i32,
-        challenger: i32,
-        id: String,
-        rounds: usize,
-        game_record_presigned: PresignedRequest,
-        public_logs_presigned: PresignedRequest,
-        defender_logs_presigned: PresignedRequest,
-        challenger_logs_presigned: PresignedRequest,
-    },
+    /// Boxed since it is much larger than the other tasks. It is sent the same
+    /// way as if its fields were in the variant.
+    Game(Box<QueuedGame>),
     TestGame {
         bot: i32,
         log_presigned: PresignedRequest,
     },
+    /// An unrated game against a bot connected to the website's relay under `session`
+    RemoteGame {
+        opponent: i32,
+        session: String,
+        #[serde(default)]
+        config: GameConfig,
+        /// The remote bot asked to be sent `CONFIG`
+        #[serde(default)]
+        game_config: bool,
+    },
+}
+
+/// A game between two bots, and where to upload its record and logs
+#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+pub struct QueuedGame {
+    pub defender: Participant,
+    pub challenger: Participant,
+    pub id: String,
+    #[serde(default)]
+    pub config: GameConfig,
+    pub game_record_presigned: PresignedRequest,
+    pub public_logs_presigned: PresignedRequest,
+    pub defender_logs_presigned: PresignedRequest,
+    pub challenger_logs_presigned: PresignedRequest,
 }
 
 #[derive(
@@ -157,31 +356,131 @@ pub enum GameError {
     InternalError,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[derive(Serialize, Debug, Clone, TS)]
 pub enum GameStatus {
     ScoreChanged(i32, i32),
+    /// How the bot did in each scenario of its test game
+    TestGameFailed(Vec<ScenarioResult>),
+    TestGameSucceeded(Vec<ScenarioResult>),
+}
+
+/// Only derives the deserializer of the current `GameStatus`
+#[allow(dead_code)]
+#[derive(Deserialize)]
+#[serde(remote = "GameStatus")]
+enum GameStatusDef {
+    ScoreChanged(i32, i32),
+    TestGameFailed(Vec<ScenarioResult>),
+    TestGameSucceeded(Vec<ScenarioResult>),
+}
+
+/// A game status as it is read from the queue. Test game results used to be
+/// sent without their scenarios, and any still queued are read with none.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum QueuedGameStatus {
+    Current(#[serde(with = "GameStatusDef")] GameStatus),
+    WithoutScenarios(TestGameStatus),
+}
+
+#[derive(Deserialize)]
+enum TestGameStatus {
     TestGameFailed,
     TestGameSucceeded,
 }
 
+impl<'de> Deserialize<'de> for GameStatus {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match QueuedGameStatus::deserialize(deserializer)? {
+            QueuedGameStatus::Current(status) => status,
+            QueuedGameStatus::WithoutScenarios(TestGameStatus::TestGameFailed) => {
+                GameStatus::TestGameFailed(vec![])
+            }
+            QueuedGameStatus::WithoutScenarios(TestGameStatus::TestGameSucceeded) => {
+                GameStatus::TestGameSucceeded(vec![])
+            }
+        })
+    }
+}
+
+/// One game of a bot's test game against a reference opponent
+#[derive(Serialize, Deserialize, Debug, Clone, TS)]
+#[cfg_attr(feature = "ts-bindings", ts(export))]
+pub struct ScenarioResult {
+    pub name: String,
+    /// Why the scenario failed, or None if it passed
+    pub error: Option<GameError>,
+}
+
+impl ScenarioResult {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct GameStatusMessage {
     pub result: Result<GameStatus, GameError>,
     pub id: String,
 }
 
+/// How the engine exchanges messages with a bot
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default, TS)]
+#[serde(rename_all = "lowercase")]
+pub enum BotTransport {
+    /// Messages are written to the bot's stdin and actions read from its stdout
+    #[default]
+    Stdio,
+    /// The bot connects to the unix socket at `$ENGINE_SOCKET`, and its stdout is logged
+    Socket,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, TS)]
 pub struct BotJson {
     pub name: String,
     pub description: Option<String>,
+    /// A preset that supplies the build and run commands when they are left out
+    #[serde(default, alias = "template")]
+    pub language: Option<presets::BotLanguage>,
     pub build: Option<String>,
-    pub run: String,
+    pub run: Option<String>,
+    /// The bot understands `NEWMATCH`, so its process can be kept
+    /// alive between matches
+    #[serde(default)]
+    pub warm_start: bool,
+    /// The bot understands `CONFIG`, so it is told the rules of each game
+    /// before the first hand
+    #[serde(default)]
+    pub game_config: bool,
+    #[serde(default)]
+    pub transport: BotTransport,
 }
 
+impl BotJson {
+    /// The script the builder runs, if the bot needs building
+    pub fn build_script(&self) -> Option<String> {
+        match self.language {
+            Some(language) => Some(
+                language.build_script(self.build.as_deref().unwrap_or(language.default_build())),
+            ),
+            None => self.build.clone(),
+        }
+    }
+
+    /// The command the engine runs the bot with
+    pub fn run_command(&self) -> Option<String> {
+        self.run
+            .clone()
+            .or_else(|| self.language.map(|l| l.default_run().to_string()))
+    }
+}
+
+#[cfg(feature = "aws")]
 pub async fn aws_config() -> SdkConfig {
     aws_config::from_env().load().await
 }
 
+#[cfg(feature = "aws")]
 pub async fn sqs_client(conf: &aws_config::SdkConfig) -> aws_sdk_sqs::Client {
     let mut sqs_config_builder = aws_sdk_sqs::config::Builder::from(conf);
     match std::env::var("SQS_ADDRESS") {
@@ -194,6 +493,7 @@ pub async fn sqs_client(conf: &aws_config::SdkConfig) -> aws_sdk_sqs::Client {
     aws_sdk_sqs::Client::new(conf)
 }
 
+#[cfg(feature = "aws")]
 pub async fn s3_client(conf: &aws_config::SdkConfig) -> aws_sdk_s3::Client {
     let mut s3_config_builder = aws_sdk_s3::config::Builder::from(conf);
     match std::env::var("S3_ADDRESS") {
@@ -215,3 +515,84 @@ pub async fn s3_client(conf: &aws_config::SdkConfig) -> aws_sdk_s3::Client {
     }
     aws_sdk_s3::Client::new(conf)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_game_results_without_scenarios_are_read() {
+        let message: GameStatusMessage =
+            serde_json::from_str(r#"{"result":{"Ok":"TestGameFailed"},"id":"3"}"#).unwrap();
+        assert!(
+            matches!(message.result, Ok(GameStatus::TestGameFailed(scenarios)) if scenarios.is_empty())
+        );
+        let status = GameStatus::TestGameSucceeded(vec![ScenarioResult {
+            name: "fold".to_string(),
+            error: None,
+        }]);
+        let status: GameStatus =
+            serde_json::from_str(&serde_json::to_string(&status).unwrap()).unwrap();
+        assert!(matches!(status, GameStatus::TestGameSucceeded(scenarios) if scenarios.len() == 1));
+        let status: GameStatus = serde_json::from_str(r#"{"ScoreChanged":[5,-5]}"#).unwrap();
+        assert!(matches!(status, GameStatus::ScoreChanged(5, -5)));
+    }
+
+    #[test]
+    fn games_are_queued_as_before_they_were_boxed() {
+        let request = r#"{"url":"https://example.com","headers":[]}"#;
+        let message = format!(
+            r#"{{"Game":{{"defender":1,"challenger":2,"id":"ab","game_record_presigned":{0},"public_logs_presigned":{0},"defender_logs_presigned":{0},"challenger_logs_presigned":{0}}}}}"#,
+            request
+        );
+        let task: GameTask = serde_json::from_str(&message).unwrap();
+        let GameTask::Game(game) = &task else {
+            panic!("Expected a game, got {:?}", task);
+        };
+        assert_eq!(game.id, "ab");
+        assert!(matches!(game.challenger, Participant::Bot(2)));
+        let sent: serde_json::Value = serde_json::to_value(&task).unwrap();
+        assert_eq!(sent["Game"]["id"], "ab");
+    }
+
+    #[test]
+    fn build_results_without_a_timestamp_are_read() {
+        let message: BuildResultMessage =
+            serde_json::from_str(r#"{"status":"Building","bot":3,"error":null}"#).unwrap();
+        assert_eq!(message.bot, 3);
+        assert_eq!(message.timestamp, None);
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        assert_eq!(GameConfig::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn unplayable_configs_are_rejected() {
+        let default = GameConfig::default();
+        let cases = [
+            (GameConfig { hands: 0, ..default }, InvalidConfig::NoHands),
+            (GameConfig { stack: 0, ..default }, InvalidConfig::NoStack),
+            (
+                GameConfig { small_blind: 2, big_blind: 2, ..default },
+                InvalidConfig::BlindsOutOfOrder,
+            ),
+            (
+                GameConfig { small_blind: 3, big_blind: 2, ..default },
+                InvalidConfig::BlindsOutOfOrder,
+            ),
+            (
+                GameConfig { stack: 1, ..default },
+                InvalidConfig::StackBelowBigBlind,
+            ),
+            (
+                GameConfig { limit: 1, ..default },
+                InvalidConfig::LimitBelowBigBlind,
+            ),
+        ];
+        for (config, error) in cases {
+            assert_eq!(config.validate(), Err(error), "{:?}", config);
+        }
+    }
+}
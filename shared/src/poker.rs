@@ -1,2 +1,3 @@
 pub mod game;
 pub mod hands;
+pub mod protocol;
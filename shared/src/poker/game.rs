@@ -137,8 +137,18 @@ pub struct GameState {
 }
 
 impl GameState {
-    /// The sb is always player 0
+    /// The sb is always player 0. The blinds are 1 and 2.
     pub fn new<T: Into<[u32; 2]>>(stacks: T, deck: Vec<Card>, limit: u32) -> GameState {
+        GameState::with_blinds(stacks, deck, limit, [1, 2])
+    }
+
+    /// Like [GameState::new], with the small and the big blind given
+    pub fn with_blinds<T: Into<[u32; 2]>>(
+        stacks: T,
+        deck: Vec<Card>,
+        limit: u32,
+        blinds: [u32; 2],
+    ) -> GameState {
         let stacks: [u32; 2] = stacks.into();
         if stacks[0] == 0 || stacks[1] == 0 {
             panic!("Stacks must be greater than 0");
@@ -163,14 +173,14 @@ impl GameState {
             community_cards: CommunityCards(vec![]),
             round: Round::PreFlop,
             last_aggressor: PlayerPosition::BigBlind,
-            target_push: 2,
+            target_push: blinds[1],
             player_states,
             end_reason: None,
             limit,
         };
         // Pay little and big blinds
-        out.player_states[0].pushed = 1;
-        out.player_states[1].pushed = min(2, stacks[1]);
+        out.player_states[0].pushed = min(blinds[0], stacks[0]);
+        out.player_states[1].pushed = min(blinds[1], stacks[1]);
 
         out.target_push = min(out.player_states[1].pushed, min(stacks[0], stacks[1]));
 
@@ -948,4 +958,26 @@ mod tests {
         assert_eq!(state.round, Round::Flop);
 
     }
+
+    #[test]
+    pub fn custom_blinds() {
+        let deck = cards_from("Jc9h7s7hQh7dQc6h4c")
+            .into_iter()
+            .rev()
+            .collect_vec();
+        let state = GameState::with_blinds([500, 500], deck.clone(), 100, [5, 10]);
+        assert_eq!(state.player_states[0].pushed, 5);
+        assert_eq!(state.player_states[1].pushed, 10);
+        assert_eq!(state.target_push, 10);
+        // The sb calls and the bb checks
+        let state = state.post_action(Action::Raise(0)).unwrap();
+        assert_eq!(state.player_states[0].pushed, 10);
+        let state = state.post_action(Action::Raise(0)).unwrap();
+        assert_eq!(state.round, Round::Flop);
+
+        // A short big blind only posts what it has
+        let state = GameState::with_blinds([500, 4], deck, 100, [5, 10]);
+        assert_eq!(state.player_states[1].pushed, 4);
+        assert_eq!(state.target_push, 4);
+    }
 }
@@ -1,7 +1,7 @@
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use ts_rs::TS;
-use std::{cmp::Ordering, fmt::Display};
+use std::{cmp::Ordering, fmt::Display, str::FromStr};
 
 #[derive(PartialEq, Eq, Clone, Debug, Copy, Serialize, Deserialize, TS)]
 #[cfg_attr(feature="db", derive(diesel::AsExpression))]
@@ -57,6 +57,35 @@ impl Display for Card {
     }
 }
 
+/// Parses cards the way they are displayed, e.g. `As`, `Td` or `7c`
+impl FromStr for Card {
+    type Err = crate::GameActionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        let value = match chars.next() {
+            Some('A') => 1,
+            Some(c @ '2'..='9') => c.to_digit(10).unwrap(),
+            Some('T') => 10,
+            Some('J') => 11,
+            Some('Q') => 12,
+            Some('K') => 13,
+            _ => return Err(crate::GameActionError::CouldNotParse),
+        };
+        let suite = match chars.next() {
+            Some('c') => Suite::Clubs,
+            Some('s') => Suite::Spades,
+            Some('h') => Suite::Hearts,
+            Some('d') => Suite::Diamonds,
+            _ => return Err(crate::GameActionError::CouldNotParse),
+        };
+        if chars.next().is_some() {
+            return Err(crate::GameActionError::CouldNotParse);
+        }
+        Ok(Card { value, suite })
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Hand {
     pub cards: [Card; 5],
@@ -82,78 +111,86 @@ impl Ord for Hand {
 }
 
 pub mod hand_eval {
-    use std::{cmp::Ordering, ops::Deref};
+    use std::cmp::Ordering;
 
     use super::*;
 
     #[derive(Debug)]
     pub struct HandValue(pub (u8, Vec<u8>, Vec<u8>));
 
-    pub fn hand_value(hand: &[Card; 5]) -> HandValue {
-        let mut hist = hand
-            .iter()
-            .counts_by(|c| if c.value == 1 { 14 } else { c.value })
-            .iter()
-            .map(|(k, v)| (u8::try_from(*v).unwrap(), u8::try_from(*k).unwrap()))
-            .sorted()
-            .rev()
-            .collect_vec();
+    /// What [HandValue] compares: the category, how many cards there are of
+    /// each rank and the ranks, most common first. The arrays are padded with
+    /// zeros, which orders the same as the shorter vectors would.
+    type Rank = (u8, [u8; 5], [u8; 5]);
+
+    fn rank(hand: &[Card; 5]) -> Rank {
+        let mut hist = [0u8; 15];
+        for card in hand {
+            hist[if card.value == 1 { 14 } else { card.value as usize }] += 1;
+        }
+        let mut counts = [0u8; 5];
+        let mut values = [0u8; 5];
+        let mut len = 0;
+        // Most common first, then highest first
+        for count in (1..=4).rev() {
+            for value in (2..=14).rev() {
+                if hist[value] == count {
+                    counts[len] = count;
+                    values[len] = value as u8;
+                    len += 1;
+                }
+            }
+        }
         // check low straight
-        if hist == vec![(1, 14), (1, 5), (1, 4), (1, 3), (1, 2)] {
-            hist = vec![(1, 5), (1, 4), (1, 3), (1, 2), (1, 1)];
+        if len == 5 && values == [14, 5, 4, 3, 2] {
+            values = [5, 4, 3, 2, 1];
         }
+        let category = if len < 5 {
+            (counts[0] + counts[1] == 5) as u8 * 4
+        } else {
+            (hand.iter().all(|card| card.suite == hand[0].suite)) as u8 * 3
+                + (values[0] == values[4] + 4) as u8 * 2
+        };
+        (category, counts, values)
+    }
 
-        HandValue((
-            if hist.len() < 5 {
-                (hist[0].0 + hist[1].0 == 5) as u8 * 4
-            } else {
-                (hand.map(|c| c.suite).iter().all_equal()) as u8 * 3
-                    + (hist[0].1 == hist[4].1 + 4) as u8 * 2
-            },
-            hist.iter().map(|(k, _)| *k).collect(),
-            hist.iter().map(|(_, v)| *v).collect(),
-        ))
+    pub fn hand_value(hand: &[Card; 5]) -> HandValue {
+        let (category, counts, values) = rank(hand);
+        let len = counts.iter().take_while(|count| **count > 0).count();
+        HandValue((category, counts[..len].to_vec(), values[..len].to_vec()))
     }
 
     pub fn compare_hands(hand1: &[Card; 5], hand2: &[Card; 5]) -> Ordering {
-        hand_value(hand1).0.cmp(&hand_value(hand2).0)
+        rank(hand1).cmp(&rank(hand2))
     }
 
-    pub fn best5(hand: &Vec<Card>) -> Hand {
+    /// The best five cards out of five or more
+    pub fn best5(hand: &[Card]) -> Hand {
         if hand.len() < 5 {
             panic!("Not enough cards");
         }
-        let h = hand
-            .iter()
-            .combinations(5)
-            .max_by(|a, b| {
-                let a = [*a[0], *a[1], *a[2], *a[3], *a[4]];
-                let b = [*b[0], *b[1], *b[2], *b[3], *b[4]];
-                compare_hands(&a, &b)
-            })
-            .unwrap();
-        Hand {
-            cards: [*h[0], *h[1], *h[2], *h[3], *h[4]],
+        let n = hand.len();
+        let mut indices = [0, 1, 2, 3, 4];
+        let first = indices.map(|i| hand[i]);
+        let mut best = (first, rank(&first));
+        // Step through the combinations in lexicographic order
+        while let Some(i) = (0..5).rev().find(|&i| indices[i] < n - 5 + i) {
+            indices[i] += 1;
+            for j in i + 1..5 {
+                indices[j] = indices[j - 1] + 1;
+            }
+            let cards = indices.map(|i| hand[i]);
+            let rank = rank(&cards);
+            // Later combinations win ties, as with `Iterator::max_by`
+            if rank >= best.1 {
+                best = (cards, rank);
+            }
         }
+        Hand { cards: best.0 }
     }
     impl Card {
         pub(crate) fn from(code: &str) -> Card {
-            let value = match code.chars().nth(0).unwrap() {
-                'A' => 1,
-                'T' => 10,
-                'J' => 11,
-                'Q' => 12,
-                'K' => 13,
-                _ => code.chars().nth(0).unwrap().to_digit(10).unwrap() as u32,
-            };
-            let suite = match code.chars().nth(1).unwrap() {
-                'c' => Suite::Clubs,
-                's' => Suite::Spades,
-                'h' => Suite::Hearts,
-                'd' => Suite::Diamonds,
-                _ => panic!("Invalid suite"),
-            };
-            Self { value, suite }
+            code.parse().expect("Invalid card")
         }
     }
 
@@ -296,5 +333,15 @@ pub mod hand_eval {
                 }
             }
         }
+
+        #[test]
+        pub fn parse_cards() {
+            for card in crate::poker::game::GameState::get_deck() {
+                assert_eq!(card.to_string().parse::<Card>().unwrap(), card);
+            }
+            assert!("1s".parse::<Card>().is_err());
+            assert!("Ax".parse::<Card>().is_err());
+            assert!("Asd".parse::<Card>().is_err());
+        }
     }
 }
@@ -1,14 +1,20 @@
+//! The text protocol between the engine and bots, described in `docs/src/bot_dev.md`
+
 use std::fmt::Display;
 
-use shared::WhichBot;
+use crate::{GameConfig, WhichBot};
 
-use shared::poker::game::HoleCards;
-use shared::poker::{
+use crate::poker::game::HoleCards;
+use crate::poker::{
     game::{EndReason, GameState, PlayerPosition, PlayerState},
     hands::Card,
 };
 
 pub enum EngineCommunication {
+    /// Sent to a warm bot before it is reused for another match
+    NewMatch,
+    /// Sent before the first hand of a match to bots that opt in to it
+    Config(GameConfig),
     StartGame,
     BettingState {
         sb_pushed: u32,
@@ -54,6 +60,16 @@ impl EngineCommunication {
 
     pub fn render_for_bot(&self, position: PlayerPosition) -> String {
         match self {
+            EngineCommunication::NewMatch => "NEWMATCH".to_string(),
+            EngineCommunication::Config(config) => format!(
+                "CONFIG {} {} {} {} {} {}",
+                config.hands,
+                config.stack,
+                config.small_blind,
+                config.big_blind,
+                config.limit,
+                config.clock_ms
+            ),
             EngineCommunication::StartGame => {
                 format!("START {}", position)
             }
@@ -127,26 +143,53 @@ impl EngineCommunication {
 
 pub fn parse_action<T: AsRef<str>>(
     line: T,
-) -> Result<shared::poker::game::Action, shared::GameActionError> {
+) -> Result<crate::poker::game::Action, crate::GameActionError> {
     let line = line.as_ref();
     Ok(match line.as_ref() {
-        "F" => shared::poker::game::Action::Fold,
-        "C" => shared::poker::game::Action::Raise(0),
+        "F" => crate::poker::game::Action::Fold,
+        "C" => crate::poker::game::Action::Raise(0),
         _ => {
             if line.chars().nth(0) != Some('R') {
-                Err(shared::GameActionError::CouldNotParse)?;
+                Err(crate::GameActionError::CouldNotParse)?;
             }
             let amount = line[1..]
                 .parse::<u32>()
-                .map_err(|_| shared::GameActionError::CouldNotParse)?;
-            shared::poker::game::Action::Raise(amount)
+                .map_err(|_| crate::GameActionError::CouldNotParse)?;
+            crate::poker::game::Action::Raise(amount)
         }
     })
 }
 
+/// The line a bot sends for an action, the inverse of [parse_action]
+pub fn render_action(action: &crate::poker::game::Action) -> String {
+    match action {
+        crate::poker::game::Action::Fold => "F".to_string(),
+        crate::poker::game::Action::Raise(0) => "C".to_string(),
+        crate::poker::game::Action::Raise(amount) => format!("R{}", amount),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::parse_action;
+    use super::{parse_action, render_action, EngineCommunication};
+    use crate::{poker::game::PlayerPosition, GameConfig};
+
+    #[test]
+    fn config_is_rendered_in_field_order() {
+        let config = GameConfig {
+            hands: 200,
+            stack: 400,
+            small_blind: 5,
+            big_blind: 10,
+            limit: 100,
+            clock_ms: 2000,
+        };
+        assert_eq!(
+            EngineCommunication::Config(config).render_for_bot(PlayerPosition::SmallBlind),
+            "CONFIG 200 400 5 10 100 2000"
+        );
+    }
+
     #[test]
     fn parse_action_check() {
         assert!(parse_action(&"X".to_owned()).is_err());
@@ -156,7 +199,7 @@ mod tests {
     fn parse_action_fold() {
         assert_eq!(
             parse_action(&"F".to_owned()).unwrap(),
-            shared::poker::game::Action::Fold
+            crate::poker::game::Action::Fold
         );
     }
 
@@ -164,7 +207,7 @@ mod tests {
     fn parse_action_call() {
         assert_eq!(
             parse_action(&"C".to_owned()).unwrap(),
-            shared::poker::game::Action::Raise(0)
+            crate::poker::game::Action::Raise(0)
         );
     }
 
@@ -172,7 +215,7 @@ mod tests {
     fn parse_action_raise() {
         assert_eq!(
             parse_action(&"R1234".to_owned()).unwrap(),
-            shared::poker::game::Action::Raise(1234)
+            crate::poker::game::Action::Raise(1234)
         );
     }
 
@@ -205,4 +248,15 @@ mod tests {
     fn parse_action_raise_invalid6() {
         assert!(parse_action(&"B".to_owned()).is_err());
     }
+
+    #[test]
+    fn render_action_round_trips() {
+        for action in [
+            crate::poker::game::Action::Fold,
+            crate::poker::game::Action::Raise(0),
+            crate::poker::game::Action::Raise(25),
+        ] {
+            assert_eq!(parse_action(render_action(&action)).unwrap(), action);
+        }
+    }
 }
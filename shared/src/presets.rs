@@ -0,0 +1,160 @@
+//! Vetted build and run commands for common languages, so that a bot.json
+//! only has to name its language.
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+/// Where the builder's package mirror is mounted inside the build sandbox
+pub const MIRROR_PATH: &str = "/mirror";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, TS)]
+#[serde(rename_all = "lowercase")]
+pub enum BotLanguage {
+    /// Runs `bot.py`, with the packages from `requirements.txt`
+    Python,
+    /// Builds the `bot` binary of the crate in `Cargo.toml`
+    Rust,
+    /// Compiles every `.cpp` file into `bot`
+    Cpp,
+    /// Compiles every `.java` file and runs the class `Main`, with the jars in `libs/`
+    Java,
+    /// Builds the module in `go.mod` into `bot`
+    Go,
+}
+
+impl BotLanguage {
+    fn name(&self) -> &'static str {
+        match self {
+            BotLanguage::Python => "python",
+            BotLanguage::Rust => "rust",
+            BotLanguage::Cpp => "cpp",
+            BotLanguage::Java => "java",
+            BotLanguage::Go => "go",
+        }
+    }
+
+    /// Programs that must be installed for the preset to build and run
+    pub fn prerequisites(&self) -> &'static [&'static str] {
+        match self {
+            BotLanguage::Python => &["python3"],
+            BotLanguage::Rust => &["cargo"],
+            BotLanguage::Cpp => &["g++"],
+            BotLanguage::Java => &["javac", "java"],
+            BotLanguage::Go => &["go"],
+        }
+    }
+
+    /// Installs the bot's dependencies from the mirror, if it declares any
+    fn install_dependencies(&self) -> String {
+        match self {
+            BotLanguage::Python => format!(
+                "if [ -f requirements.txt ]; then\n\
+                 {}\
+                 {}\
+                 pip3 install --no-index --find-links {mirror}/python --target .deps -r requirements.txt\n\
+                 fi\n",
+                require_program(self, "pip3"),
+                require_mirror(self, "python", "requirements.txt"),
+                mirror = MIRROR_PATH,
+            ),
+            BotLanguage::Rust => format!(
+                "export CARGO_HOME=/tmp/cargo\n\
+                 if grep -qE '^\\[(.*-)?dependencies' Cargo.toml; then\n\
+                 {}\
+                 mkdir -p .cargo\n\
+                 printf '[source.crates-io]\\nreplace-with = \"mirror\"\\n\\n[source.mirror]\\ndirectory = \"{mirror}/cargo\"\\n' > .cargo/config.toml\n\
+                 fi\n",
+                require_mirror(self, "cargo", "Cargo.toml"),
+                mirror = MIRROR_PATH,
+            ),
+            BotLanguage::Go => format!(
+                "export GOPATH=/tmp/go GOCACHE=/tmp/go-cache GOSUMDB=off GOFLAGS=-mod=mod\n\
+                 if grep -q '^require' go.mod 2>/dev/null; then\n\
+                 {}\
+                 export GOPROXY=file://{mirror}/go\n\
+                 else\n\
+                 export GOPROXY=off\n\
+                 fi\n",
+                require_mirror(self, "go", "go.mod"),
+                mirror = MIRROR_PATH,
+            ),
+            BotLanguage::Cpp | BotLanguage::Java => String::new(),
+        }
+    }
+
+    pub fn default_build(&self) -> &'static str {
+        match self {
+            BotLanguage::Python => "python3 -m compileall -q .",
+            BotLanguage::Rust => "cargo build --release --offline --bin bot && cp target/release/bot bot",
+            BotLanguage::Cpp => "g++ -std=c++17 -O2 -o bot $(find . -name '*.cpp')",
+            BotLanguage::Java => "mkdir -p classes && javac -d classes -cp 'libs/*' $(find . -name '*.java')",
+            BotLanguage::Go => "go build -o bot .",
+        }
+    }
+
+    pub fn default_run(&self) -> &'static str {
+        match self {
+            BotLanguage::Python => "PYTHONPATH=.deps exec python3 bot.py",
+            BotLanguage::Rust | BotLanguage::Cpp | BotLanguage::Go => "./bot",
+            BotLanguage::Java => "exec java -cp 'classes:libs/*' Main",
+        }
+    }
+
+    /// The whole build script for the preset: checks that its prerequisites
+    /// are installed, installs dependencies, then runs `build`
+    pub fn build_script(&self, build: &str) -> String {
+        let mut script = String::from("set -e\n");
+        for program in self.prerequisites() {
+            script.push_str(&require_program(self, program));
+        }
+        script.push_str(&self.install_dependencies());
+        script.push_str(build);
+        script.push('\n');
+        script
+    }
+}
+
+/// Fail the build with a clear message if a program isn't installed
+fn require_program(language: &BotLanguage, program: &str) -> String {
+    format!(
+        "if ! command -v {program} >/dev/null 2>&1; then\n\
+         echo \"error: the {language} preset needs {program}, which is not installed on the builder\"\n\
+         exit 1\n\
+         fi\n",
+        language = language.name(),
+    )
+}
+
+/// Fail the build with a clear message if a part of the mirror is missing
+fn require_mirror(language: &BotLanguage, directory: &str, manifest: &str) -> String {
+    format!(
+        "if [ ! -d {mirror}/{directory} ]; then\n\
+         echo \"error: the {language} preset installs the dependencies in {manifest} from {mirror}/{directory}, which is missing on the builder\"\n\
+         exit 1\n\
+         fi\n",
+        mirror = MIRROR_PATH,
+        language = language.name(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_prerequisites_before_building() {
+        let script = BotLanguage::Java.build_script(BotLanguage::Java.default_build());
+        let javac = script.find("command -v javac").unwrap();
+        let java = script.find("command -v java ").unwrap();
+        let build = script.find("javac -d classes").unwrap();
+        assert!(javac < build && java < build);
+        assert!(script.starts_with("set -e\n"));
+    }
+
+    #[test]
+    fn parses_lowercase_names() {
+        let language: BotLanguage = serde_json::from_str("\"cpp\"").unwrap();
+        assert_eq!(language, BotLanguage::Cpp);
+        assert!(serde_json::from_str::<BotLanguage>("\"c++\"").is_err());
+    }
+}
@@ -0,0 +1,346 @@
+use diesel::prelude::*;
+
+use crate::{
+    db::models::{Bot, Team},
+    GameError,
+};
+
+// chosen arbitrarily. 32 is the standard for chess,
+// but games are much faster here so it should be smaller
+const K_FACTOR: f32 = 12.0;
+
+/// Converts between ratings and the Glicko-2 scale
+const GLICKO2_SCALE: f64 = 173.7178;
+/// Constrains how fast the volatility changes. Glickman suggests 0.3 to 1.2.
+const TAU: f64 = 0.5;
+/// The deviation of a team that hasn't played yet
+pub const MAX_DEVIATION: f32 = 350.0;
+/// Key of the advisory lock taken by [lock_ratings]
+const RATINGS_LOCK: i64 = 0x7261_7469_6e67;
+
+/// Which rating system to use, from `RATING_SYSTEM` (`glicko2` or `elo`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RatingSystem {
+    Elo,
+    Glicko2,
+}
+
+impl RatingSystem {
+    pub fn from_env() -> Self {
+        std::env::var("RATING_SYSTEM")
+            .ok()
+            .and_then(|name| Self::from_name(&name))
+            .unwrap_or(RatingSystem::Glicko2)
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "elo" => Some(RatingSystem::Elo),
+            "glicko2" => Some(RatingSystem::Glicko2),
+            _ => None,
+        }
+    }
+
+    /// The new ratings of both teams after a game. `score` is the defender's,
+    /// between 0 and 1.
+    pub fn rate(&self, defender: Rating, challenger: Rating, score: f32) -> (Rating, Rating) {
+        self.rate_with(&RatingParams::default(), defender, challenger, score)
+    }
+
+    /// [RatingSystem::rate] with other constants than the ones used for live games
+    pub fn rate_with(
+        &self,
+        params: &RatingParams,
+        defender: Rating,
+        challenger: Rating,
+        score: f32,
+    ) -> (Rating, Rating) {
+        match self {
+            RatingSystem::Elo => {
+                let (defender_change, challenger_change) = get_rating_change(
+                    params.k_factor,
+                    defender.rating,
+                    score,
+                    challenger.rating,
+                    1.0 - score,
+                );
+                (
+                    Rating {
+                        rating: defender.rating + defender_change,
+                        ..defender
+                    },
+                    Rating {
+                        rating: challenger.rating + challenger_change,
+                        ..challenger
+                    },
+                )
+            }
+            RatingSystem::Glicko2 => (
+                glicko2_update(defender, &[(challenger, score)], params.tau),
+                glicko2_update(challenger, &[(defender, 1.0 - score)], params.tau),
+            ),
+        }
+    }
+}
+
+/// The constants of the rating systems
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RatingParams {
+    /// How far an Elo rating can move in one game
+    pub k_factor: f32,
+    /// Constrains how fast the Glicko-2 volatility changes
+    pub tau: f64,
+}
+
+impl Default for RatingParams {
+    fn default() -> Self {
+        RatingParams {
+            k_factor: K_FACTOR,
+            tau: TAU,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rating {
+    pub rating: f32,
+    pub deviation: f32,
+    pub volatility: f32,
+}
+
+/// The rating of a team or bot that hasn't played yet
+impl Default for Rating {
+    fn default() -> Self {
+        Rating {
+            rating: 1000.0,
+            deviation: MAX_DEVIATION,
+            volatility: 0.06,
+        }
+    }
+}
+
+impl From<&Team> for Rating {
+    fn from(team: &Team) -> Self {
+        Rating {
+            rating: team.rating,
+            deviation: team.rating_deviation,
+            volatility: team.rating_volatility,
+        }
+    }
+}
+
+impl From<&Bot> for Rating {
+    fn from(bot: &Bot) -> Self {
+        Rating {
+            rating: bot.rating,
+            deviation: bot.rating_deviation,
+            volatility: bot.rating_volatility,
+        }
+    }
+}
+
+/// elo rating system
+pub fn get_rating_change(
+    k_factor: f32,
+    defender_rating: f32,
+    defender_score: f32,
+    challenger_rating: f32,
+    challenger_score: f32,
+) -> (f32, f32) {
+    let defender_expected =
+        1.0 / (1.0 + 10.0f32.powf((challenger_rating - defender_rating) / 400.0));
+    let challenger_expected =
+        1.0 / (1.0 + 10.0f32.powf((defender_rating - challenger_rating) / 400.0));
+    let defender_change = k_factor * (defender_score - defender_expected);
+    let challenger_change = k_factor * (challenger_score - challenger_expected);
+
+    log::info!(
+        "defender_rating: {}, defender_score: {}, challenger_rating: {}, challenger_score: {}, defender_change: {}, challenger_change: {}, defender expected: {}, challenger expected: {}",
+        defender_rating,
+        defender_score,
+        challenger_rating,
+        challenger_score,
+        defender_change,
+        challenger_change,
+        defender_expected,
+        challenger_expected
+    );
+
+    (defender_change, challenger_change)
+}
+
+/// The defender's score used for rating, between 0 and 1. The luck-adjusted
+/// score is used instead of the chips won if `RATING_SCORE` is `adjusted`,
+/// except for games that ended in an error, which are forfeits.
+pub fn rated_score(
+    defender_score: i32,
+    adjusted_score: Option<f32>,
+    error_type: Option<&GameError>,
+    stack: i32,
+) -> f32 {
+    let rated_score = match adjusted_score {
+        Some(adjusted)
+            if error_type.is_none()
+                && std::env::var("RATING_SCORE").as_deref() == Ok("adjusted") =>
+        {
+            adjusted
+        }
+        _ => defender_score as f32,
+    };
+    normalized_score(rated_score, stack)
+}
+
+/// A score in chips, from losing the whole stack to winning it, as a score
+/// between 0 and 1
+pub fn normalized_score(score: f32, stack: i32) -> f32 {
+    ((stack as f32 + score) / (2.0f32 * stack as f32)).clamp(0.0, 1.0)
+}
+
+/// The start of a Glicko-2 rating period: the rating gets less certain for
+/// every team, whether it plays in the period or not.
+pub fn start_rating_period(rating: Rating) -> Rating {
+    let phi = rating.deviation as f64 / GLICKO2_SCALE;
+    let sigma = rating.volatility as f64;
+    Rating {
+        deviation: (((phi * phi + sigma * sigma).sqrt() * GLICKO2_SCALE) as f32).min(MAX_DEVIATION),
+        ..rating
+    }
+}
+
+/// [start_rating_period] as SQL over the `rating_deviation` and
+/// `rating_volatility` columns, so a whole table can be updated at once
+pub fn start_rating_period_sql() -> String {
+    format!(
+        "LEAST({}, SQRT(rating_deviation * rating_deviation + POWER(rating_volatility * {}, 2)))::REAL",
+        MAX_DEVIATION, GLICKO2_SCALE
+    )
+}
+
+/// Wait for anything else that rates games, voids them or replaces ratings,
+/// and hold them off until the current transaction ends. Take it before
+/// touching any rows, so that they are always locked in the same order.
+pub fn lock_ratings(conn: &mut PgConnection) -> Result<(), diesel::result::Error> {
+    diesel::sql_query("SELECT pg_advisory_xact_lock($1)")
+        .bind::<diesel::sql_types::BigInt, _>(RATINGS_LOCK)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Glicko-2 (<http://www.glicko.net/glicko/glicko2.pdf>) for `games` against
+/// opponents with the given scores. Games are rated as soon as they finish,
+/// so the deviation grows in [start_rating_period] instead of here.
+pub fn glicko2_update(player: Rating, games: &[(Rating, f32)], tau: f64) -> Rating {
+    if games.is_empty() {
+        return player;
+    }
+    let mu = player.rating as f64 / GLICKO2_SCALE;
+    let phi = player.deviation as f64 / GLICKO2_SCALE;
+    let sigma = player.volatility as f64;
+    let g = |phi: f64| 1.0 / (1.0 + 3.0 * phi * phi / std::f64::consts::PI.powi(2)).sqrt();
+
+    // the estimated variance from the games, and the estimated improvement
+    let mut inverse_variance = 0.0;
+    let mut improvement = 0.0;
+    for (opponent, score) in games {
+        let opponent_mu = opponent.rating as f64 / GLICKO2_SCALE;
+        let opponent_g = g(opponent.deviation as f64 / GLICKO2_SCALE);
+        let expected = 1.0 / (1.0 + (-opponent_g * (mu - opponent_mu)).exp());
+        inverse_variance += opponent_g * opponent_g * expected * (1.0 - expected);
+        improvement += opponent_g * (*score as f64 - expected);
+    }
+    let v = 1.0 / inverse_variance;
+    let delta = v * improvement;
+
+    // the new volatility, with the Illinois algorithm
+    let a = (sigma * sigma).ln();
+    let f = |x: f64| {
+        let ex = x.exp();
+        ex * (delta * delta - phi * phi - v - ex) / (2.0 * (phi * phi + v + ex).powi(2))
+            - (x - a) / (tau * tau)
+    };
+    let mut low = a;
+    let mut high = if delta * delta > phi * phi + v {
+        (delta * delta - phi * phi - v).ln()
+    } else {
+        let mut k = 1.0;
+        while f(a - k * tau) < 0.0 {
+            k += 1.0;
+        }
+        a - k * tau
+    };
+    let (mut f_low, mut f_high) = (f(low), f(high));
+    while (high - low).abs() > 0.000001 {
+        let c = low + (low - high) * f_low / (f_high - f_low);
+        let f_c = f(c);
+        if f_c * f_high <= 0.0 {
+            (low, f_low) = (high, f_high);
+        } else {
+            f_low /= 2.0;
+        }
+        (high, f_high) = (c, f_c);
+    }
+    let new_sigma = (low / 2.0).exp();
+
+    let new_phi = 1.0 / (1.0 / (phi * phi) + 1.0 / v).sqrt();
+    let new_mu = mu + new_phi * new_phi * improvement;
+    Rating {
+        rating: (new_mu * GLICKO2_SCALE) as f32,
+        deviation: (new_phi * GLICKO2_SCALE) as f32,
+        volatility: new_sigma as f32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rating(rating: f32, deviation: f32) -> Rating {
+        Rating {
+            rating,
+            deviation,
+            volatility: 0.06,
+        }
+    }
+
+    /// The example from Glickman's paper
+    #[test]
+    fn glicko2_example() {
+        let player = start_rating_period(rating(1500.0, 200.0));
+        let updated = glicko2_update(
+            player,
+            &[
+                (rating(1400.0, 30.0), 1.0),
+                (rating(1550.0, 100.0), 0.0),
+                (rating(1700.0, 300.0), 0.0),
+            ],
+            TAU,
+        );
+        assert!((updated.rating - 1464.06).abs() < 0.5, "{:?}", updated);
+        assert!((updated.deviation - 151.52).abs() < 0.5, "{:?}", updated);
+        assert!(
+            (updated.volatility - 0.05999).abs() < 0.0001,
+            "{:?}",
+            updated
+        );
+    }
+
+    #[test]
+    fn uncertain_ratings_move_faster() {
+        let opponent = rating(1000.0, 50.0);
+        let new = glicko2_update(rating(1000.0, 350.0), &[(opponent, 1.0)], TAU);
+        let settled = glicko2_update(rating(1000.0, 50.0), &[(opponent, 1.0)], TAU);
+        assert!(new.rating - 1000.0 > 5.0 * (settled.rating - 1000.0));
+        assert!(new.deviation < 350.0);
+        assert_eq!(
+            start_rating_period(rating(1000.0, 349.9)).deviation,
+            MAX_DEVIATION
+        );
+    }
+
+    #[test]
+    fn forfeits_are_rated_by_their_chips() {
+        let timeout = GameError::TimeoutError(crate::WhichBot::Defender);
+        assert_eq!(rated_score(-500, Some(120.0), Some(&timeout), 500), 0.0);
+        assert_eq!(rated_score(500, None, None, 500), 1.0);
+    }
+}
@@ -0,0 +1,660 @@
+//! Tournaments, for running finals between a fixed set of bots. In a round
+//! robin every entrant plays every other once, and in a Swiss tournament
+//! entrants with similar scores play each other for a fixed number of rounds.
+//! An admin advances a tournament once all of a round's games have finished.
+//! Tournament games are unrated, so they don't affect the ladder.
+
+use std::{collections::HashMap, fmt};
+
+use diesel::prelude::*;
+use crate::{
+    db::{
+        conn::{begin_transaction, end_transaction},
+        dao::{
+            games::{GameQueue, GamesDao},
+            tournaments::TournamentsDao,
+        },
+        models::{
+            GameResult, NewTournament, NewTournamentPairing, Team, Tournament, TournamentEntrant,
+            TournamentPairing,
+        },
+        schema,
+    },
+    GameConfig, GameError, TournamentFormat,
+};
+
+/// How many times the Swiss pairing search may backtrack before it allows rematches
+const SWISS_SEARCH_STEPS: usize = 100_000;
+/// Seconds after which a game without a result is taken to be lost, e.g.
+/// because a worker died while playing it, and is played again
+pub const LOST_GAME_TIMEOUT: i64 = 60 * 60;
+
+#[derive(Debug)]
+pub enum TournamentError {
+    NotFound,
+    Finished,
+    /// Teams need an active bot to enter
+    NoActiveBot(i32),
+    TooFewEntrants,
+    /// The number of games in the current round that haven't finished
+    RoundRunning(usize),
+    Database(diesel::result::Error),
+}
+
+impl fmt::Display for TournamentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TournamentError::NotFound => write!(f, "Tournament not found"),
+            TournamentError::Finished => write!(f, "The tournament has finished"),
+            TournamentError::NoActiveBot(team) => {
+                write!(f, "Team {} doesn't exist or has no active bot", team)
+            }
+            TournamentError::TooFewEntrants => write!(f, "A tournament needs at least two teams"),
+            TournamentError::RoundRunning(games) => {
+                write!(f, "{} games of this round haven't finished", games)
+            }
+            TournamentError::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for TournamentError {}
+
+impl From<diesel::result::Error> for TournamentError {
+    fn from(e: diesel::result::Error) -> Self {
+        TournamentError::Database(e)
+    }
+}
+
+/// Every entrant plays every other once in a round robin, and a Swiss
+/// tournament needs about log2(entrants) rounds to find a winner
+pub fn default_rounds(format: TournamentFormat, entrants: usize) -> i32 {
+    match format {
+        TournamentFormat::RoundRobin => (entrants + entrants % 2).saturating_sub(1) as i32,
+        TournamentFormat::Swiss => (entrants.max(2) as f64).log2().ceil() as i32,
+    }
+}
+
+/// Create a tournament between `teams`, or every team with an active bot.
+/// Each team plays with the bot that is active now, and is seeded by rating.
+pub fn create_tournament(
+    conn: &mut PgConnection,
+    name: String,
+    format: TournamentFormat,
+    rounds: Option<i32>,
+    teams: Option<Vec<i32>>,
+) -> Result<Tournament, TournamentError> {
+    use schema::teams::dsl;
+    let mut query = dsl::teams
+        .filter(dsl::deleted_at.is_null())
+        .filter(dsl::active_bot.is_not_null())
+        .into_boxed();
+    if let Some(teams) = &teams {
+        query = query.filter(dsl::id.eq_any(teams));
+    }
+    let entrants = query.order_by(dsl::rating.desc()).load::<Team>(conn)?;
+    if let Some(teams) = &teams {
+        if let Some(missing) = teams
+            .iter()
+            .find(|team| !entrants.iter().any(|entrant| entrant.id == **team))
+        {
+            return Err(TournamentError::NoActiveBot(*missing));
+        }
+    }
+    if entrants.len() < 2 {
+        return Err(TournamentError::TooFewEntrants);
+    }
+
+    let most_rounds = default_rounds(TournamentFormat::RoundRobin, entrants.len());
+    let rounds = match format {
+        TournamentFormat::RoundRobin => most_rounds,
+        // More rounds than a round robin would mean rematches
+        TournamentFormat::Swiss => rounds
+            .unwrap_or(default_rounds(format, entrants.len()))
+            .clamp(1, most_rounds),
+    };
+    Ok(conn.create_tournament(
+        NewTournament {
+            name,
+            format,
+            rounds,
+        },
+        entrants
+            .iter()
+            .enumerate()
+            .map(|(i, team)| TournamentEntrant {
+                tournament: 0,
+                team: team.id,
+                bot: team.active_bot.unwrap(),
+                seed: i as i32 + 1,
+                points: 0.0,
+                buchholz: 0.0,
+                sonneborn_berger: 0.0,
+                chips: 0,
+            })
+            .collect(),
+    )?)
+}
+
+/// The outcome of a pairing
+#[derive(Debug, Clone, PartialEq)]
+pub struct PairingResult {
+    pub defender: i32,
+    pub challenger: Option<i32>,
+    /// 1 for a win, 0.5 for a draw and 0 for a loss
+    pub defender_points: f32,
+    /// The chips the defender won from the challenger
+    pub chips: i64,
+}
+
+/// The outcome of a pairing, or None if its game hasn't finished or wasn't
+/// played because of an internal error. A bye counts as a win.
+pub fn pairing_result(
+    pairing: &TournamentPairing,
+    result: Option<&GameResult>,
+) -> Option<PairingResult> {
+    let (defender_points, chips) = match (pairing.challenger, result) {
+        (None, _) => (1.0, 0),
+        (Some(_), None) => return None,
+        (Some(_), Some(result)) if matches!(result.error_type, Some(GameError::InternalError)) => {
+            return None
+        }
+        (Some(_), Some(result)) => (
+            match result.defender_score.cmp(&result.challenger_score) {
+                std::cmp::Ordering::Greater => 1.0,
+                std::cmp::Ordering::Equal => 0.5,
+                std::cmp::Ordering::Less => 0.0,
+            },
+            (result.defender_score - result.challenger_score) as i64 / 2,
+        ),
+    };
+    Some(PairingResult {
+        defender: pairing.defender,
+        challenger: pairing.challenger,
+        defender_points,
+        chips,
+    })
+}
+
+/// The entrants' standings after `results`, best first. Ties on points are
+/// broken by the Buchholz score, then the Sonneborn-Berger score, then chips,
+/// and finally by seed.
+pub fn standings(
+    entrants: &[TournamentEntrant],
+    results: &[PairingResult],
+) -> Vec<TournamentEntrant> {
+    let mut points = HashMap::<i32, f32>::new();
+    let mut chips = HashMap::<i32, i64>::new();
+    for result in results {
+        *points.entry(result.defender).or_default() += result.defender_points;
+        if let Some(challenger) = result.challenger {
+            *points.entry(challenger).or_default() += 1.0 - result.defender_points;
+            *chips.entry(result.defender).or_default() += result.chips;
+            *chips.entry(challenger).or_default() -= result.chips;
+        }
+    }
+    let points_of = |team: i32| points.get(&team).copied().unwrap_or(0.0);
+    let mut buchholz = HashMap::<i32, f32>::new();
+    let mut sonneborn_berger = HashMap::<i32, f32>::new();
+    for result in results {
+        let Some(challenger) = result.challenger else {
+            continue;
+        };
+        *buchholz.entry(result.defender).or_default() += points_of(challenger);
+        *buchholz.entry(challenger).or_default() += points_of(result.defender);
+        *sonneborn_berger.entry(result.defender).or_default() +=
+            result.defender_points * points_of(challenger);
+        *sonneborn_berger.entry(challenger).or_default() +=
+            (1.0 - result.defender_points) * points_of(result.defender);
+    }
+
+    let mut standings = entrants
+        .iter()
+        .map(|entrant| TournamentEntrant {
+            points: points_of(entrant.team),
+            buchholz: buchholz.get(&entrant.team).copied().unwrap_or(0.0),
+            sonneborn_berger: sonneborn_berger.get(&entrant.team).copied().unwrap_or(0.0),
+            chips: chips.get(&entrant.team).copied().unwrap_or(0),
+            ..entrant.clone()
+        })
+        .collect::<Vec<_>>();
+    standings.sort_by(|a, b| {
+        b.points
+            .total_cmp(&a.points)
+            .then(b.buchholz.total_cmp(&a.buchholz))
+            .then(b.sonneborn_berger.total_cmp(&a.sonneborn_berger))
+            .then(b.chips.cmp(&a.chips))
+            .then(a.seed.cmp(&b.seed))
+    });
+    standings
+}
+
+/// The pairings of a round (starting from 1) of a round robin between `teams`,
+/// as (defender, challenger). With an odd number of teams, one of them has a
+/// bye every round.
+pub fn round_robin_pairings(teams: &[i32], round: i32) -> Vec<(i32, Option<i32>)> {
+    // The circle method: the first team stays put and the rest rotate around it
+    let mut slots = teams.iter().copied().map(Some).collect::<Vec<_>>();
+    if slots.len() % 2 == 1 {
+        slots.push(None);
+    }
+    let n = slots.len();
+    if n < 2 {
+        return vec![];
+    }
+    slots[1..].rotate_right((round - 1).max(0) as usize % (n - 1));
+    (0..n / 2)
+        .filter_map(|i| {
+            let (mut a, mut b) = (slots[i], slots[n - 1 - i]);
+            // Take turns defending
+            if (i + round as usize) % 2 == 1 {
+                (a, b) = (b, a);
+            }
+            match (a, b) {
+                (Some(a), b) => Some((a, b)),
+                (None, Some(b)) => Some((b, None)),
+                (None, None) => None,
+            }
+        })
+        .collect()
+}
+
+/// The pairings of the next round of a Swiss tournament, from the standings
+/// (best first) and the pairings so far. Each team plays the best ranked team
+/// it hasn't played yet, as long as everyone else can still be paired without
+/// a rematch. With an odd number of teams, the lowest ranked team that hasn't
+/// had a bye gets one.
+pub fn swiss_pairings(ranked: &[i32], previous: &[(i32, Option<i32>)]) -> Vec<(i32, Option<i32>)> {
+    let played = |a: i32, b: i32| {
+        previous
+            .iter()
+            .any(|pairing| *pairing == (a, Some(b)) || *pairing == (b, Some(a)))
+    };
+    let defended = |team: i32| {
+        previous
+            .iter()
+            .filter(|(defender, challenger)| *defender == team && challenger.is_some())
+            .count()
+    };
+
+    let mut teams = ranked.to_vec();
+    let mut bye = None;
+    if teams.len() % 2 == 1 {
+        let had_bye = |team: i32| previous.contains(&(team, None));
+        let i = teams
+            .iter()
+            .rposition(|team| !had_bye(*team))
+            .unwrap_or(teams.len() - 1);
+        bye = Some(teams.remove(i));
+    }
+    let mut steps = SWISS_SEARCH_STEPS;
+    let pairs = pair_without_rematches(&teams, &played, &mut steps)
+        .unwrap_or_else(|| teams.chunks(2).map(|pair| (pair[0], pair[1])).collect());
+
+    let mut pairings = pairs
+        .into_iter()
+        .map(|(a, b)| {
+            // Whoever has defended less defends
+            if defended(a) <= defended(b) {
+                (a, Some(b))
+            } else {
+                (b, Some(a))
+            }
+        })
+        .collect::<Vec<_>>();
+    pairings.extend(bye.map(|team| (team, None)));
+    pairings
+}
+
+/// Pair up `teams` in order without rematches, by backtracking
+fn pair_without_rematches(
+    teams: &[i32],
+    played: &impl Fn(i32, i32) -> bool,
+    steps: &mut usize,
+) -> Option<Vec<(i32, i32)>> {
+    let Some((&first, rest)) = teams.split_first() else {
+        return Some(vec![]);
+    };
+    for (i, &opponent) in rest.iter().enumerate() {
+        if *steps == 0 {
+            return None;
+        }
+        *steps -= 1;
+        if played(first, opponent) {
+            continue;
+        }
+        let mut remaining = rest.to_vec();
+        remaining.remove(i);
+        if let Some(mut pairs) = pair_without_rematches(&remaining, played, steps) {
+            pairs.insert(0, (first, opponent));
+            return Some(pairs);
+        }
+    }
+    None
+}
+
+/// Whether a pairing needs a new game: one was never queued, its game failed
+/// with an internal error, or its game was created (at `created`) more than
+/// [LOST_GAME_TIMEOUT] before `now` and still has no result. Byes never do.
+pub fn needs_game(
+    pairing: &TournamentPairing,
+    result: Option<&GameResult>,
+    created: Option<i64>,
+    now: i64,
+) -> bool {
+    if pairing.challenger.is_none() {
+        return false;
+    }
+    match (&pairing.game, result) {
+        (None, _) => true,
+        (Some(_), Some(result)) => matches!(result.error_type, Some(GameError::InternalError)),
+        (Some(_), None) => match created {
+            Some(created) => now - created > LOST_GAME_TIMEOUT,
+            // The game was deleted, so it will never finish
+            None => true,
+        },
+    }
+}
+
+/// Start a tournament, or finish its current round: update the standings and
+/// either pair the next round and queue its games, or finish the tournament
+/// after the last round. Games that couldn't be queued, failed with an
+/// internal error or were lost are queued again first, see [needs_game].
+pub async fn advance_tournament(
+    conn: &mut PgConnection,
+    id: i32,
+    queue: &GameQueue<'_>,
+) -> Result<Tournament, TournamentError> {
+    // The tournament stays locked while games are queued, so two admins
+    // advancing it at once can't both pair the next round
+    begin_transaction(conn)?;
+    let advanced = advance_locked(conn, id, queue).await;
+    end_transaction(conn, &advanced)?;
+    advanced
+}
+
+async fn advance_locked(
+    conn: &mut PgConnection,
+    id: i32,
+    queue: &GameQueue<'_>,
+) -> Result<Tournament, TournamentError> {
+    let tournament = schema::tournaments::table
+        .find(id)
+        .for_update()
+        .first::<Tournament>(conn)
+        .optional()?
+        .ok_or(TournamentError::NotFound)?;
+    if tournament.finished.is_some() {
+        return Err(TournamentError::Finished);
+    }
+    let entrants = conn.get_standings(id)?;
+    let pairings = conn.get_pairings(id)?;
+    let current = pairings
+        .iter()
+        .filter(|(pairing, _)| pairing.round == tournament.current_round)
+        .collect::<Vec<_>>();
+
+    let created = schema::games::table
+        .filter(
+            schema::games::dsl::id
+                .eq_any(current.iter().filter_map(|(pairing, _)| pairing.game.clone())),
+        )
+        .select((schema::games::dsl::id, schema::games::dsl::created))
+        .load::<(String, i64)>(conn)?
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    let now = chrono::Utc::now().timestamp();
+    let unplayed = current
+        .iter()
+        .filter(|(pairing, result)| {
+            let created = pairing.game.as_ref().and_then(|game| created.get(game)).copied();
+            needs_game(pairing, result.as_ref(), created, now)
+        })
+        .map(|(pairing, _)| pairing.clone())
+        .collect::<Vec<_>>();
+    if !unplayed.is_empty() {
+        queue_games(conn, &entrants, &unplayed, queue).await?;
+        return Ok(tournament);
+    }
+    let running = current
+        .iter()
+        .filter(|(pairing, result)| pairing_result(pairing, result.as_ref()).is_none())
+        .count();
+    if running > 0 {
+        return Err(TournamentError::RoundRunning(running));
+    }
+
+    let results = pairings
+        .iter()
+        .filter_map(|(pairing, result)| pairing_result(pairing, result.as_ref()))
+        .collect::<Vec<_>>();
+    let standings = standings(&entrants, &results);
+    conn.update_standings(&standings)?;
+    if tournament.current_round >= tournament.rounds {
+        return Ok(conn.set_current_round(
+            id,
+            tournament.current_round,
+            Some(now),
+        )?);
+    }
+
+    let round = tournament.current_round + 1;
+    let next = match tournament.format {
+        TournamentFormat::RoundRobin => {
+            let mut seeded = entrants.clone();
+            seeded.sort_by_key(|entrant| entrant.seed);
+            round_robin_pairings(
+                &seeded
+                    .iter()
+                    .map(|entrant| entrant.team)
+                    .collect::<Vec<_>>(),
+                round,
+            )
+        }
+        TournamentFormat::Swiss => swiss_pairings(
+            &standings
+                .iter()
+                .map(|entrant| entrant.team)
+                .collect::<Vec<_>>(),
+            &results
+                .iter()
+                .map(|result| (result.defender, result.challenger))
+                .collect::<Vec<_>>(),
+        ),
+    };
+    let next = conn.add_pairings(
+        &next
+            .into_iter()
+            .map(|(defender, challenger)| NewTournamentPairing {
+                tournament: id,
+                round,
+                defender,
+                challenger,
+            })
+            .collect::<Vec<_>>(),
+    )?;
+    let tournament = conn.set_current_round(id, round, None)?;
+    queue_games(conn, &entrants, &next, queue).await?;
+    Ok(tournament)
+}
+
+/// Queue an unrated game for each pairing, between the bots the teams entered with
+async fn queue_games(
+    conn: &mut PgConnection,
+    entrants: &[TournamentEntrant],
+    pairings: &[TournamentPairing],
+    queue: &GameQueue<'_>,
+) -> Result<(), TournamentError> {
+    let teams = schema::teams::table
+        .filter(schema::teams::dsl::id.eq_any(entrants.iter().map(|entrant| entrant.team)))
+        .load::<Team>(conn)?;
+    let entrant = |id: i32| {
+        let team = teams.iter().find(|team| team.id == id)?;
+        let bot = entrants.iter().find(|entrant| entrant.team == id)?.bot;
+        Some((team, bot))
+    };
+    for pairing in pairings {
+        let (Some(defender), Some(challenger)) = (
+            entrant(pairing.defender),
+            pairing.challenger.and_then(entrant),
+        ) else {
+            continue;
+        };
+        match conn
+            .create_game_with_bots(defender, challenger, false, GameConfig::default(), queue)
+            .await
+        {
+            Ok(game) => conn.set_pairing_game(pairing.id, &game)?,
+            Err(e) => log::error!(
+                "Failed to create the game for tournament pairing {}: {}",
+                pairing.id,
+                e
+            ),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_robin_plays_everyone_once() {
+        for teams in [4, 5] {
+            let ids = (1..=teams).collect::<Vec<_>>();
+            let rounds = default_rounds(TournamentFormat::RoundRobin, ids.len());
+            let mut games = vec![];
+            for round in 1..=rounds {
+                let pairings = round_robin_pairings(&ids, round);
+                let mut playing = pairings
+                    .iter()
+                    .flat_map(|(defender, challenger)| [Some(*defender), *challenger])
+                    .flatten()
+                    .collect::<Vec<_>>();
+                playing.sort();
+                assert_eq!(playing, ids);
+                games.extend(
+                    pairings
+                        .into_iter()
+                        .filter_map(|(a, b)| Some((a.min(b?), a.max(b?)))),
+                );
+            }
+            games.sort();
+            games.dedup();
+            assert_eq!(games.len(), ids.len() * (ids.len() - 1) / 2);
+        }
+    }
+
+    #[test]
+    fn swiss_avoids_rematches_and_repeated_byes() {
+        // 1 beat 2, 3 beat 4 and 5 had a bye. 5 and 1 are ahead, but 5 can't
+        // have another bye.
+        let previous = [(1, Some(2)), (3, Some(4)), (5, None)];
+        let pairings = swiss_pairings(&[1, 5, 3, 2, 4], &previous);
+        assert_eq!(pairings.len(), 3);
+        assert!(pairings.contains(&(4, None)));
+        for (defender, challenger) in &pairings {
+            if let Some(challenger) = challenger {
+                assert!(!previous.contains(&(*defender, Some(*challenger))));
+                assert!(!previous.contains(&(*challenger, Some(*defender))));
+            }
+        }
+        // 1 plays 5, the best ranked team it hasn't played
+        assert!(pairings.contains(&(5, Some(1))));
+    }
+
+    #[test]
+    fn internal_errors_and_lost_games_are_played_again() {
+        let pairing = |challenger: Option<i32>, game: Option<&str>| TournamentPairing {
+            id: 1,
+            tournament: 1,
+            round: 1,
+            defender: 1,
+            challenger,
+            game: game.map(str::to_string),
+        };
+        let result = |error_type: Option<GameError>| GameResult {
+            id: "game".to_string(),
+            challenger_rating_change: 0.0,
+            defender_rating_change: 0.0,
+            defender_score: 0,
+            challenger_score: 0,
+            error_type,
+            updated_at: 0,
+            defender_rating: 1000.0,
+            challenger_rating: 1000.0,
+            adjusted_score: None,
+            voided: false,
+            voided_by: None,
+            void_reason: None,
+            voided_at: None,
+            defender_deviation_before: None,
+            defender_volatility_before: None,
+            challenger_deviation_before: None,
+            challenger_volatility_before: None,
+            defender_bot_rating_change: None,
+            challenger_bot_rating_change: None,
+            defender_bot_deviation_before: None,
+            defender_bot_volatility_before: None,
+            challenger_bot_deviation_before: None,
+            challenger_bot_volatility_before: None,
+            finish_order: 0,
+        };
+        let now = 10 * LOST_GAME_TIMEOUT;
+        let recent = Some(now - LOST_GAME_TIMEOUT / 2);
+        let old = Some(now - LOST_GAME_TIMEOUT - 1);
+        let played = pairing(Some(2), Some("game"));
+
+        assert!(!needs_game(&pairing(None, None), None, None, now));
+        assert!(needs_game(&pairing(Some(2), None), None, None, now));
+        // Still being played, or lost
+        assert!(!needs_game(&played, None, recent, now));
+        assert!(needs_game(&played, None, old, now));
+        // Finished, or not played at all
+        let internal = result(Some(GameError::InternalError));
+        let crashed = result(Some(GameError::RunTimeError(crate::WhichBot::Challenger)));
+        assert!(needs_game(&played, Some(&internal), old, now));
+        assert_eq!(pairing_result(&played, Some(&internal)), None);
+        assert!(!needs_game(&played, Some(&crashed), old, now));
+        assert!(pairing_result(&played, Some(&crashed)).is_some());
+        assert!(!needs_game(&played, Some(&result(None)), recent, now));
+    }
+
+    #[test]
+    fn standings_break_ties() {
+        let entrant = |team: i32| TournamentEntrant {
+            tournament: 1,
+            team,
+            bot: team,
+            seed: team,
+            points: 0.0,
+            buchholz: 0.0,
+            sonneborn_berger: 0.0,
+            chips: 0,
+        };
+        let result =
+            |defender: i32, challenger: i32, defender_points: f32, chips: i64| PairingResult {
+                defender,
+                challenger: Some(challenger),
+                defender_points,
+                chips,
+            };
+        // 1, 2 and 3 all win once. 2 and 3 played better opponents than 1,
+        // and 2 beat one of them.
+        let results = [
+            result(1, 4, 1.0, 100),
+            result(2, 3, 1.0, 10),
+            result(3, 4, 1.0, 10),
+        ];
+        let standings = standings(&[entrant(1), entrant(2), entrant(3), entrant(4)], &results);
+        let order = standings
+            .iter()
+            .map(|entrant| entrant.team)
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec![2, 3, 1, 4]);
+        assert_eq!(standings[0].buchholz, 1.0);
+        assert_eq!(standings[0].sonneborn_berger, 1.0);
+        assert_eq!(standings[2].chips, 100);
+    }
+}
@@ -0,0 +1,352 @@
+//! Voiding games whose results can't be trusted, for example because a worker
+//! bug or an internal error scored them wrongly.
+
+use std::collections::HashMap;
+
+use diesel::prelude::*;
+use uuid::Uuid;
+
+use crate::{
+    db::{
+        dao::admin::{change_team_rating, AdminDao},
+        models::{Bot, GameResult, Team},
+        schema::{bots, game_results, games, rating_history, teams},
+        schema_aliases::{challenger_bots, defender_bots},
+    },
+    rating::{lock_ratings, rated_score, Rating, RatingSystem},
+    GameConfig, GameError,
+};
+
+/// Mark a game voided by `admin` and reverse its rating changes. With
+/// `recompute`, every rated game finished after it is rated again as if it had
+/// never been played. Games can't finish in the meantime, see [lock_ratings].
+pub fn void_game(
+    conn: &mut PgConnection,
+    id: &str,
+    admin: Uuid,
+    reason: &str,
+    recompute: bool,
+) -> Result<GameResult, diesel::result::Error> {
+    conn.transaction(|conn| {
+        lock_ratings(conn)?;
+        let result = conn.void_game(id, admin, reason)?;
+        if recompute {
+            let rerated = recompute_ratings_after(conn, &result)?;
+            log::info!("Rated {} games again after voiding {}", rerated, id);
+        }
+        Ok(result)
+    })
+}
+
+/// Rate every rated game that finished after `voided` again, in order, for
+/// teams and bots. Ratings start from what they would have been without the
+/// games' recorded changes, and each game is rated with the deviations and
+/// volatilities recorded before it, where they were. Results, rating history,
+/// team and bot ratings are updated to match. Games played stay as they are,
+/// since only the voided game is taken off them. Returns the number of games
+/// rated again.
+pub fn recompute_ratings_after(
+    conn: &mut PgConnection,
+    voided: &GameResult,
+) -> Result<usize, diesel::result::Error> {
+    let later = game_results::table
+        .inner_join(games::table)
+        .inner_join(defender_bots.on(games::defender.eq(defender_bots.field(bots::id))))
+        .inner_join(challenger_bots.on(games::challenger.eq(challenger_bots.field(bots::id))))
+        .filter(games::rated.eq(true))
+        .filter(game_results::voided.eq(false))
+        .filter(game_results::finish_order.gt(voided.finish_order))
+        .order_by(game_results::finish_order.asc())
+        .select((
+            GameResult::as_select(),
+            games::config,
+            defender_bots.field(bots::team),
+            challenger_bots.field(bots::team),
+            games::defender,
+            games::challenger,
+        ))
+        .load::<(GameResult, GameConfig, i32, i32, i32, i32)>(conn)?
+        .into_iter()
+        // Internal errors never changed any ratings
+        .filter(|(result, ..)| !matches!(result.error_type, Some(GameError::InternalError)))
+        .collect::<Vec<_>>();
+
+    let mut current_teams = HashMap::new();
+    let mut current_bots = HashMap::new();
+    for (_, _, defender, challenger, defender_bot, challenger_bot) in &later {
+        for team in [*defender, *challenger] {
+            if !current_teams.contains_key(&team) {
+                let team: Team = teams::table.find(team).first(conn)?;
+                current_teams.insert(team.id, Rating::from(&team));
+            }
+        }
+        for bot in [*defender_bot, *challenger_bot] {
+            if !current_bots.contains_key(&bot) {
+                let bot: Bot = bots::table.find(bot).first(conn)?;
+                current_bots.insert(bot.id, Rating::from(&bot));
+            }
+        }
+    }
+    let mut team_ratings = current_teams.clone();
+    let mut bot_ratings = current_bots.clone();
+    for (result, _, defender, challenger, defender_bot, challenger_bot) in &later {
+        for (team, change) in [
+            (defender, result.defender_rating_change),
+            (challenger, result.challenger_rating_change),
+        ] {
+            team_ratings.get_mut(team).unwrap().rating -= change;
+        }
+        for (bot, change) in [
+            (defender_bot, result.defender_bot_rating_change),
+            (challenger_bot, result.challenger_bot_rating_change),
+        ] {
+            // Unknown for games finished before bot changes were recorded
+            if let Some(change) = change {
+                bot_ratings.get_mut(bot).unwrap().rating -= change;
+            }
+        }
+    }
+
+    let score = |result: &GameResult, config: &GameConfig| {
+        rated_score(
+            result.defender_score,
+            result.adjusted_score,
+            result.error_type.as_ref(),
+            config.stack as i32,
+        )
+    };
+    let team_games = later
+        .iter()
+        .map(|(result, config, defender, challenger, ..)| ReplayedGame {
+            defender: *defender,
+            challenger: *challenger,
+            defender_before: result
+                .defender_deviation_before
+                .zip(result.defender_volatility_before),
+            challenger_before: result
+                .challenger_deviation_before
+                .zip(result.challenger_volatility_before),
+            score: score(result, config),
+        })
+        .collect::<Vec<_>>();
+    let bot_games = later
+        .iter()
+        .map(
+            |(result, config, _, _, defender_bot, challenger_bot)| ReplayedGame {
+                defender: *defender_bot,
+                challenger: *challenger_bot,
+                defender_before: result
+                    .defender_bot_deviation_before
+                    .zip(result.defender_bot_volatility_before),
+                challenger_before: result
+                    .challenger_bot_deviation_before
+                    .zip(result.challenger_bot_volatility_before),
+                score: score(result, config),
+            },
+        )
+        .collect::<Vec<_>>();
+    let system = RatingSystem::from_env();
+    let team_changes = replay(system, &mut team_ratings, &team_games);
+    let bot_changes = replay(system, &mut bot_ratings, &bot_games);
+
+    for (((result, ..), game), (change, bot_change)) in later
+        .iter()
+        .zip(&team_games)
+        .zip(team_changes.iter().zip(&bot_changes))
+    {
+        diesel::update(game_results::table.find(&result.id))
+            .set((
+                game_results::defender_rating_change.eq(change.defender_rating_change),
+                game_results::challenger_rating_change.eq(change.challenger_rating_change),
+                game_results::defender_rating.eq(change.defender_rating),
+                game_results::challenger_rating.eq(change.challenger_rating),
+                game_results::defender_bot_rating_change
+                    .eq(Some(bot_change.defender_rating_change)),
+                game_results::challenger_bot_rating_change
+                    .eq(Some(bot_change.challenger_rating_change)),
+            ))
+            .execute(conn)?;
+        for (team, rating, deviation) in [
+            (
+                game.defender,
+                change.defender_rating,
+                change.defender_deviation,
+            ),
+            (
+                game.challenger,
+                change.challenger_rating,
+                change.challenger_deviation,
+            ),
+        ] {
+            diesel::update(rating_history::table)
+                .filter(rating_history::game.eq(&result.id))
+                .filter(rating_history::team.eq(team))
+                .set((
+                    rating_history::rating.eq(rating),
+                    rating_history::rating_deviation.eq(Some(deviation)),
+                ))
+                .execute(conn)?;
+        }
+    }
+    for (team, rating) in team_ratings {
+        diesel::update(teams::table.find(team))
+            .set((
+                teams::rating_deviation.eq(rating.deviation),
+                teams::rating_volatility.eq(rating.volatility),
+            ))
+            .execute(conn)?;
+        change_team_rating(conn, team, rating.rating - current_teams[&team].rating)?;
+    }
+    for (bot, rating) in bot_ratings {
+        diesel::update(bots::table.find(bot))
+            .set((
+                bots::rating.eq(bots::rating + (rating.rating - current_bots[&bot].rating)),
+                bots::rating_deviation.eq(rating.deviation),
+                bots::rating_volatility.eq(rating.volatility),
+            ))
+            .execute(conn)?;
+    }
+    Ok(later.len())
+}
+
+/// A rated game between two teams or two bots, with the defender's score
+/// between 0 and 1
+#[derive(Debug, Clone, Copy)]
+pub struct ReplayedGame {
+    pub defender: i32,
+    pub challenger: i32,
+    /// The deviations and volatilities the sides had before the game, if
+    /// they were recorded
+    pub defender_before: Option<(f32, f32)>,
+    pub challenger_before: Option<(f32, f32)>,
+    pub score: f32,
+}
+
+/// How a replayed game changed the ratings, and what they were after it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayedChange {
+    pub defender_rating_change: f32,
+    pub challenger_rating_change: f32,
+    pub defender_rating: f32,
+    pub challenger_rating: f32,
+    pub defender_deviation: f32,
+    pub challenger_deviation: f32,
+}
+
+/// Rate `games` in order, starting from `ratings`, which are left as they
+/// are after the last game. Each side is rated with the deviation and
+/// volatility it had before the game when it is known, and with the one it
+/// was left with by the replay otherwise.
+pub fn replay(
+    system: RatingSystem,
+    ratings: &mut HashMap<i32, Rating>,
+    games: &[ReplayedGame],
+) -> Vec<ReplayedChange> {
+    let before = |rating: Rating, before: Option<(f32, f32)>| match before {
+        Some((deviation, volatility)) => Rating {
+            deviation,
+            volatility,
+            ..rating
+        },
+        None => rating,
+    };
+    games
+        .iter()
+        .map(|game| {
+            let defender = before(ratings[&game.defender], game.defender_before);
+            let challenger = before(ratings[&game.challenger], game.challenger_before);
+            let (new_defender, new_challenger) = system.rate(defender, challenger, game.score);
+            ratings.insert(game.defender, new_defender);
+            ratings.insert(game.challenger, new_challenger);
+            ReplayedChange {
+                defender_rating_change: new_defender.rating - defender.rating,
+                challenger_rating_change: new_challenger.rating - challenger.rating,
+                defender_rating: new_defender.rating,
+                challenger_rating: new_challenger.rating,
+                defender_deviation: new_defender.deviation,
+                challenger_deviation: new_challenger.deviation,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rating(rating: f32) -> Rating {
+        Rating {
+            rating,
+            deviation: 100.0,
+            volatility: 0.06,
+        }
+    }
+
+    #[test]
+    fn replay_matches_rating_in_order() {
+        let system = RatingSystem::Elo;
+        let mut ratings = HashMap::from([(1, rating(1000.0)), (2, rating(1100.0))]);
+        let games = [
+            ReplayedGame {
+                defender: 1,
+                challenger: 2,
+                defender_before: None,
+                challenger_before: None,
+                score: 1.0,
+            },
+            ReplayedGame {
+                defender: 2,
+                challenger: 1,
+                defender_before: None,
+                challenger_before: None,
+                score: 0.25,
+            },
+        ];
+        let changes = replay(system, &mut ratings, &games);
+
+        let (first_defender, first_challenger) = system.rate(rating(1000.0), rating(1100.0), 1.0);
+        assert_eq!(changes[0].defender_rating, first_defender.rating);
+        assert_eq!(changes[0].challenger_rating, first_challenger.rating);
+        let (second_defender, second_challenger) =
+            system.rate(first_challenger, first_defender, 0.25);
+        assert_eq!(
+            changes[1].defender_rating_change,
+            second_defender.rating - first_challenger.rating
+        );
+        assert_eq!(ratings[&1].rating, second_challenger.rating);
+        assert_eq!(ratings[&2].rating, second_defender.rating);
+        assert_eq!(ratings[&1].deviation, 100.0);
+    }
+
+    #[test]
+    fn replay_starts_each_game_from_the_recorded_deviations() {
+        let system = RatingSystem::Glicko2;
+        let game = |defender_before| ReplayedGame {
+            defender: 1,
+            challenger: 2,
+            defender_before,
+            challenger_before: None,
+            score: 1.0,
+        };
+        let mut ratings = HashMap::from([(1, rating(1000.0)), (2, rating(1000.0))]);
+        let changes = replay(system, &mut ratings, &[game(Some((300.0, 0.07)))]);
+
+        let (defender, challenger) = system.rate(
+            Rating {
+                deviation: 300.0,
+                volatility: 0.07,
+                ..rating(1000.0)
+            },
+            rating(1000.0),
+            1.0,
+        );
+        assert_eq!(changes[0].defender_rating, defender.rating);
+        assert_eq!(changes[0].defender_deviation, defender.deviation);
+        assert_eq!(ratings[&1], defender);
+        assert_eq!(ratings[&2], challenger);
+        // Without a recorded deviation, the replay carries on from the last game
+        let changes = replay(system, &mut ratings, &[game(None)]);
+        let (defender, _) = system.rate(defender, challenger, 1.0);
+        assert_eq!(changes[0].defender_deviation, defender.deviation);
+    }
+}
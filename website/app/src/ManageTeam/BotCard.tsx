@@ -58,6 +58,22 @@ function BuildStatusChip(props: { status: BuildStatus }) {
       text = "Test game failed";
       color = "danger";
       break;
+    case "BuildTimedOut":
+      text = "Build timed out";
+      color = "danger";
+      break;
+    case "BuildOutOfMemory":
+      text = "Build ran out of memory";
+      color = "danger";
+      break;
+    case "ArchiveTooLarge":
+      text = "Zip file too large";
+      color = "danger";
+      break;
+    case "ArchiveTooManyFiles":
+      text = "Too many files";
+      color = "danger";
+      break;
   }
 
   return (
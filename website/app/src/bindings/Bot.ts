@@ -1,4 +1,5 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { BuildStatus } from "./BuildStatus";
+import type { HouseBot } from "./HouseBot";
 
-export interface Bot { id: number, team: number, name: string, description: string | null, created: bigint, uploaded_by: string, build_status: BuildStatus, deleted_at: bigint | null, }
\ No newline at end of file
+export interface Bot { id: number, team: number, name: string, description: string | null, created: bigint, uploaded_by: string, build_status: BuildStatus, deleted_at: bigint | null, rating: number, rating_deviation: number, rating_volatility: number, games_played: number, house: HouseBot | null, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { BuildStatus } from "./BuildStatus";
+
+export interface BotStatusEvent { id: number, bot: number, status: BuildStatus, error: string | null, created: bigint, }
\ No newline at end of file
@@ -1,3 +1,3 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export type BuildStatus = "Unqueued" | "Queued" | "Building" | "BuildSucceeded" | "PlayingTestGame" | "TestGameSucceeded" | "BuildFailed" | "TestGameFailed";
\ No newline at end of file
+export type BuildStatus = "Unqueued" | "Queued" | "Building" | "BuildSucceeded" | "PlayingTestGame" | "TestGameSucceeded" | "BuildFailed" | "TestGameFailed" | "BuildTimedOut" | "BuildOutOfMemory" | "ArchiveTooLarge" | "ArchiveTooManyFiles";
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface GameConfig { hands: number, stack: number, small_blind: number, big_blind: number, limit: number, clock_ms: number, }
\ No newline at end of file
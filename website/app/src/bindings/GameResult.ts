@@ -1,4 +1,4 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 import type { GameError } from "./GameError";
 
-export interface GameResult { id: string, challenger_rating_change: number, defender_rating_change: number, defender_score: number, challenger_score: number, error_type: GameError | null, updated_at: bigint, defender_rating: number, challenger_rating: number, }
\ No newline at end of file
+export interface GameResult { id: string, challenger_rating_change: number, defender_rating_change: number, defender_score: number, challenger_score: number, error_type: GameError | null, updated_at: bigint, defender_rating: number, challenger_rating: number, adjusted_score: number | null, voided: boolean, voided_by: string | null, void_reason: string | null, voided_at: bigint | null, defender_deviation_before: number | null, defender_volatility_before: number | null, challenger_deviation_before: number | null, challenger_volatility_before: number | null, defender_bot_rating_change: number | null, challenger_bot_rating_change: number | null, defender_bot_deviation_before: number | null, defender_bot_volatility_before: number | null, challenger_bot_deviation_before: number | null, challenger_bot_volatility_before: number | null, finish_order: bigint, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface RatingHistoryEntry { id: number, team: number, bot: number | null, game: string | null, rating: number, rating_deviation: number | null, created: bigint, }
\ No newline at end of file
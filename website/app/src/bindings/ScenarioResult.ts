@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { GameError } from "./GameError";
+
+export interface ScenarioResult { name: string, error: GameError | null, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface Scrimmage { id: number, team: number, bot_a: number, bot_b: number, created: bigint, }
\ No newline at end of file
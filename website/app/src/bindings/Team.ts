@@ -1,3 +1,3 @@
 // This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
 
-export interface Team { id: number, name: string, owner: string, active_bot: number | null, deleted_at: bigint | null, rating: number, }
\ No newline at end of file
+export interface Team { id: number, name: string, owner: string, active_bot: number | null, deleted_at: bigint | null, rating: number, rating_deviation: number, rating_volatility: number, }
\ No newline at end of file
@@ -0,0 +1,4 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { TournamentFormat } from "./TournamentFormat";
+
+export interface Tournament { id: number, name: string, format: TournamentFormat, rounds: number, current_round: number, created: bigint, finished: bigint | null, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface TournamentEntrant { tournament: number, team: number, bot: number, seed: number, points: number, buchholz: number, sonneborn_berger: number, chips: bigint, }
\ No newline at end of file
@@ -0,0 +1,3 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+
+export interface TournamentPairing { id: number, tournament: number, round: number, defender: number, challenger: number | null, game: string | null, }
\ No newline at end of file
@@ -0,0 +1,7 @@
+// This file was generated by [ts-rs](https://github.com/Aleph-Alpha/ts-rs). Do not edit this file manually.
+import type { GameResult } from "./GameResult";
+import type { Tournament } from "./Tournament";
+import type { TournamentEntrant } from "./TournamentEntrant";
+import type { TournamentPairing } from "./TournamentPairing";
+
+export interface TournamentWithStandings { tournament: Tournament, standings: Array<TournamentEntrant>, pairings: Array<[TournamentPairing, GameResult | null]>, }
\ No newline at end of file
@@ -44,7 +44,8 @@ export function TeamsTable() {
   const teamList = teams.map((team, i) => (
     <Box>
       <Typography textColor="#CDC0FF" level="h4" mb={1}>
-        {getNumberWithOrdinal(i + 1)} place, rating {team.rating.toFixed(0)}
+        {getNumberWithOrdinal(i + 1)} place, rating {team.rating.toFixed(0)} ±{" "}
+        {team.rating_deviation.toFixed(0)}
       </Typography>
       <TeamCard variant={i < 3 ? "large" : "small"} team={team} />
     </Box>
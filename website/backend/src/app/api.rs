@@ -28,13 +28,17 @@ use shared::{
     PresignedRequest,
 };
 
+pub mod admin;
 pub mod auth;
 pub mod bots;
 pub mod data;
 pub mod games;
 pub mod oauth;
 pub mod profile;
+pub mod remote;
+pub mod scrimmages;
 pub mod team;
+pub mod tournaments;
 
 pub fn api_service() -> actix_web::Scope {
     actix_web::web::scope("/api")
@@ -52,6 +56,7 @@ pub fn api_service() -> actix_web::Scope {
         .service(bots::build_log)
         .service(bots::delete_bot)
         .service(bots::set_active_bot)
+        .service(bots::bot_status_history)
         .service(profile::my_account)
         .service(profile::my_team)
         .service(profile::my_email)
@@ -67,11 +72,24 @@ pub fn api_service() -> actix_web::Scope {
         .service(data::bots)
         .service(data::code)
         .service(data::pfp)
+        .service(data::rating_history)
+        .service(data::bot_leaderboard)
         .service(games::games)
         .service(games::count_games)
         .service(games::game_log)
         .service(games::game_length)
         .service(games::game_state)
+        .service(games::head_to_head)
+        .service(games::bot_stats)
+        .service(games::challenge)
+        .service(scrimmages::create_scrimmage)
+        .service(scrimmages::get_scrimmage)
+        .service(scrimmages::scrimmages)
+        .service(tournaments::tournaments)
+        .service(tournaments::get_tournament)
+        .service(remote::remote_bot)
+        .service(remote::remote_bot_relay)
+        .service(admin::admin_service().wrap_fn(admin::require_admin))
 }
 
 pub fn auth_service() -> actix_web::Scope {
@@ -0,0 +1,412 @@
+//! Endpoints for admins, under `/api/admin`. Everything that changes
+//! something is written to the audit log.
+
+use actix_session::SessionExt;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse};
+use futures_util::future::{FutureExt, LocalBoxFuture};
+use shared::db::{
+    dao::{admin::AdminDao, bots::BotsDao},
+    models::{AuditLogEntry, GameResult, NewAuditLogEntry, NewBotStatusEvent},
+};
+use uuid::Uuid;
+
+use super::*;
+
+pub fn admin_service() -> actix_web::Scope {
+    actix_web::web::scope("/admin")
+        .service(search_users)
+        .service(remove_member)
+        .service(disband_team)
+        .service(rename_team)
+        .service(disable_bot)
+        .service(void_game)
+        .service(requeue_build)
+        .service(adjust_rating)
+        .service(audit_log)
+        .service(tournaments::create_tournament)
+        .service(tournaments::start_tournament)
+        .service(tournaments::advance_tournament)
+}
+
+/// Middleware for the admin scope that turns away everyone but admins
+pub fn require_admin<S>(
+    req: ServiceRequest,
+    srv: &S,
+) -> LocalBoxFuture<'static, Result<ServiceResponse, actix_web::Error>>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse, Error = actix_web::Error>,
+    S::Future: 'static,
+{
+    if auth::is_admin(&req.get_session()) {
+        srv.call(req).boxed_local()
+    } else {
+        let response = req.error_response(ApiError {
+            status_code: StatusCode::FORBIDDEN,
+            message: "Only admins can do this".to_string(),
+        });
+        async { Ok(response) }.boxed_local()
+    }
+}
+
+fn admin_id(session: &Session) -> Result<Uuid, ApiError> {
+    Ok(session
+        .get::<Uuid>("user")?
+        .ok_or(actix_web::error::ErrorUnauthorized("Not logged in"))?)
+}
+
+/// Write an admin's action to the audit log
+pub(super) fn audit(
+    conn: &mut PgConnection,
+    session: &Session,
+    action: &str,
+    target: impl ToString,
+    details: Option<serde_json::Value>,
+) -> Result<(), ApiError> {
+    conn.record_audit(NewAuditLogEntry {
+        admin: admin_id(session)?,
+        action: action.to_string(),
+        target: target.to_string(),
+        details: details.map(|details| details.to_string()),
+    })?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct PageQuery {
+    pub page: Option<i64>,
+    pub page_size: Option<i64>,
+}
+
+impl PageQuery {
+    fn page(&self) -> (i64, i64) {
+        (
+            self.page.unwrap_or(0).max(0),
+            self.page_size.unwrap_or(50).clamp(1, 500),
+        )
+    }
+}
+
+#[derive(Serialize, TS)]
+#[cfg_attr(feature = "ts-bindings", ts(export))]
+pub struct AdminUser {
+    pub user: User,
+    pub email: String,
+    pub is_admin: bool,
+}
+
+#[derive(Deserialize)]
+pub struct UsersQuery {
+    /// Part of a display name or email
+    pub search: Option<String>,
+    #[serde(flatten)]
+    pub page: PageQuery,
+}
+
+#[get("/users")]
+pub async fn search_users(
+    web::Query::<UsersQuery>(UsersQuery { search, page }): web::Query<UsersQuery>,
+) -> ApiResult<Vec<AdminUser>> {
+    let conn = &mut (*DB_CONNECTION).get()?;
+    let (page, page_size) = page.page();
+    Ok(web::Json(
+        conn.search_users(search.as_deref(), page, page_size)?
+            .into_iter()
+            .map(|(user, email, is_admin)| AdminUser {
+                user,
+                email,
+                is_admin,
+            })
+            .collect(),
+    ))
+}
+
+#[derive(Deserialize)]
+pub struct RemoveMemberQuery {
+    pub user_id: Uuid,
+}
+
+/// Take a user off their team. Owners can't be removed, disband the team instead.
+#[get("/remove-member")]
+pub async fn remove_member(
+    session: Session,
+    web::Query::<RemoveMemberQuery>(RemoveMemberQuery { user_id }): web::Query<RemoveMemberQuery>,
+) -> ApiResult<()> {
+    let conn = &mut (*DB_CONNECTION).get()?;
+    let user: User = users::table
+        .find(user_id)
+        .first::<User>(conn)
+        .optional()?
+        .ok_or(actix_web::error::ErrorNotFound("User not found"))?;
+    let team = user.team.ok_or(actix_web::error::ErrorBadRequest(
+        "The user isn't on a team",
+    ))?;
+    let owner = teams::table
+        .find(team)
+        .select(teams::dsl::owner)
+        .first::<Uuid>(conn)?;
+    if owner == user_id {
+        return Err(actix_web::error::ErrorBadRequest(
+            "The owner can't be removed, disband the team instead",
+        )
+        .into());
+    }
+    conn.transaction(|conn| {
+        diesel::update(users::table.find(user_id))
+            .set(users::dsl::team.eq::<Option<i32>>(None))
+            .execute(conn)?;
+        audit(
+            conn,
+            &session,
+            "remove-member",
+            user_id,
+            Some(serde_json::json!({ "team": team })),
+        )
+    })?;
+    Ok(web::Json(()))
+}
+
+#[derive(Deserialize)]
+pub struct TeamQuery {
+    pub id: i32,
+}
+
+/// Delete a team and take everyone off it
+#[get("/disband-team")]
+pub async fn disband_team(
+    session: Session,
+    web::Query::<TeamQuery>(TeamQuery { id }): web::Query<TeamQuery>,
+) -> ApiResult<()> {
+    let conn = &mut (*DB_CONNECTION).get()?;
+    conn.transaction(|conn| {
+        let disbanded = diesel::update(teams::table.find(id))
+            .filter(teams::dsl::deleted_at.is_null())
+            .set(teams::dsl::deleted_at.eq(Some(chrono::offset::Utc::now().timestamp())))
+            .execute(conn)?;
+        if disbanded == 0 {
+            return Err(actix_web::error::ErrorNotFound("Team not found").into());
+        }
+        let members = diesel::update(users::table)
+            .filter(users::dsl::team.eq(id))
+            .set(users::dsl::team.eq::<Option<i32>>(None))
+            .returning(users::dsl::id)
+            .get_results::<Uuid>(conn)?;
+        audit(
+            conn,
+            &session,
+            "disband-team",
+            id,
+            Some(serde_json::json!({ "members": members })),
+        )
+    })?;
+    Ok(web::Json(()))
+}
+
+#[derive(Deserialize)]
+pub struct RenameTeamQuery {
+    pub id: i32,
+    pub to: String,
+}
+
+#[get("/rename-team")]
+pub async fn rename_team(
+    session: Session,
+    web::Query::<RenameTeamQuery>(RenameTeamQuery { id, to }): web::Query<RenameTeamQuery>,
+) -> ApiResult<()> {
+    team::validate_name(&to, "Invalid team name. It must be at most 20 characters and cannot contain consecutive spaces.")?;
+    let conn = &mut (*DB_CONNECTION).get()?;
+    conn.transaction(|conn| {
+        let from = teams::table
+            .find(id)
+            .select(teams::dsl::name)
+            .first::<String>(conn)
+            .optional()?
+            .ok_or(actix_web::error::ErrorNotFound("Team not found"))?;
+        diesel::update(teams::table.find(id))
+            .set(teams::dsl::name.eq(&to))
+            .execute(conn)?;
+        audit(
+            conn,
+            &session,
+            "rename-team",
+            id,
+            Some(serde_json::json!({ "from": from, "to": to })),
+        )
+    })?;
+    Ok(web::Json(()))
+}
+
+#[derive(Deserialize)]
+pub struct BotQuery {
+    pub id: i32,
+}
+
+/// Stop a bot from playing. It is marked deleted, and is no longer its team's
+/// active bot.
+#[get("/disable-bot")]
+pub async fn disable_bot(
+    session: Session,
+    web::Query::<BotQuery>(BotQuery { id }): web::Query<BotQuery>,
+) -> ApiResult<()> {
+    use schema::bots;
+    let conn = &mut (*DB_CONNECTION).get()?;
+    conn.transaction(|conn| {
+        let disabled = diesel::update(bots::table.find(id))
+            .filter(bots::dsl::deleted_at.is_null())
+            .set(bots::dsl::deleted_at.eq(Some(chrono::offset::Utc::now().timestamp())))
+            .execute(conn)?;
+        if disabled == 0 {
+            return Err(actix_web::error::ErrorNotFound("Bot not found").into());
+        }
+        diesel::update(teams::table)
+            .filter(teams::dsl::active_bot.eq(id))
+            .set(teams::dsl::active_bot.eq::<Option<i32>>(None))
+            .execute(conn)?;
+        audit(conn, &session, "disable-bot", id, None)
+    })?;
+    Ok(web::Json(()))
+}
+
+#[derive(Deserialize)]
+pub struct VoidGameQuery {
+    pub id: String,
+    /// Why the game's result can't be trusted
+    pub reason: String,
+    /// Rate every game finished after this one again
+    pub recompute: Option<bool>,
+}
+
+/// Reverse a finished game's rating changes
+#[get("/void-game")]
+pub async fn void_game(
+    session: Session,
+    web::Query::<VoidGameQuery>(VoidGameQuery {
+        id,
+        reason,
+        recompute,
+    }): web::Query<VoidGameQuery>,
+) -> ApiResult<GameResult> {
+    if reason.trim().is_empty() {
+        return Err(actix_web::error::ErrorBadRequest("Give a reason for voiding the game").into());
+    }
+    let admin = admin_id(&session)?;
+    let recompute = recompute.unwrap_or(false);
+    let conn = &mut (*DB_CONNECTION).get()?;
+    let result = conn.transaction(|conn| {
+        let result = shared::void::void_game(conn, &id, admin, &reason, recompute)
+            .optional()?
+            .ok_or(actix_web::error::ErrorNotFound(
+                "No finished game that hasn't been voided",
+            ))?;
+        audit(
+            conn,
+            &session,
+            "void-game",
+            &id,
+            Some(serde_json::json!({
+                "reason": reason,
+                "recompute": recompute,
+                "defender_rating_change": result.defender_rating_change,
+                "challenger_rating_change": result.challenger_rating_change,
+            })),
+        )?;
+        Ok::<_, ApiError>(result)
+    })?;
+    Ok(web::Json(result))
+}
+
+/// Build a bot again from the code it was uploaded with. Deleted bots can't be
+/// built again, and the build doesn't make the bot its team's active bot.
+#[get("/requeue-build")]
+pub async fn requeue_build(
+    session: Session,
+    web::Query::<BotQuery>(BotQuery { id }): web::Query<BotQuery>,
+    s3_client: web::Data<aws_sdk_s3::Client>,
+    sqs_client: web::Data<aws_sdk_sqs::Client>,
+) -> ApiResult<()> {
+    let conn = &mut (*DB_CONNECTION).get()?;
+    let bot = schema::bots::table
+        .find(id)
+        .first::<Bot>(conn)
+        .optional()?
+        .ok_or(actix_web::error::ErrorNotFound("Bot not found"))?;
+    if bot.deleted_at.is_some() {
+        return Err(actix_web::error::ErrorBadRequest("Bot has been deleted").into());
+    }
+    conn.record_bot_status(NewBotStatusEvent {
+        bot: id,
+        status: shared::BuildStatus::Queued,
+        error: None,
+        created: chrono::offset::Utc::now().timestamp(),
+    })?;
+    bots::queue_build(id, &s3_client, &sqs_client).await?;
+    audit(conn, &session, "requeue-build", id, None)?;
+    Ok(web::Json(()))
+}
+
+#[derive(Deserialize)]
+pub struct AdjustRatingRequest {
+    /// Either a team or a bot
+    pub team: Option<i32>,
+    pub bot: Option<i32>,
+    pub rating: f32,
+}
+
+/// Set the rating of a team or a bot
+#[post("/adjust-rating")]
+pub async fn adjust_rating(
+    session: Session,
+    web::Json(AdjustRatingRequest { team, bot, rating }): web::Json<AdjustRatingRequest>,
+) -> ApiResult<()> {
+    use schema::bots;
+    if !rating.is_finite() {
+        return Err(actix_web::error::ErrorBadRequest("Invalid rating").into());
+    }
+    let conn = &mut (*DB_CONNECTION).get()?;
+    conn.transaction(|conn| match (team, bot) {
+        (Some(team), None) => {
+            let from = teams::table
+                .find(team)
+                .select(teams::dsl::rating)
+                .first::<f32>(conn)
+                .optional()?
+                .ok_or(actix_web::error::ErrorNotFound("Team not found"))?;
+            conn.set_team_rating(team, rating)?;
+            audit(
+                conn,
+                &session,
+                "adjust-team-rating",
+                team,
+                Some(serde_json::json!({ "from": from, "to": rating })),
+            )
+        }
+        (None, Some(bot)) => {
+            let from = bots::table
+                .find(bot)
+                .select(bots::dsl::rating)
+                .first::<f32>(conn)
+                .optional()?
+                .ok_or(actix_web::error::ErrorNotFound("Bot not found"))?;
+            diesel::update(bots::table.find(bot))
+                .set(bots::dsl::rating.eq(rating))
+                .execute(conn)?;
+            audit(
+                conn,
+                &session,
+                "adjust-bot-rating",
+                bot,
+                Some(serde_json::json!({ "from": from, "to": rating })),
+            )
+        }
+        _ => Err(actix_web::error::ErrorBadRequest("Give either a team or a bot").into()),
+    })?;
+    Ok(web::Json(()))
+}
+
+#[get("/audit-log")]
+pub async fn audit_log(
+    web::Query::<PageQuery>(page): web::Query<PageQuery>,
+) -> ApiResult<Vec<AuditLogEntry>> {
+    let conn = &mut (*DB_CONNECTION).get()?;
+    let (page, page_size) = page.page();
+    Ok(web::Json(conn.get_audit_log(page, page_size)?))
+}
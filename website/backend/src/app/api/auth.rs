@@ -341,6 +341,18 @@ pub fn get_user(session: &Session) -> Option<User> {
         .ok()
 }
 
+pub fn is_admin(session: &Session) -> bool {
+    let Some(id) = session.get::<Uuid>("user").ok().flatten() else {
+        return false;
+    };
+    let conn = &mut (*DB_CONNECTION).get().unwrap();
+    auth::dsl::auth
+        .filter(auth::dsl::id.eq(id))
+        .select(auth::dsl::is_admin)
+        .first::<bool>(conn)
+        .unwrap_or(false)
+}
+
 pub fn get_profile(session: &Session) -> Option<UserProfile> {
     let id: Uuid = session.get("user").ok()??;
     let conn = &mut (*DB_CONNECTION).get().unwrap();
@@ -377,6 +389,7 @@ pub fn get_team(session: &Session) -> Option<TeamWithMembers<User>> {
         name: team.name,
         owner: team.owner,
         rating: team.rating,
+        rating_deviation: team.rating_deviation,
         active_bot: team.active_bot,
         members,
         invites,
@@ -1,4 +1,7 @@
-use shared::db::models::NewBot;
+use shared::db::{
+    dao::bots::BotsDao,
+    models::{BotStatusEvent, NewBot, NewBotStatusEvent},
+};
 use std::io::Read;
 
 use crate::config::{bot_s3_bucket, bot_size, build_logs_s3_bucket};
@@ -78,11 +81,19 @@ pub struct UploadBotResponse {
     id: i32,
 }
 
+#[derive(Deserialize)]
+pub struct UploadBotQuery {
+    /// Start the bot from the team's current rating instead of the default
+    #[serde(default)]
+    seed_rating: bool,
+}
+
 #[post("/upload-bot")]
 pub async fn upload_bot(
     s3_client: actix_web::web::Data<aws_sdk_s3::Client>,
     sqs_client: actix_web::web::Data<aws_sdk_sqs::Client>,
     session: Session,
+    web::Query::<UploadBotQuery>(UploadBotQuery { seed_rating }): web::Query<UploadBotQuery>,
     mut payload: web::Payload,
 ) -> ApiResult<UploadBotResponse> {
     use shared::db::schema::{bots, teams};
@@ -114,6 +125,12 @@ pub async fn upload_bot(
     log::debug!("bot.json: {}", bot_json);
 
     let bot: shared::BotJson = serde_json::from_str(&bot_json)?;
+    if bot.run_command().is_none() {
+        return Err(actix_web::error::ErrorBadRequest(
+            "bot.json needs a run command or a language",
+        )
+        .into());
+    }
 
     println!("{:?}", bot);
     // Create a bot entry in the database
@@ -125,9 +142,16 @@ pub async fn upload_bot(
             description: bot.description,
             uploaded_by: user.id,
             build_status: shared::BuildStatus::Queued,
+            rating: seed_rating.then_some(team.rating),
         })
         .returning(bots::dsl::id)
         .get_result::<i32>(conn)?;
+    conn.record_bot_status(NewBotStatusEvent {
+        bot: id,
+        status: shared::BuildStatus::Queued,
+        error: None,
+        created: chrono::offset::Utc::now().timestamp(),
+    })?;
     // upload the file to s3
     if let Err(e) = s3_client
         .put_object()
@@ -144,6 +168,17 @@ pub async fn upload_bot(
         return Err(e.into());
     }
 
+    // TODO: Handle errors by deleting the bot from the database
+    queue_build(id, &s3_client, &sqs_client).await?;
+    Ok(web::Json(UploadBotResponse { id }))
+}
+
+/// Push an uploaded bot to the 'bot_uploads' queue to be built
+pub async fn queue_build(
+    id: i32,
+    s3_client: &aws_sdk_s3::Client,
+    sqs_client: &aws_sdk_sqs::Client,
+) -> Result<(), ApiError> {
     let presign_config =
         PresigningConfig::expires_in(std::time::Duration::from_secs(60 * 60 * 24 * 7))?;
     let log_presigned = s3_client
@@ -156,8 +191,6 @@ pub async fn upload_bot(
         url: log_presigned.uri().to_string(),
         headers: log_presigned.headers().into(),
     };
-    // push the bot to the 'bot_uploads' queue
-    // TODO: Handle errors by deleting the bot from the database
     sqs_client
         .send_message()
         .queue_url(std::env::var("BOT_UPLOADS_QUEUE_URL")?)
@@ -167,17 +200,20 @@ pub async fn upload_bot(
         })?)
         .send()
         .await?;
-    Ok(web::Json(UploadBotResponse { id }))
+    Ok(())
 }
 
 #[derive(Deserialize)]
 pub struct BuildLogQuery {
     bot: i32,
+    /// Get the report of the bot's test game instead of its build log
+    #[serde(default)]
+    test_game: bool,
 }
 #[get("/build-log")]
 pub async fn build_log(
     session: Session,
-    web::Query::<BuildLogQuery>(BuildLogQuery { bot }): web::Query<BuildLogQuery>,
+    web::Query::<BuildLogQuery>(BuildLogQuery { bot, test_game }): web::Query<BuildLogQuery>,
     sqs_client: web::Data<aws_sdk_sqs::Client>,
     s3_client: web::Data<aws_sdk_s3::Client>,
 ) -> Result<HttpResponse, ApiError> {
@@ -194,7 +230,7 @@ pub async fn build_log(
             actix_web::error::ErrorUnauthorized("Only the owner can view a bot's logs.").into(),
         );
     }
-    let key = format!("{}/build", bot);
+    let key = format!("{}/{}", bot, if test_game { "test_game" } else { "build" });
     let presign_config =
         PresigningConfig::expires_in(std::time::Duration::from_secs(60 * 60 * 24 * 7))?;
     let response = s3_client
@@ -205,3 +241,32 @@ pub async fn build_log(
         .await?;
     Ok(HttpResponse::Ok().streaming(response.body))
 }
+
+#[derive(Deserialize)]
+pub struct BotStatusHistoryQuery {
+    bot: i32,
+}
+
+/// Every status a team's bot has been in since it was uploaded, oldest first
+#[get("/bot-status-history")]
+pub async fn bot_status_history(
+    session: Session,
+    web::Query::<BotStatusHistoryQuery>(BotStatusHistoryQuery { bot }): web::Query<
+        BotStatusHistoryQuery,
+    >,
+) -> ApiResult<Vec<BotStatusEvent>> {
+    let team =
+        auth::get_team(&session).ok_or(actix_web::error::ErrorUnauthorized("Not on a team"))?;
+    let conn = &mut (*DB_CONNECTION).get()?;
+    let bots: Vec<Bot> = schema::bots::dsl::bots
+        .filter(schema::bots::dsl::id.eq(bot))
+        .filter(schema::bots::dsl::team.eq(team.id))
+        .load::<Bot>(conn)?;
+    if bots.len() == 0 {
+        return Err(actix_web::error::ErrorUnauthorized(
+            "Only the owner can view a bot's status history.",
+        )
+        .into());
+    }
+    Ok(web::Json(conn.get_bot_status_history(bot)?))
+}
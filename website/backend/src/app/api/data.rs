@@ -1,5 +1,8 @@
 use super::*;
-use shared::db::models::{BotWithTeam, Team, TeamWithMembers, User, UserProfile};
+use shared::db::dao::ratings::RatingsDao;
+use shared::db::models::{
+    BotWithTeam, RatingHistoryEntry, Team, TeamWithMembers, User, UserProfile,
+};
 
 #[derive(Deserialize)]
 pub enum TeamsQuerySort {
@@ -126,6 +129,7 @@ pub async fn teams(
                     id: t.id,
                     owner: t.owner,
                     rating: t.rating,
+                    rating_deviation: t.rating_deviation,
                     name: t.name,
                     deleted_at: t.deleted_at,
                 })
@@ -204,6 +208,8 @@ pub async fn bots(
             description: b.description,
             name: b.name,
             uploaded_by: u,
+            rating: b.rating,
+            games_played: b.games_played,
         })
         .collect();
     Ok(web::Json(BotsResponse::Bots(result)))
@@ -257,3 +263,71 @@ pub async fn pfp(
 
     Ok(HttpResponse::Ok().streaming(response.body))
 }
+
+#[derive(Deserialize)]
+pub struct RatingHistoryQuery {
+    team: i32,
+    from: Option<i64>,
+    to: Option<i64>,
+    points: Option<usize>,
+}
+
+/// A team's rating after each game, downsampled to at most `points` entries
+#[get("/rating-history")]
+pub async fn rating_history(
+    web::Query::<RatingHistoryQuery>(RatingHistoryQuery {
+        team,
+        from,
+        to,
+        points,
+    }): web::Query<RatingHistoryQuery>,
+) -> ApiResult<Vec<RatingHistoryEntry>> {
+    let conn = &mut (*DB_CONNECTION).get()?;
+    Ok(web::Json(conn.get_rating_history(
+        team,
+        from,
+        to,
+        points.unwrap_or(500).min(2000),
+    )?))
+}
+
+#[derive(Deserialize)]
+pub struct BotLeaderboardQuery {
+    pub page_size: Option<i32>,
+    pub page: Option<i32>,
+    /// Leave out bots that have played fewer rated games. Defaults to 1.
+    pub min_games: Option<i32>,
+}
+
+/// Bots ranked by their own rating, best first
+#[get("/bot-leaderboard")]
+pub async fn bot_leaderboard(
+    web::Query::<BotLeaderboardQuery>(BotLeaderboardQuery {
+        page_size,
+        page,
+        min_games,
+    }): web::Query<BotLeaderboardQuery>,
+) -> ApiResult<Vec<BotWithTeam<Team>>> {
+    let conn = &mut (*DB_CONNECTION).get()?;
+    let page_size = page_size.unwrap_or(10).min(100);
+    let page = page.unwrap_or(0);
+    let result = schema::bots::dsl::bots
+        .filter(schema::bots::dsl::deleted_at.is_null())
+        .filter(schema::bots::dsl::games_played.ge(min_games.unwrap_or(1)))
+        .inner_join(
+            schema::teams::dsl::teams.on(schema::bots::dsl::team.eq(schema::teams::dsl::id)),
+        )
+        .filter(schema::teams::dsl::deleted_at.is_null())
+        .inner_join(
+            schema::users::dsl::users.on(schema::bots::dsl::uploaded_by.eq(schema::users::dsl::id)),
+        )
+        .order_by(schema::bots::dsl::rating.desc())
+        .limit(page_size.into())
+        .offset((page * page_size).into())
+        .select((Bot::as_select(), Team::as_select(), User::as_select()))
+        .load::<(Bot, Team, User)>(conn)?
+        .into_iter()
+        .map(|(b, t, u)| BotWithTeam::from_bot_team_user(b, t, u))
+        .collect();
+    Ok(web::Json(result))
+}
@@ -3,25 +3,34 @@ use futures_util::TryStreamExt;
 use itertools::Itertools;
 use shared::{
     db::{
+        conn::{begin_transaction, end_transaction},
         dao::{
             bots::BotsDao,
-            games::{GameQueryOptions, GamesDao, PageOptions},
+            games::{GameQueryOptions, GameQueue, GamesDao, PageOptions},
         },
         models::{BotWithTeam, GameWithBots, GameWithBotsWithResult, Team},
         schema::game_states::{self, game_id, step},
         schema_aliases::*,
     },
     poker::game::GameStateSQL,
-    WhichBot,
+    GameConfig, HouseBot, WhichBot,
 };
 use std::usize;
 
+use crate::config::{challenges_per_hour, new_games_queue_url, CHALLENGE_MAX_ROUNDS};
+
 use super::*;
 
 #[derive(Deserialize)]
 pub struct MakeGameQuery {
-    pub defender: i32,
-    pub challenger: i32,
+    /// The team to play against, whose active bot defends
+    pub defender: Option<i32>,
+    /// A house bot to play against instead of a team
+    pub house: Option<HouseBot>,
+    /// One of your team's bots, by default the active one
+    pub challenger: Option<i32>,
+    /// Hands to play. Defaults to the length of a ladder game.
+    pub rounds: Option<usize>,
 }
 
 #[derive(Serialize, TS)]
@@ -30,6 +39,149 @@ pub struct CreateGameResponse {
     pub id: String,
 }
 
+/// Unrated games started by `team` since `since`, not counting tournament
+/// games or scrimmages between the team's own bots
+fn count_challenges(
+    conn: &mut PgConnection,
+    team: i32,
+    since: i64,
+) -> Result<i64, diesel::result::Error> {
+    use schema::{bots, games, scrimmage_games, tournament_pairings};
+    games::table
+        .filter(games::rated.eq(false))
+        .filter(games::created.ge(since))
+        .filter(games::challenger.eq_any(bots::table.filter(bots::team.eq(team)).select(bots::id)))
+        .filter(diesel::dsl::not(
+            games::id.nullable().eq_any(
+                tournament_pairings::table
+                    .filter(tournament_pairings::game.is_not_null())
+                    .select(tournament_pairings::game),
+            ),
+        ))
+        .filter(diesel::dsl::not(
+            games::id.eq_any(scrimmage_games::table.select(scrimmage_games::game)),
+        ))
+        .count()
+        .get_result(conn)
+}
+
+/// Queue an unrated scrimmage between one of your bots and another team's
+/// active bot. Each team can start a limited number of these an hour.
+#[post("/challenge")]
+pub async fn challenge(
+    session: Session,
+    web::Json(MakeGameQuery {
+        defender,
+        house,
+        challenger,
+        rounds,
+    }): web::Json<MakeGameQuery>,
+    s3_client: web::Data<aws_sdk_s3::Client>,
+    sqs_client: web::Data<aws_sdk_sqs::Client>,
+) -> ApiResult<CreateGameResponse> {
+    let team =
+        auth::get_team(&session).ok_or(actix_web::error::ErrorUnauthorized("Not on a team"))?;
+    if defender == Some(team.id) {
+        return Err(actix_web::error::ErrorBadRequest("You can't challenge your own team").into());
+    }
+    let conn = &mut (*DB_CONNECTION).get()?;
+    let (opponent, opponent_bot) = match (defender, house) {
+        (Some(defender), None) => {
+            let opponent: Team = schema::teams::dsl::teams
+                .find(defender)
+                .filter(schema::teams::dsl::deleted_at.is_null())
+                .first::<Team>(conn)
+                .optional()?
+                .ok_or(actix_web::error::ErrorNotFound("Opponent team not found"))?;
+            let opponent_bot = opponent
+                .active_bot
+                .ok_or(actix_web::error::ErrorBadRequest(
+                    "Opponent team has no active bot",
+                ))?;
+            (opponent, opponent_bot)
+        }
+        (None, Some(house)) => {
+            // House bots belong to the house team, which never plays ladder games
+            let (opponent_bot, opponent): (Bot, Team) = schema::bots::dsl::bots
+                .inner_join(schema::teams::table)
+                .filter(schema::bots::dsl::house.eq(house))
+                .filter(schema::bots::dsl::deleted_at.is_null())
+                .first::<(Bot, Team)>(conn)
+                .optional()?
+                .ok_or(actix_web::error::ErrorNotFound("House bot not found"))?;
+            (opponent, opponent_bot.id)
+        }
+        _ => {
+            return Err(actix_web::error::ErrorBadRequest(
+                "Give either a defender team or a house bot",
+            )
+            .into())
+        }
+    };
+    let bot = challenger
+        .or(team.active_bot)
+        .ok_or(actix_web::error::ErrorBadRequest(
+            "Your team has no active bot",
+        ))?;
+    let bot: Bot = schema::bots::dsl::bots
+        .find(bot)
+        .filter(schema::bots::dsl::team.eq(team.id))
+        .filter(schema::bots::dsl::deleted_at.is_null())
+        .first::<Bot>(conn)
+        .optional()?
+        .ok_or(actix_web::error::ErrorNotFound("Bot not found"))?;
+    match bot.build_status {
+        shared::BuildStatus::TestGameSucceeded => {}
+        _ => return Err(actix_web::error::ErrorBadRequest("Bot is not ready to play.").into()),
+    }
+
+    // The team stays locked until the game is created, so challenges sent at
+    // the same time can't all fit under the limit
+    begin_transaction(conn)?;
+    let created = async {
+        let own_team: Team = schema::teams::dsl::teams
+            .find(team.id)
+            .for_update()
+            .first::<Team>(conn)?;
+        let since = chrono::Utc::now().timestamp() - 60 * 60;
+        if count_challenges(conn, team.id, since)? >= challenges_per_hour() {
+            return Err(actix_web::error::ErrorTooManyRequests(
+                "Your team has started too many unrated games in the last hour",
+            )
+            .into());
+        }
+
+        let default = GameConfig::default();
+        let config = GameConfig {
+            hands: rounds
+                .unwrap_or(default.hands)
+                .clamp(1, CHALLENGE_MAX_ROUNDS),
+            ..default
+        };
+        config
+            .validate()
+            .map_err(|e| actix_web::error::ErrorBadRequest(e.to_string()))?;
+        conn.create_game_with_bots(
+            (&opponent, opponent_bot),
+            (&own_team, bot.id),
+            false,
+            config,
+            &GameQueue {
+                game_logs_s3_bucket: &game_logs_s3_bucket(),
+                new_games_sqs_queue: &new_games_queue_url(),
+                sqs_client: &sqs_client,
+                s3_client: &s3_client,
+            },
+        )
+        .await
+        .map_err(|e| ApiError::from(actix_web::error::ErrorInternalServerError(e.to_string())))
+    }
+    .await;
+    end_transaction(conn, &created)?;
+    let id = created?;
+    Ok(web::Json(CreateGameResponse { id }))
+}
+
 #[get("/games")]
 pub async fn games(
     session: Session,
@@ -159,3 +311,62 @@ pub async fn game_length(
         Err(err) => return Err(actix_web::error::ErrorNotFound(err).into()),
     }
 }
+
+#[derive(Deserialize)]
+pub struct HeadToHeadQuery {
+    bot: i32,
+    opponent: i32,
+    /// Defaults to 0.95
+    confidence: Option<f64>,
+}
+
+/// How one bot did against another over all their games, with a confidence
+/// interval and a significance test
+#[get("/head-to-head")]
+pub async fn head_to_head(
+    web::Query::<HeadToHeadQuery>(HeadToHeadQuery {
+        bot,
+        opponent,
+        confidence,
+    }): web::Query<HeadToHeadQuery>,
+) -> ApiResult<results::analysis::HeadToHead> {
+    let confidence = confidence.unwrap_or(0.95);
+    if !(0.5..1.0).contains(&confidence) {
+        return Err(actix_web::error::ErrorBadRequest(
+            "Confidence has to be at least 0.5 and less than 1",
+        )
+        .into());
+    }
+    let conn = &mut (*DB_CONNECTION).get()?;
+    Ok(web::Json(results::analysis::head_to_head(
+        conn, bot, opponent, confidence,
+    )?))
+}
+
+#[derive(Deserialize)]
+pub struct BotStatsQuery {
+    bot: i32,
+    /// Only count games against this bot
+    opponent: Option<i32>,
+    /// Only count this game
+    game: Option<String>,
+}
+
+/// How a bot plays its hands: VPIP, PFR, 3-bet, fold to raise, aggression
+/// factor, WTSD, W$SD and the average pot, overall and against each opponent
+#[get("/bot-stats")]
+pub async fn bot_stats(
+    web::Query::<BotStatsQuery>(BotStatsQuery {
+        bot,
+        opponent,
+        game,
+    }): web::Query<BotStatsQuery>,
+) -> ApiResult<shared::hand_stats::BotStats> {
+    let conn = &mut (*DB_CONNECTION).get()?;
+    Ok(web::Json(shared::hand_stats::bot_stats(
+        conn,
+        bot,
+        opponent,
+        game.as_deref(),
+    )?))
+}
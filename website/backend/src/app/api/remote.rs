@@ -0,0 +1,243 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+use actix_web::HttpRequest;
+use actix_ws::{Message, MessageStream};
+use lazy_static::lazy_static;
+use shared::{GameConfig, GameTask};
+use tokio::sync::mpsc;
+
+use crate::config::{
+    max_remote_sessions, remote_games_per_hour, REMOTE_GAME_DEFAULT_ROUNDS, REMOTE_GAME_MAX_ROUNDS,
+};
+
+use super::*;
+
+/// A remote bot that has connected and is waiting for the engine
+struct WaitingClient {
+    socket: actix_ws::Session,
+    // Text messages sent by the bot
+    lines: mpsc::UnboundedReceiver<String>,
+}
+
+/// A team's remote games: when they started in the last hour, and how many
+/// bots are still connected
+#[derive(Default)]
+struct TeamSessions {
+    started: VecDeque<i64>,
+    connected: usize,
+}
+
+lazy_static! {
+    // Remote bots by session token, until the gameplay worker connects to the relay.
+    // These only live in this process, so the website has to run as a single
+    // instance for the worker's relay connection to find the bot.
+    static ref WAITING_CLIENTS: Mutex<HashMap<String, WaitingClient>> = Mutex::new(HashMap::new());
+    static ref TEAM_SESSIONS: Mutex<HashMap<i32, TeamSessions>> = Mutex::new(HashMap::new());
+}
+
+/// Held while a team's remote bot is connected
+struct SessionSlot(i32);
+
+impl SessionSlot {
+    /// Start a remote game for the team, if it is under its limits
+    fn take(team: i32) -> Result<Self, actix_web::Error> {
+        let mut sessions = TEAM_SESSIONS.lock().unwrap();
+        let sessions = sessions.entry(team).or_default();
+        let since = chrono::Utc::now().timestamp() - 60 * 60;
+        while sessions.started.front().is_some_and(|&started| started < since) {
+            sessions.started.pop_front();
+        }
+        if sessions.connected >= max_remote_sessions() {
+            return Err(actix_web::error::ErrorTooManyRequests(
+                "Your team already has too many remote bots connected",
+            ));
+        }
+        if sessions.started.len() >= remote_games_per_hour() {
+            return Err(actix_web::error::ErrorTooManyRequests(
+                "Your team has started too many remote games in the last hour",
+            ));
+        }
+        sessions.started.push_back(chrono::Utc::now().timestamp());
+        sessions.connected += 1;
+        Ok(SessionSlot(team))
+    }
+}
+
+impl Drop for SessionSlot {
+    fn drop(&mut self) {
+        if let Some(sessions) = TEAM_SESSIONS.lock().unwrap().get_mut(&self.0) {
+            sessions.connected -= 1;
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RemoteBotQuery {
+    pub opponent: i32,
+    pub rounds: Option<usize>,
+    /// Send the bot the rules of the game with `CONFIG` before the first hand
+    #[serde(default)]
+    pub game_config: bool,
+}
+
+/// Play an unrated game from your own machine against a team's active bot.
+/// Each text message is one line of the engine protocol, and the socket is
+/// closed with the result of the game as the reason. A team can only have a
+/// few remote bots connected at once, and start a limited number an hour.
+#[get("/remote-bot")]
+pub async fn remote_bot(
+    session: Session,
+    req: HttpRequest,
+    body: web::Payload,
+    web::Query::<RemoteBotQuery>(RemoteBotQuery {
+        opponent,
+        rounds,
+        game_config,
+    }): web::Query<RemoteBotQuery>,
+    sqs_client: web::Data<aws_sdk_sqs::Client>,
+) -> Result<HttpResponse, ApiError> {
+    let team =
+        auth::get_team(&session).ok_or(actix_web::error::ErrorUnauthorized("Not on a team"))?;
+    if opponent == team.id {
+        return Err(actix_web::error::ErrorBadRequest("You can't play against your own team").into());
+    }
+    let conn = &mut (*DB_CONNECTION).get()?;
+    let opponent: Team = teams::dsl::teams
+        .find(opponent)
+        .filter(teams::dsl::deleted_at.is_null())
+        .first::<Team>(conn)
+        .optional()?
+        .ok_or(actix_web::error::ErrorNotFound("Opponent team not found"))?;
+    let opponent_bot = opponent.active_bot.ok_or(actix_web::error::ErrorBadRequest(
+        "Opponent team has no active bot",
+    ))?;
+    let rounds = rounds
+        .unwrap_or(REMOTE_GAME_DEFAULT_ROUNDS)
+        .clamp(1, REMOTE_GAME_MAX_ROUNDS);
+    let config = GameConfig {
+        hands: rounds,
+        ..Default::default()
+    };
+    config
+        .validate()
+        .map_err(|e| actix_web::error::ErrorBadRequest(e.to_string()))?;
+    let slot = SessionSlot::take(team.id)?;
+
+    let (response, socket, mut messages) = actix_ws::handle(&req, body)?;
+    let token = format!("{:032x}", rand::thread_rng().gen::<u128>());
+    let (lines_tx, lines) = mpsc::unbounded_channel();
+    WAITING_CLIENTS.lock().unwrap().insert(
+        token.clone(),
+        WaitingClient {
+            socket: socket.clone(),
+            lines,
+        },
+    );
+    let queued = sqs_client
+        .send_message()
+        .queue_url(std::env::var("NEW_GAMES_QUEUE_URL")?)
+        .message_body(serde_json::to_string(&GameTask::RemoteGame {
+            opponent: opponent_bot,
+            session: token.clone(),
+            config,
+            game_config,
+        })?)
+        .send()
+        .await;
+    if let Err(e) = queued {
+        WAITING_CLIENTS.lock().unwrap().remove(&token);
+        return Err(e.into());
+    }
+
+    actix_web::rt::spawn(async move {
+        let _slot = slot;
+        let mut socket = socket;
+        while let Some(Ok(message)) = messages.next().await {
+            match message {
+                Message::Text(text) => {
+                    if lines_tx.send(text.to_string()).is_err() {
+                        break;
+                    }
+                }
+                Message::Ping(bytes) => {
+                    if socket.pong(&bytes).await.is_err() {
+                        break;
+                    }
+                }
+                Message::Close(_) => break,
+                _ => {}
+            }
+        }
+        // The engine may not have picked the game up yet
+        WAITING_CLIENTS.lock().unwrap().remove(&token);
+    });
+    Ok(response)
+}
+
+#[derive(Deserialize)]
+pub struct RemoteBotRelayQuery {
+    pub session: String,
+}
+
+/// Where the gameplay worker connects to talk to a waiting remote bot.
+/// The session token is only known to the bot's connection and the worker.
+#[get("/remote-bot-relay")]
+pub async fn remote_bot_relay(
+    req: HttpRequest,
+    body: web::Payload,
+    web::Query::<RemoteBotRelayQuery>(RemoteBotRelayQuery { session }): web::Query<
+        RemoteBotRelayQuery,
+    >,
+) -> Result<HttpResponse, ApiError> {
+    let client = WAITING_CLIENTS
+        .lock()
+        .unwrap()
+        .remove(&session)
+        .ok_or(actix_web::error::ErrorNotFound(
+            "No remote bot is waiting for this session",
+        ))?;
+    let (response, engine, engine_messages) = actix_ws::handle(&req, body)?;
+    actix_web::rt::spawn(relay(client, engine, engine_messages));
+    Ok(response)
+}
+
+/// Pass messages between the engine and the remote bot until either side leaves
+async fn relay(
+    mut client: WaitingClient,
+    mut engine: actix_ws::Session,
+    mut engine_messages: MessageStream,
+) {
+    let reason = loop {
+        tokio::select! {
+            message = engine_messages.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    if client.socket.text(text.to_string()).await.is_err() {
+                        break None;
+                    }
+                }
+                Some(Ok(Message::Ping(bytes))) => {
+                    if engine.pong(&bytes).await.is_err() {
+                        break None;
+                    }
+                }
+                Some(Ok(Message::Close(reason))) => break reason,
+                Some(Ok(_)) => {}
+                Some(Err(_)) | None => break None,
+            },
+            line = client.lines.recv() => match line {
+                Some(line) => {
+                    if engine.text(line).await.is_err() {
+                        break None;
+                    }
+                }
+                // The remote bot disconnected
+                None => break None,
+            },
+        }
+    };
+    client.socket.close(reason).await.ok();
+    engine.close(None).await.ok();
+}
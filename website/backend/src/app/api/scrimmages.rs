@@ -0,0 +1,182 @@
+use results::analysis::{head_to_head_in_games, HeadToHead};
+use shared::{
+    db::{
+        conn::{begin_transaction, end_transaction},
+        dao::{
+            games::{GameQueue, GamesDao},
+            scrimmages::ScrimmagesDao,
+        },
+        models::{NewScrimmage, Scrimmage},
+    },
+    GameConfig,
+};
+
+use crate::config::{
+    new_games_queue_url, scrimmage_games_per_hour, CHALLENGE_MAX_ROUNDS, SCRIMMAGE_MAX_GAMES,
+};
+
+use super::*;
+
+#[derive(Serialize)]
+pub struct ScrimmageSummary {
+    pub scrimmage: Scrimmage,
+    pub games: Vec<String>,
+    /// How many of the games have finished
+    pub finished: usize,
+    /// How `bot_a` did against `bot_b` in the finished games
+    pub result: HeadToHead,
+}
+
+fn summarize(conn: &mut PgConnection, scrimmage: Scrimmage) -> ApiResult<ScrimmageSummary> {
+    let games = conn.get_scrimmage_games(scrimmage.id)?;
+    let result = head_to_head_in_games(conn, scrimmage.bot_a, scrimmage.bot_b, &games, 0.95)?;
+    Ok(web::Json(ScrimmageSummary {
+        finished: result.games,
+        games,
+        scrimmage,
+        result,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct ScrimmageRequest {
+    pub bot_a: i32,
+    pub bot_b: i32,
+    /// How many games to play, taking turns defending. Defaults to 1.
+    pub games: Option<usize>,
+    /// Hands in each game. Defaults to the length of a ladder game.
+    pub rounds: Option<usize>,
+}
+
+/// Queue unrated games between two of your team's bots
+#[post("/scrimmage")]
+pub async fn create_scrimmage(
+    session: Session,
+    web::Json(ScrimmageRequest {
+        bot_a,
+        bot_b,
+        games,
+        rounds,
+    }): web::Json<ScrimmageRequest>,
+    s3_client: web::Data<aws_sdk_s3::Client>,
+    sqs_client: web::Data<aws_sdk_sqs::Client>,
+) -> ApiResult<ScrimmageSummary> {
+    let team =
+        auth::get_team(&session).ok_or(actix_web::error::ErrorUnauthorized("Not on a team"))?;
+    if bot_a == bot_b {
+        return Err(actix_web::error::ErrorBadRequest("Choose two different bots").into());
+    }
+    let conn = &mut (*DB_CONNECTION).get()?;
+    let bots: Vec<Bot> = schema::bots::dsl::bots
+        .filter(schema::bots::dsl::id.eq_any([bot_a, bot_b]))
+        .filter(schema::bots::dsl::team.eq(team.id))
+        .filter(schema::bots::dsl::deleted_at.is_null())
+        .load::<Bot>(conn)?;
+    if bots.len() != 2 {
+        return Err(actix_web::error::ErrorNotFound("Bot not found").into());
+    }
+    if bots
+        .iter()
+        .any(|bot| !matches!(bot.build_status, shared::BuildStatus::TestGameSucceeded))
+    {
+        return Err(actix_web::error::ErrorBadRequest("Bot is not ready to play.").into());
+    }
+
+    let games = games.unwrap_or(1).clamp(1, SCRIMMAGE_MAX_GAMES);
+    // The team stays locked until the games are created, so scrimmages started
+    // at the same time can't all fit under the limit
+    begin_transaction(conn)?;
+    let created = async {
+        let own_team: Team = schema::teams::dsl::teams
+            .find(team.id)
+            .for_update()
+            .first::<Team>(conn)?;
+        let since = chrono::Utc::now().timestamp() - 60 * 60;
+        if conn.count_scrimmage_games(team.id, since)? + games as i64 > scrimmage_games_per_hour()
+        {
+            return Err(actix_web::error::ErrorTooManyRequests(
+                "Your team has started too many scrimmage games in the last hour",
+            )
+            .into());
+        }
+
+        let default = GameConfig::default();
+        let config = GameConfig {
+            hands: rounds
+                .unwrap_or(default.hands)
+                .clamp(1, CHALLENGE_MAX_ROUNDS),
+            ..default
+        };
+        config
+            .validate()
+            .map_err(|e| actix_web::error::ErrorBadRequest(e.to_string()))?;
+        let scrimmage = conn.create_scrimmage(NewScrimmage {
+            team: team.id,
+            bot_a,
+            bot_b,
+        })?;
+        for i in 0..games {
+            let (defender, challenger) = if i % 2 == 0 {
+                (bot_a, bot_b)
+            } else {
+                (bot_b, bot_a)
+            };
+            match conn
+                .create_game_with_bots(
+                    (&own_team, defender),
+                    (&own_team, challenger),
+                    false,
+                    config,
+                    &GameQueue {
+                        game_logs_s3_bucket: &game_logs_s3_bucket(),
+                        new_games_sqs_queue: &new_games_queue_url(),
+                        sqs_client: &sqs_client,
+                        s3_client: &s3_client,
+                    },
+                )
+                .await
+            {
+                Ok(game) => conn.add_scrimmage_game(scrimmage.id, &game)?,
+                Err(e) => log::error!(
+                    "Failed to create a game for scrimmage {}: {}",
+                    scrimmage.id,
+                    e
+                ),
+            }
+        }
+        Ok::<_, ApiError>(scrimmage)
+    }
+    .await;
+    end_transaction(conn, &created)?;
+    summarize(conn, created?)
+}
+
+#[derive(Deserialize)]
+pub struct ScrimmageQuery {
+    pub id: i32,
+}
+
+/// The combined results of a scrimmage's games so far
+#[get("/scrimmage")]
+pub async fn get_scrimmage(
+    session: Session,
+    web::Query::<ScrimmageQuery>(ScrimmageQuery { id }): web::Query<ScrimmageQuery>,
+) -> ApiResult<ScrimmageSummary> {
+    let team =
+        auth::get_team(&session).ok_or(actix_web::error::ErrorUnauthorized("Not on a team"))?;
+    let conn = &mut (*DB_CONNECTION).get()?;
+    let scrimmage = conn
+        .get_scrimmage(id)?
+        .filter(|scrimmage| scrimmage.team == team.id)
+        .ok_or(actix_web::error::ErrorNotFound("Scrimmage not found"))?;
+    summarize(conn, scrimmage)
+}
+
+/// Your team's scrimmages, newest first
+#[get("/scrimmages")]
+pub async fn scrimmages(session: Session) -> ApiResult<Vec<Scrimmage>> {
+    let team =
+        auth::get_team(&session).ok_or(actix_web::error::ErrorUnauthorized("Not on a team"))?;
+    let conn = &mut (*DB_CONNECTION).get()?;
+    Ok(web::Json(conn.get_scrimmages(team.id)?))
+}
@@ -0,0 +1,155 @@
+use shared::tournament::{self, TournamentError};
+use shared::{
+    db::{
+        dao::{games::GameQueue, tournaments::TournamentsDao},
+        models::{GameResult, Tournament, TournamentEntrant, TournamentPairing},
+    },
+    TournamentFormat,
+};
+
+use crate::config::new_games_queue_url;
+
+use super::*;
+
+impl From<TournamentError> for ApiError {
+    fn from(err: TournamentError) -> Self {
+        let status_code = match err {
+            TournamentError::NotFound => StatusCode::NOT_FOUND,
+            TournamentError::Finished
+            | TournamentError::NoActiveBot(_)
+            | TournamentError::TooFewEntrants => StatusCode::BAD_REQUEST,
+            TournamentError::RoundRunning(_) => StatusCode::CONFLICT,
+            TournamentError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError {
+            status_code,
+            message: err.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, TS)]
+#[cfg_attr(feature = "ts-bindings", ts(export))]
+pub struct TournamentWithStandings {
+    pub tournament: Tournament,
+    /// Best first, as of the last finished round
+    pub standings: Vec<TournamentEntrant>,
+    /// Every pairing so far, with the result of its game once it has finished
+    pub pairings: Vec<(TournamentPairing, Option<GameResult>)>,
+}
+
+#[get("/tournaments")]
+pub async fn tournaments() -> ApiResult<Vec<Tournament>> {
+    let conn = &mut (*DB_CONNECTION).get()?;
+    Ok(web::Json(conn.get_tournaments()?))
+}
+
+#[derive(Deserialize)]
+pub struct TournamentQuery {
+    pub id: i32,
+}
+
+#[get("/tournament")]
+pub async fn get_tournament(
+    web::Query::<TournamentQuery>(TournamentQuery { id }): web::Query<TournamentQuery>,
+) -> ApiResult<TournamentWithStandings> {
+    let conn = &mut (*DB_CONNECTION).get()?;
+    let tournament = conn.get_tournament(id)?.ok_or(TournamentError::NotFound)?;
+    Ok(web::Json(TournamentWithStandings {
+        tournament,
+        standings: conn.get_standings(id)?,
+        pairings: conn.get_pairings(id)?,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct CreateTournamentRequest {
+    pub name: String,
+    pub format: TournamentFormat,
+    /// Only used by Swiss tournaments. Defaults to about log2(entrants).
+    pub rounds: Option<i32>,
+    /// Defaults to every team with an active bot
+    pub teams: Option<Vec<i32>>,
+}
+
+/// Enter teams into a new tournament with their current active bots
+#[post("/create-tournament")]
+pub async fn create_tournament(
+    session: Session,
+    web::Json(CreateTournamentRequest {
+        name,
+        format,
+        rounds,
+        teams,
+    }): web::Json<CreateTournamentRequest>,
+) -> ApiResult<Tournament> {
+    let conn = &mut (*DB_CONNECTION).get()?;
+    let tournament = tournament::create_tournament(conn, name, format, rounds, teams)?;
+    admin::audit(conn, &session, "create-tournament", tournament.id, None)?;
+    Ok(web::Json(tournament))
+}
+
+/// Pair the first round and queue its games
+#[get("/start-tournament")]
+pub async fn start_tournament(
+    session: Session,
+    web::Query::<TournamentQuery>(TournamentQuery { id }): web::Query<TournamentQuery>,
+    s3_client: web::Data<aws_sdk_s3::Client>,
+    sqs_client: web::Data<aws_sdk_sqs::Client>,
+) -> ApiResult<Tournament> {
+    let conn = &mut (*DB_CONNECTION).get()?;
+    let tournament = conn.get_tournament(id)?.ok_or(TournamentError::NotFound)?;
+    if tournament.current_round != 0 {
+        return Err(actix_web::error::ErrorBadRequest("The tournament has already started").into());
+    }
+    let tournament = tournament::advance_tournament(
+        conn,
+        id,
+        &GameQueue {
+            game_logs_s3_bucket: &game_logs_s3_bucket(),
+            new_games_sqs_queue: &new_games_queue_url(),
+            sqs_client: &sqs_client,
+            s3_client: &s3_client,
+        },
+    )
+    .await?;
+    admin::audit(
+        conn,
+        &session,
+        "start-tournament",
+        id,
+        Some(serde_json::json!({ "round": tournament.current_round })),
+    )?;
+    Ok(web::Json(tournament))
+}
+
+/// Finish the current round once its games are done, then pair the next one
+/// or finish the tournament
+#[get("/advance-tournament")]
+pub async fn advance_tournament(
+    session: Session,
+    web::Query::<TournamentQuery>(TournamentQuery { id }): web::Query<TournamentQuery>,
+    s3_client: web::Data<aws_sdk_s3::Client>,
+    sqs_client: web::Data<aws_sdk_sqs::Client>,
+) -> ApiResult<Tournament> {
+    let conn = &mut (*DB_CONNECTION).get()?;
+    let tournament = tournament::advance_tournament(
+        conn,
+        id,
+        &GameQueue {
+            game_logs_s3_bucket: &game_logs_s3_bucket(),
+            new_games_sqs_queue: &new_games_queue_url(),
+            sqs_client: &sqs_client,
+            s3_client: &s3_client,
+        },
+    )
+    .await?;
+    admin::audit(
+        conn,
+        &session,
+        "advance-tournament",
+        id,
+        Some(serde_json::json!({ "round": tournament.current_round })),
+    )?;
+    Ok(web::Json(tournament))
+}
@@ -9,6 +9,10 @@ use std::fs;
 use lettre::transport::smtp::client::{Tls, TlsParameters};
 
 pub const TEAM_SIZE: usize = 5;
+pub const REMOTE_GAME_DEFAULT_ROUNDS: usize = 100;
+pub const REMOTE_GAME_MAX_ROUNDS: usize = 1000;
+pub const CHALLENGE_MAX_ROUNDS: usize = 1000;
+pub const SCRIMMAGE_MAX_GAMES: usize = 20;
 
 pub fn microsoft_client_id() -> String {
     std::env::var("APP_MICROSOFT_CLIENT_ID").expect("MICROSOFT_CLIENT_ID must be set in .env")
@@ -72,6 +76,42 @@ pub fn game_logs_s3_bucket() -> String {
     std::env::var("GAME_LOGS_S3_BUCKET").expect("GAME_LOGS_S3_BUCKET must be set in .env")
 }
 
+pub fn new_games_queue_url() -> String {
+    std::env::var("NEW_GAMES_QUEUE_URL").expect("NEW_GAMES_QUEUE_URL must be set in .env")
+}
+
+/// How many unrated games a team can start in an hour
+pub fn challenges_per_hour() -> i64 {
+    std::env::var("CHALLENGES_PER_HOUR")
+        .ok()
+        .and_then(|limit| limit.parse().ok())
+        .unwrap_or(10)
+}
+
+/// How many games between its own bots a team can start in an hour
+pub fn scrimmage_games_per_hour() -> i64 {
+    std::env::var("SCRIMMAGE_GAMES_PER_HOUR")
+        .ok()
+        .and_then(|limit| limit.parse().ok())
+        .unwrap_or(50)
+}
+
+/// How many remote games a team can start in an hour
+pub fn remote_games_per_hour() -> usize {
+    std::env::var("REMOTE_GAMES_PER_HOUR")
+        .ok()
+        .and_then(|limit| limit.parse().ok())
+        .unwrap_or(30)
+}
+
+/// How many remote bots a team can have connected at once
+pub fn max_remote_sessions() -> usize {
+    std::env::var("MAX_REMOTE_SESSIONS")
+        .ok()
+        .and_then(|limit| limit.parse().ok())
+        .unwrap_or(2)
+}
+
 pub fn bot_size() -> u64 {
     std::env::var("BOT_SIZE")
         .expect("BOT_SIZE must be set in .env")
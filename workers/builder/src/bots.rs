@@ -1,44 +1,179 @@
-use subprocess::PopenConfig;
+use subprocess::{ExitStatus, PopenConfig};
 use tokio::{fs, io};
 
-use std::path::{Path, PathBuf};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use shared::BotJson;
+use shared::{BotJson, BuildStatus};
 
-pub async fn build_bot<T: AsRef<Path>>(bot_folder: T) -> Result<(), io::Error> {
-    //TODO: run this in a cgroup this to protect against zip bombs
-    let path = bot_folder.as_ref();
-    log::info!("Constructing bot in {:?}", path);
+use crate::limits::{extract_archive, ArchiveError, BuildLimits, Cgroup};
+
+/// Why a bot failed to build
+#[derive(Debug)]
+pub enum BuildError {
+    Archive(ArchiveError),
+    TimedOut(Duration),
+    OutOfMemory(String),
+    Failed(String),
+    Io(io::Error),
+}
+
+impl BuildError {
+    /// The status the bot is left in
+    pub fn status(&self) -> BuildStatus {
+        match self {
+            BuildError::Archive(ArchiveError::TooLarge { .. }) => BuildStatus::ArchiveTooLarge,
+            BuildError::Archive(ArchiveError::TooManyFiles { .. }) => {
+                BuildStatus::ArchiveTooManyFiles
+            }
+            BuildError::TimedOut(_) => BuildStatus::BuildTimedOut,
+            BuildError::OutOfMemory(_) => BuildStatus::BuildOutOfMemory,
+            BuildError::Archive(ArchiveError::Invalid(_) | ArchiveError::Io(_))
+            | BuildError::Failed(_)
+            | BuildError::Io(_) => BuildStatus::BuildFailed,
+        }
+    }
+}
 
-    subprocess::Popen::create(
-        &["unzip", "-o", "bot.zip"],
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Archive(ArchiveError::TooLarge { size, limit }) => write!(
+                f,
+                "bot.zip is at least {} bytes uncompressed, more than the limit of {}",
+                size, limit
+            ),
+            BuildError::Archive(ArchiveError::TooManyFiles { count, limit }) => write!(
+                f,
+                "bot.zip has {} files, more than the limit of {}",
+                count, limit
+            ),
+            BuildError::Archive(ArchiveError::Invalid(e)) => {
+                write!(f, "bot.zip is not a valid zip file: {}", e)
+            }
+            BuildError::Archive(ArchiveError::Io(e)) => {
+                write!(f, "Failed to extract bot.zip: {}", e)
+            }
+            BuildError::TimedOut(timeout) => {
+                write!(f, "Build timed out after {} seconds", timeout.as_secs())
+            }
+            BuildError::OutOfMemory(limit) => {
+                write!(f, "Build ran out of memory, the limit is {}", limit)
+            }
+            BuildError::Failed(e) => write!(f, "{}", e),
+            BuildError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl From<io::Error> for BuildError {
+    fn from(e: io::Error) -> Self {
+        BuildError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for BuildError {
+    fn from(e: serde_json::Error) -> Self {
+        BuildError::Failed(format!("Unable to parse bot.json: {}", e))
+    }
+}
+
+/// Mount the package mirror used by language presets, if this builder has one
+fn mirror_bind() -> String {
+    match std::env::var("PACKAGE_MIRROR") {
+        Ok(mirror) => format!("--ro-bind {} {} ", mirror, shared::presets::MIRROR_PATH),
+        Err(_) => String::new(),
+    }
+}
+
+/// Run a step of the build inside the build's cgroup, writing its output to the build log.
+/// The step is killed if it runs for longer than the build timeout.
+fn run_limited(
+    command: &[&str],
+    cwd: &Path,
+    log: &Path,
+    cgroup: Option<&Cgroup>,
+    limits: &BuildLimits,
+) -> Result<ExitStatus, BuildError> {
+    let argv = match cgroup {
+        Some(cgroup) => cgroup.wrap(command),
+        None => command.iter().map(|s| s.to_string()).collect(),
+    };
+    let mut process = subprocess::Popen::create(
+        &argv,
         PopenConfig {
-            cwd: Some(path.into()),
+            cwd: Some(cwd.into()),
             stderr: subprocess::Redirection::Merge,
-            stdout: subprocess::Redirection::File(std::fs::File::create(path.join("logs"))?),
+            stdout: subprocess::Redirection::File(std::fs::File::create(log)?),
             ..Default::default()
         },
     )
-    .and_then(|mut p| p.wait())
-    .map_err(|e| {
-        io::Error::new(
-            io::ErrorKind::Other,
-            format!("Failed to unzip bot: {:?}", e),
-        )
-    })?;
+    .map_err(|e| BuildError::Failed(format!("Failed to start {}: {:?}", command[0], e)))?;
+
+    let status = process
+        .wait_timeout(limits.timeout)
+        .map_err(|e| BuildError::Failed(format!("{:?}", e)))?;
+    let Some(status) = status else {
+        match cgroup {
+            Some(cgroup) => cgroup.kill(),
+            None => process.kill()?,
+        }
+        process.wait().ok();
+        return Err(BuildError::TimedOut(limits.timeout));
+    };
+    if cgroup.is_some_and(|cgroup| cgroup.oom_killed()) {
+        return Err(BuildError::OutOfMemory(limits.memory.clone()));
+    }
+    Ok(status)
+}
+
+/// Build the bot in `bot_folder`. The build waits on processes, so it runs
+/// on a blocking thread.
+pub async fn build_bot<T: AsRef<Path>>(
+    bot_folder: T,
+    limits: &BuildLimits,
+) -> Result<(), BuildError> {
+    let path = bot_folder.as_ref().to_path_buf();
+    let limits = limits.clone();
+    tokio::task::spawn_blocking(move || build(&path, &limits))
+        .await
+        .map_err(|e| BuildError::Failed(format!("Build panicked: {}", e)))?
+}
+
+fn build(path: &Path, limits: &BuildLimits) -> Result<(), BuildError> {
+    log::info!("Constructing bot in {:?}", path);
+
+    extract_archive(std::fs::File::open(path.join("bot.zip"))?, path, limits)
+        .map_err(BuildError::Archive)?;
+    let name = format!(
+        "build-{}",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    );
+    let cgroup = match Cgroup::create(&name, limits) {
+        Ok(cgroup) => Some(cgroup),
+        Err(e) => {
+            log::warn!("Building without resource limits: {}", e);
+            None
+        }
+    };
 
     // This fails if the bot folder is not named bot.
     // This should be guaranteed by the server.
 
     // Read build command from bot.json
     let bot_json: BotJson = {
-        let json = fs::read_to_string(path.join("bot/bot.json")).await?;
+        let json = std::fs::read_to_string(path.join("bot/bot.json"))?;
         serde_json::from_str::<BotJson>(&json)?
     };
 
     std::fs::write(
         path.join("bot/build.sh"),
-        bot_json.build.unwrap_or_default(),
+        bot_json.build_script().unwrap_or_default(),
     )
     .expect("write to build.sh failed");
     let chown_result = subprocess::Popen::create(
@@ -75,23 +210,16 @@ pub async fn build_bot<T: AsRef<Path>>(bot_folder: T) -> Result<(), io::Error> {
         )),
     };
 
-    let build_result = subprocess::Popen::create(
-        &["su", "builder", "-c","bwrap --unshare-all --die-with-parent --dir /tmp --ro-bind /usr /usr --proc /proc --dev /dev --ro-bind /lib /lib --ro-bind /usr/bin /usr/bin --ro-bind /bin /bin --bind . /home/builder --chdir /home/builder ./build.sh" ],
-        PopenConfig {
-            cwd: Some(path.join("bot").into()),
-            stderr: subprocess::Redirection::Merge,
-            stdout: subprocess::Redirection::File(std::fs::File::create(path.join("logs"))?),
-            ..Default::default()
-        },
-    )
-    .and_then(|mut p| p.wait());
-
-    match build_result {
-        Ok(status) if status.success() => Ok(()),
-        _ => Err(io::Error::new(
-            io::ErrorKind::Other,
-            format!("Build failed: {:?}", build_result),
-        )),
+    let status = run_limited(
+        &["su", "builder", "-c", &format!("bwrap --unshare-all --die-with-parent --dir /tmp --ro-bind /usr /usr --proc /proc --dev /dev --ro-bind /lib /lib --ro-bind /usr/bin /usr/bin --ro-bind /bin /bin {}--bind . /home/builder --chdir /home/builder ./build.sh", mirror_bind()) ],
+        &path.join("bot"),
+        &path.join("logs"),
+        cgroup.as_ref(),
+        limits,
+    )?;
+    match status {
+        status if status.success() => Ok(()),
+        status => Err(BuildError::Failed(format!("Build failed: {:?}", status))),
     }
 }
 pub async fn download_bot<T: Into<String>, U: Into<PathBuf>, V: Into<String>>(
@@ -122,6 +250,7 @@ mod tests {
     use std::io;
 
     use super::build_bot;
+    use crate::limits::BuildLimits;
     use rand::Rng;
     use tokio::fs;
 
@@ -136,7 +265,7 @@ mod tests {
         )
         .await
         .unwrap();
-        build_bot(format!("/tmp/{}", test_id))
+        build_bot(format!("/tmp/{}", test_id), &BuildLimits::from_env())
             .await
             .map(move |_| test_id)
     }
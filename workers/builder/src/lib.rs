@@ -1 +1,2 @@
 pub mod bots;
+pub mod limits;
@@ -0,0 +1,284 @@
+use std::{
+    fs,
+    io::{self, Read},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+/// The resources a single build may use
+#[derive(Debug, Clone)]
+pub struct BuildLimits {
+    /// Wall-clock time for running the build script
+    pub timeout: Duration,
+    /// Written to the build cgroup's `memory.max`
+    pub memory: String,
+    /// Number of cores the build may use
+    pub cpus: f64,
+    /// Total size of the files extracted from bot.zip, in bytes
+    pub max_uncompressed_size: u64,
+    pub max_files: usize,
+}
+
+impl BuildLimits {
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str, default: T) -> T {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        }
+        BuildLimits {
+            timeout: Duration::from_secs(var("BUILD_TIMEOUT_SECS", 300)),
+            memory: var("BUILD_MEMORY_LIMIT", "2G".to_string()),
+            cpus: var("BUILD_CPU_LIMIT", 1.0),
+            max_uncompressed_size: var("BOT_MAX_UNCOMPRESSED_SIZE", 500_000_000),
+            max_files: var("BOT_MAX_FILES", 10_000),
+        }
+    }
+}
+
+/// Why an archive was rejected
+#[derive(Debug)]
+pub enum ArchiveError {
+    TooLarge { size: u64, limit: u64 },
+    TooManyFiles { count: usize, limit: usize },
+    Invalid(String),
+    Io(io::Error),
+}
+
+impl From<io::Error> for ArchiveError {
+    fn from(e: io::Error) -> Self {
+        ArchiveError::Io(e)
+    }
+}
+
+/// Check the sizes in bot.zip's central directory, so that an obvious zip bomb
+/// is rejected before anything is extracted
+pub fn check_archive<R: Read + io::Seek>(
+    archive: R,
+    limits: &BuildLimits,
+) -> Result<(), ArchiveError> {
+    open_archive(archive, limits).map(|_| ())
+}
+
+fn open_archive<R: Read + io::Seek>(
+    archive: R,
+    limits: &BuildLimits,
+) -> Result<zip::ZipArchive<R>, ArchiveError> {
+    let mut archive =
+        zip::ZipArchive::new(archive).map_err(|e| ArchiveError::Invalid(e.to_string()))?;
+    if archive.len() > limits.max_files {
+        return Err(ArchiveError::TooManyFiles {
+            count: archive.len(),
+            limit: limits.max_files,
+        });
+    }
+    let mut size: u64 = 0;
+    for i in 0..archive.len() {
+        let file = archive
+            .by_index_raw(i)
+            .map_err(|e| ArchiveError::Invalid(e.to_string()))?;
+        size = size.saturating_add(file.size());
+        if size > limits.max_uncompressed_size {
+            return Err(ArchiveError::TooLarge {
+                size,
+                limit: limits.max_uncompressed_size,
+            });
+        }
+    }
+    Ok(archive)
+}
+
+/// Extract bot.zip into `dest` after [check_archive]. The bytes actually
+/// written are counted too, since an entry can inflate to more than it claims.
+pub fn extract_archive<R: Read + io::Seek>(
+    archive: R,
+    dest: &Path,
+    limits: &BuildLimits,
+) -> Result<(), ArchiveError> {
+    let mut archive = open_archive(archive, limits)?;
+    let mut written: u64 = 0;
+    for i in 0..archive.len() {
+        let mut file = archive
+            .by_index(i)
+            .map_err(|e| ArchiveError::Invalid(e.to_string()))?;
+        let path = file
+            .enclosed_name()
+            .map(|name| dest.join(name))
+            .ok_or_else(|| ArchiveError::Invalid(format!("{} is outside the bot", file.name())))?;
+        if file.is_dir() {
+            fs::create_dir_all(&path)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let remaining = limits.max_uncompressed_size - written;
+        written += io::copy(
+            &mut (&mut file).take(remaining + 1),
+            &mut fs::File::create(&path)?,
+        )?;
+        if written > limits.max_uncompressed_size {
+            return Err(ArchiveError::TooLarge {
+                size: written,
+                limit: limits.max_uncompressed_size,
+            });
+        }
+        if let Some(mode) = file.unix_mode() {
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode & 0o777))?;
+        }
+    }
+    Ok(())
+}
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Move the container's processes out of the root cgroup so that it can hand the
+/// memory and cpu controllers to its children. Builds run without cgroup limits if
+/// this fails, e.g. outside of a privileged container or without cgroup v2.
+pub fn init_cgroups() -> io::Result<()> {
+    let root = Path::new(CGROUP_ROOT);
+    let own = root.join("builder");
+    fs::create_dir_all(&own)?;
+    // Includes the init process if the container has one
+    for pid in fs::read_to_string(root.join("cgroup.procs"))?.lines() {
+        fs::write(own.join("cgroup.procs"), pid)?;
+    }
+    fs::write(root.join("cgroup.subtree_control"), "+memory +cpu")
+}
+
+/// A cgroup that a build's processes are placed in
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    pub fn create(name: &str, limits: &BuildLimits) -> io::Result<Self> {
+        let path = Path::new(CGROUP_ROOT).join(name);
+        fs::create_dir_all(&path)?;
+        let cgroup = Cgroup { path };
+        fs::write(cgroup.path.join("memory.max"), &limits.memory)?;
+        fs::write(cgroup.path.join("memory.swap.max"), "0")?;
+        let period = 100_000;
+        fs::write(
+            cgroup.path.join("cpu.max"),
+            format!("{} {}", (limits.cpus * period as f64) as u64, period),
+        )?;
+        Ok(cgroup)
+    }
+
+    /// Wrap a command so that it enters the cgroup before it starts
+    pub fn wrap(&self, command: &[&str]) -> Vec<String> {
+        let mut wrapped = vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            "echo $$ > \"$1\" && shift && exec \"$@\"".to_string(),
+            "sh".to_string(),
+            self.path.join("cgroup.procs").display().to_string(),
+        ];
+        wrapped.extend(command.iter().map(|s| s.to_string()));
+        wrapped
+    }
+
+    /// Whether a process in the cgroup was killed for using too much memory
+    pub fn oom_killed(&self) -> bool {
+        fs::read_to_string(self.path.join("memory.events"))
+            .map(|events| {
+                events.lines().any(|line| {
+                    line.strip_prefix("oom_kill ")
+                        .and_then(|n| n.trim().parse::<u64>().ok())
+                        .is_some_and(|n| n > 0)
+                })
+            })
+            .unwrap_or(false)
+    }
+
+    /// Kill every process left in the cgroup
+    pub fn kill(&self) {
+        if let Err(e) = fs::write(self.path.join("cgroup.kill"), "1") {
+            log::error!("Failed to kill build processes: {}", e);
+        }
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        self.kill();
+        // The kernel removes the processes asynchronously
+        for _ in 0..50 {
+            if fs::remove_dir(&self.path).is_ok() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        log::warn!("Failed to remove cgroup {:?}", self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use super::*;
+
+    fn archive(files: usize, size: usize) -> Cursor<Vec<u8>> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::FileOptions::default()
+            .compression_method(zip::CompressionMethod::Deflated);
+        for i in 0..files {
+            writer.start_file(format!("bot/{}", i), options).unwrap();
+            writer.write_all(&vec![0; size]).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    fn limits() -> BuildLimits {
+        BuildLimits {
+            max_uncompressed_size: 1000,
+            max_files: 10,
+            ..BuildLimits::from_env()
+        }
+    }
+
+    #[test]
+    fn accepts_small_archives() {
+        assert!(check_archive(archive(10, 100), &limits()).is_ok());
+    }
+
+    #[test]
+    fn rejects_highly_compressed_archives() {
+        assert!(matches!(
+            check_archive(archive(1, 100_000), &limits()),
+            Err(ArchiveError::TooLarge { .. })
+        ));
+    }
+
+    #[test]
+    fn extracts_archives_within_the_limits() {
+        let dest = std::env::temp_dir().join(format!("extract-{}", std::process::id()));
+        extract_archive(archive(3, 100), &dest, &limits()).unwrap();
+        assert_eq!(fs::read(dest.join("bot/2")).unwrap(), vec![0; 100]);
+        fs::remove_dir_all(dest).unwrap();
+    }
+
+    #[test]
+    fn counts_the_bytes_actually_extracted() {
+        // Claim that the entry is smaller than it is
+        let mut zip = archive(1, 5000).into_inner();
+        let header = zip.windows(4).rposition(|w| w == [0x50, 0x4b, 0x01, 0x02]).unwrap();
+        zip[header + 24..header + 28].copy_from_slice(&10u32.to_le_bytes());
+        let dest = std::env::temp_dir().join(format!("extract-bomb-{}", std::process::id()));
+        let result = extract_archive(Cursor::new(zip), &dest, &limits());
+        fs::remove_dir_all(dest).ok();
+        assert!(matches!(result, Err(ArchiveError::TooLarge { .. })));
+    }
+
+    #[test]
+    fn rejects_too_many_files() {
+        assert!(matches!(
+            check_archive(archive(11, 1), &limits()),
+            Err(ArchiveError::TooManyFiles { count: 11, limit: 10 })
+        ));
+    }
+}
@@ -1,13 +1,17 @@
 use std::{error::Error, process::Stdio, sync::Arc};
 
-use builder::bots::build_bot;
-use shared::{sqs::listen_on_queue, BuildStatus, BuildTask};
+use builder::{
+    bots::{build_bot, BuildError},
+    limits::{init_cgroups, BuildLimits},
+};
+use shared::{sqs::listen_on_queue, BuildResultMessage, BuildStatus, BuildTask};
 use tokio::{fs, process::Command};
 
 async fn process(
     BuildTask { bot, log_presigned }: &BuildTask,
     s3: &aws_sdk_s3::Client,
     reqwest_client: &reqwest::Client,
+    limits: &BuildLimits,
 ) -> Result<(), Box<dyn Error>> {
     let bot_bucket = std::env::var("BOT_S3_BUCKET")?;
     let compiled_bot_bucket = std::env::var("COMPILED_BOT_S3_BUCKET")?;
@@ -32,9 +36,14 @@ async fn process(
         &s3,
     )
     .await?;
-    let result = build_bot(bot_path).await;
-    // upload the logs
-    let log = fs::read(format!("/tmp/{}/logs", bot)).await?;
+    let result = build_bot(bot_path, limits).await;
+    // upload the logs, with the reason the build failed at the end
+    let mut log = fs::read(format!("/tmp/{}/logs", bot))
+        .await
+        .unwrap_or_default();
+    if let Err(e) = &result {
+        log.extend_from_slice(format!("\n{}\n", e).as_bytes());
+    }
     match reqwest_client
         .put(&log_presigned.url)
         .headers(log_presigned.headers.clone().into())
@@ -82,6 +91,33 @@ async fn process(
     Ok(())
 }
 
+/// Report a change of the bot's status to the results worker
+async fn send_status(sqs: &aws_sdk_sqs::Client, message: &BuildResultMessage) -> bool {
+    let body = match serde_json::to_string(message) {
+        Ok(body) => body,
+        Err(e) => {
+            log::error!("Error serializing message {}", e);
+            return false;
+        }
+    };
+    let sent = match std::env::var("BUILD_RESULTS_QUEUE_URL") {
+        Ok(url) => sqs
+            .send_message()
+            .queue_url(url)
+            .message_body(body)
+            .send()
+            .await
+            .is_ok(),
+        Err(_) => false,
+    };
+    if sent {
+        log::info!("Message sent.");
+    } else {
+        log::error!("Error sending message.");
+    }
+    sent
+}
+
 #[tokio::main]
 async fn main() {
     env_logger::init();
@@ -90,6 +126,10 @@ async fn main() {
     let s3 = Arc::from(shared::s3_client(&config).await);
     let sqs = Arc::from(shared::sqs_client(&config).await);
     let reqwest_client = Arc::from(reqwest::Client::new());
+    let limits = Arc::new(BuildLimits::from_env());
+    if let Err(e) = init_cgroups() {
+        log::warn!("Could not set up cgroups, builds will not be limited: {}", e);
+    }
     log::info!("Listening for messages.");
     listen_on_queue(
         std::env::var("BOT_UPLOADS_QUEUE_URL").unwrap(),
@@ -98,49 +138,31 @@ async fn main() {
             let s3 = s3.clone();
             let reqwest_client = reqwest_client.clone();
             let sqs = sqs.clone();
+            let limits = limits.clone();
 
             async move {
                 log::warn!("Received build task for {}", task.bot);
-                // TODO: send a message when the build starts
-                // right now we just send a message when it finishes
-                let result = process(&task, &s3, &reqwest_client).await;
+                // A missing progress event isn't worth failing the build over
+                send_status(
+                    &sqs,
+                    &shared::BuildResultMessage::new(task.bot, BuildStatus::Building, None),
+                )
+                .await;
+                let result = process(&task, &s3, &reqwest_client, &limits).await;
 
-                let message = shared::BuildResultMessage {
-                    bot: task.bot,
-                    status: if result.is_ok() {
-                        BuildStatus::BuildSucceeded
-                    } else {
-                        BuildStatus::BuildFailed
-                    },
-                    error: if result.is_err() {
-                        Some(format!("{}", result.unwrap_err()))
-                    } else {
-                        None
+                let message = shared::BuildResultMessage::new(
+                    task.bot,
+                    match &result {
+                        Ok(()) => BuildStatus::BuildSucceeded,
+                        Err(e) => e
+                            .downcast_ref::<BuildError>()
+                            .map(BuildError::status)
+                            .unwrap_or(BuildStatus::BuildFailed),
                     },
-                };
+                    result.err().map(|e| format!("{}", e)),
+                );
                 log::info!("Completed build: {:?}", message);
-                let body = serde_json::to_string(&message);
-                if let Ok(s) = body {
-                    if match std::env::var("BUILD_RESULTS_QUEUE_URL") {
-                        Ok(url) => sqs
-                            .send_message()
-                            .queue_url(url)
-                            .message_body(s)
-                            .send()
-                            .await
-                            .is_err(),
-                        Err(_) => true,
-                    } {
-                        log::error!("Error sending message.");
-                        return false;
-                    } else {
-                        log::info!("Message sent.");
-                    }
-                } else if let Err(e) = body {
-                    log::error!("Error serializing message {}", e);
-                    return false;
-                }
-                true
+                send_status(&sqs, &message).await
             }
         },
         |e| {
@@ -1,49 +1,89 @@
 use aws_sdk_s3::primitives::ByteStreamError;
-use futures_lite::AsyncReadExt;
 use itertools::Itertools;
 
 use rand::{thread_rng, Rng};
 use shared::poker::game::GameStateSQL;
-use shared::{BotJson, GameError, WhichBot};
-use std::env;
-use std::process::ChildStderr;
+use shared::{BotJson, BotTransport, GameConfig, GameError, HouseBot, Participant, WhichBot};
+use std::collections::VecDeque;
+use std::sync::Arc;
 use std::{
     path::{Path, PathBuf},
     process::Stdio,
     time::Duration,
 };
-use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt};
 use tokio::{
     fs,
     io::{self, BufReader},
-    process::{ChildStdout, Command},
+    net::UnixListener,
+    process::Command,
+    sync::Mutex,
+    task::JoinHandle,
+    join,
     time::Instant,
     try_join,
 };
 
-use crate::communication::{parse_action, EngineCommunication};
+use shared::poker::protocol::{parse_action, render_action, EngineCommunication};
+use crate::house::Strategy;
+use crate::pool::WarmPool;
 use shared::poker::game::{Action, GameState, PlayerPosition, Round};
+use shared::poker::hands::Card;
 
-pub async fn download_and_run<T: Into<String>, U: Into<String>, V: Into<PathBuf>>(
-    bot: U,
-    bot_path: V,
+/// How long a warm bot has to answer `NEWMATCH` with `READY`
+const RESET_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a bot using the socket transport has to connect to the engine
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Where the engine's socket is found inside the sandbox
+const SOCKET_NAME: &str = "engine.sock";
+
+pub type BotReader = BufReader<Box<dyn AsyncRead + Send + Unpin>>;
+pub type BotWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
+/// The stderr log of a bot for the match it is currently playing
+struct StderrLog {
+    file: tokio::fs::File,
+    start_time: Instant,
+}
+
+/// A bot the engine plays against and the pipes used to talk to it.
+/// Uploaded bots run in a sandbox; remote bots connected through the
+/// website have none. Bots that set `warm_start` in their bot.json can be
+/// kept alive between matches, see [WarmPool], and bots that set
+/// `game_config` are sent the rules of each match before its first hand. House bots are played by the
+/// engine itself, see [crate::house].
+pub struct BotProcess {
+    pub bot: i32,
+    pub warm_start: bool,
+    pub game_config: bool,
+    // stdin/stdout, the engine socket if the bot asked for it, or the relay
+    reader: Option<BotReader>,
+    writer: Option<BotWriter>,
+    sandbox: Option<Sandbox>,
+    strategy: Option<Box<dyn Strategy>>,
+}
+
+/// The process of an uploaded bot, and where its files and logs live.
+/// The process is killed if the sandbox is dropped without being stopped.
+struct Sandbox {
+    dir: TmpDir,
+    child: tokio::process::Child,
+    stderr_log: Arc<Mutex<StderrLog>>,
+    log_handles: Vec<JoinHandle<()>>,
+}
+
+pub async fn download_and_run<T: Into<String>>(
+    bot: i32,
     bot_bucket: T,
     s3_client: &aws_sdk_s3::Client,
-) -> Result<tokio::process::Child, anyhow::Error> {
-    let bot_path: PathBuf = bot_path.into();
-    Command::new("mount")
-        .arg("-t")
-        .arg("tmpfs")
-        .arg("-o")
-        .arg("rw,size=2G")
-        .arg(format!("{}", bot_path.display()))
-        .arg(&bot_path)
-        .stderr(Stdio::null())
-        .stdout(Stdio::null())
-        .status()
-        .await?;
+) -> Result<BotProcess, anyhow::Error> {
+    // The bot directory isn't tied to a game, since warm bots outlive the game
+    // they were started for
+    let mut dir = TmpDir::create(format!("bot-{:x}", rand::thread_rng().gen::<u32>())).await?;
+    dir.mount_tmpfs().await?;
+    let bot_path = dir.path().to_path_buf();
     shared::s3::download_file(
-        &bot.into(),
+        &bot.to_string(),
         &bot_path.join("bot.zip"),
         &bot_bucket.into(),
         &s3_client,
@@ -72,13 +112,15 @@ pub async fn download_and_run<T: Into<String>, U: Into<String>, V: Into<PathBuf>
     .await?;
     log::debug!("Read json");
 
-    let log_file = Stdio::from(std::fs::File::create(bot_path.join("logs")).map_err(|e| {
-        io::Error::new(
-            io::ErrorKind::Other,
-            format!("Failed to create log file: {}", e),
-        )
-    })?);
-    std::fs::write(bot_path.join("bot/run.sh"), bot_json.run).expect("write to build.sh failed");
+    let run = bot_json
+        .run_command()
+        .ok_or_else(|| anyhow::anyhow!("bot.json has no run command or language"))?;
+    std::fs::write(bot_path.join("bot/run.sh"), run).expect("write to build.sh failed");
+    // Bind before the chown so the bot is allowed to connect
+    let listener = match bot_json.transport {
+        BotTransport::Stdio => None,
+        BotTransport::Socket => Some(UnixListener::bind(bot_path.join("bot").join(SOCKET_NAME))?),
+    };
     Command::new("chown")
         .arg("-R")
         .arg("runner:runner")
@@ -93,7 +135,7 @@ pub async fn download_and_run<T: Into<String>, U: Into<String>, V: Into<PathBuf>
         .status()
         .await?;
 
-    Ok(Command::new("bwrap")
+    let mut child = Command::new("bwrap")
         .args([
             "--unshare-all",
             "--die-with-parent",
@@ -120,22 +162,192 @@ pub async fn download_and_run<T: Into<String>, U: Into<String>, V: Into<PathBuf>
             "/home/runner",
             "--chdir",
             "/home/runner",
+            "--setenv",
+            "ENGINE_SOCKET",
+            &format!("/home/runner/{}", SOCKET_NAME),
             "./run.sh",
         ])
         .current_dir(&bot_path.join("bot"))
         .uid(1000)
         .gid(1000)
         .process_group(0)
+        .kill_on_drop(true)
         .stderr(Stdio::piped())
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
-        .spawn()?)
+        .spawn()?;
+
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("Could not unwrap bot's stdout"))?;
+    let stderr = child
+        .stderr
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("Could not unwrap bot's stderr"))?;
+    let stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("Could not unwrap bot's stdin"))?;
+    let stderr_log = Arc::new(Mutex::new(StderrLog {
+        file: tokio::fs::File::create(bot_path.join("logs")).await?,
+        start_time: Instant::now(),
+    }));
+    let mut log_handles = vec![tokio::spawn(write_std_err(stderr, stderr_log.clone()))];
+
+    let (reader, writer): (Box<dyn AsyncRead + Send + Unpin>, BotWriter) = match listener {
+        None => (Box::new(stdout), Box::new(stdin)),
+        Some(listener) => {
+            // stdout is free for debug output, so log it with stderr
+            log_handles.push(tokio::spawn(write_std_err(stdout, stderr_log.clone())));
+            match tokio::time::timeout(CONNECT_TIMEOUT, listener.accept()).await {
+                Ok(accepted) => {
+                    let (stream, _) = accepted?;
+                    log::debug!("Bot connected to socket");
+                    let (read_half, write_half) = stream.into_split();
+                    (Box::new(read_half), Box::new(write_half))
+                }
+                Err(_) => {
+                    log::info!("Bot {} did not connect to {}", bot, SOCKET_NAME);
+                    let mut process = BotProcess {
+                        bot,
+                        warm_start: false,
+                        game_config: false,
+                        reader: None,
+                        writer: None,
+                        sandbox: Some(Sandbox {
+                            dir,
+                            child,
+                            stderr_log,
+                            log_handles,
+                        }),
+                        strategy: None,
+                    };
+                    process.stop().await;
+                    let log = process.read_logs().await?;
+                    process.remove().await?;
+                    return Err(NotConnected(log).into());
+                }
+            }
+        }
+    };
+
+    Ok(BotProcess {
+        bot,
+        warm_start: bot_json.warm_start,
+        game_config: bot_json.game_config,
+        reader: Some(BufReader::new(reader)),
+        writer: Some(writer),
+        sandbox: Some(Sandbox {
+            dir,
+            child,
+            stderr_log,
+            log_handles,
+        }),
+        strategy: None,
+    })
 }
 
+/// A bot using the socket transport that never connected to the engine,
+/// with what it wrote to stderr. The bot is to blame, unlike other errors
+/// starting it.
+#[derive(Debug)]
+pub struct NotConnected(pub Vec<u8>);
+
+impl std::fmt::Display for NotConnected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Bot did not connect to {}", SOCKET_NAME)
+    }
+}
+
+impl std::error::Error for NotConnected {}
+
 extern "C" {
     fn kill(pid: i32, sig: i32) -> i32;
 }
 
+/// A directory under /tmp for a bot or a game. Bots get a tmpfs mounted on
+/// theirs. It is removed when dropped, so that nothing is left behind when a
+/// game fails part way; [TmpDir::remove] does the same but reports errors.
+pub(crate) struct TmpDir {
+    path: PathBuf,
+    mounted: bool,
+    removed: bool,
+}
+
+impl TmpDir {
+    pub(crate) async fn create(name: String) -> Result<Self, io::Error> {
+        let path = Path::new("/tmp").join(name);
+        fs::create_dir_all(&path).await?;
+        Ok(TmpDir {
+            path,
+            mounted: false,
+            removed: false,
+        })
+    }
+
+    async fn mount_tmpfs(&mut self) -> Result<(), io::Error> {
+        Command::new("mount")
+            .arg("-t")
+            .arg("tmpfs")
+            .arg("-o")
+            .arg("rw,size=2G")
+            .arg(format!("{}", self.path.display()))
+            .arg(&self.path)
+            .stderr(Stdio::null())
+            .stdout(Stdio::null())
+            .status()
+            .await?;
+        self.mounted = true;
+        Ok(())
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) async fn remove(mut self) -> Result<(), io::Error> {
+        self.removed = true;
+        if self.mounted {
+            Command::new("umount")
+                .arg("-l")
+                .arg(format!("{}", self.path.display()))
+                .stdout(Stdio::null())
+                .status()
+                .await?;
+        }
+        fs::remove_dir_all(&self.path).await
+    }
+}
+
+impl Drop for TmpDir {
+    fn drop(&mut self) {
+        if self.removed {
+            return;
+        }
+        let (path, mounted) = (self.path.clone(), self.mounted);
+        let cleanup = move || {
+            if mounted {
+                std::process::Command::new("umount")
+                    .arg("-l")
+                    .arg(&path)
+                    .stdout(Stdio::null())
+                    .status()
+                    .ok();
+            }
+            if let Err(e) = std::fs::remove_dir_all(&path) {
+                log::error!("Failed to remove {}: {}", path.display(), e);
+            }
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn_blocking(cleanup);
+            }
+            Err(_) => cleanup(),
+        }
+    }
+}
+
 pub struct GameResult {
     pub status: Result<shared::GameStatus, GameError>,
     pub defender_log: Vec<u8>,
@@ -144,127 +356,349 @@ pub struct GameResult {
     pub game_record: Vec<u8>,
 }
 
-async fn write_std_err(
-    stderr: tokio::process::ChildStderr,
-    bot_path: PathBuf,
-    start_time: Instant,
-) -> Result<(), io::Error> {
+async fn write_std_err<R: AsyncRead + Unpin>(stderr: R, stderr_log: Arc<Mutex<StderrLog>>) {
     let mut reader = io::BufReader::new(stderr);
     let mut buffer = String::new();
-    let mut stderr_file = tokio::fs::File::create(bot_path.join("logs")).await?;
-
-    reader.buffer().read_to_string(&mut buffer).await?;
 
     while let Ok(n) = reader.read_line(&mut buffer).await {
         if n == 0 {
             break;
         }
 
+        let mut stderr_log = stderr_log.lock().await;
         let timestamp = tokio::time::Instant::now()
-            .duration_since(start_time)
+            .duration_since(stderr_log.start_time)
             .as_millis();
         let formatted_line = format!("[{}] {}", timestamp, buffer);
-        stderr_file.write_all(formatted_line.as_bytes()).await?;
+        if let Err(e) = stderr_log.file.write_all(formatted_line.as_bytes()).await {
+            log::error!("Error writing bot stderr to log: {}", e);
+            break;
+        }
 
         buffer.clear();
     }
+}
 
-    Ok(())
+impl BotProcess {
+    /// A bot that doesn't run in a sandbox, talking over the given pipes,
+    /// like a remote bot connected through the website.
+    /// These bots aren't uploaded, so they have no bot id.
+    pub fn from_pipes(reader: Box<dyn AsyncRead + Send + Unpin>, writer: BotWriter) -> Self {
+        BotProcess {
+            bot: 0,
+            warm_start: false,
+            game_config: false,
+            reader: Some(BufReader::new(reader)),
+            writer: Some(writer),
+            sandbox: None,
+            strategy: None,
+        }
+    }
+
+    pub fn house(bot: HouseBot) -> Self {
+        Self::with_strategy(crate::house::strategy(bot))
+    }
+
+    /// A bot played by the engine. The engine asks its strategy for actions
+    /// instead of writing to it, so its pipes go nowhere.
+    pub fn with_strategy(strategy: Box<dyn Strategy>) -> Self {
+        BotProcess {
+            bot: 0,
+            warm_start: false,
+            game_config: false,
+            reader: Some(BufReader::new(Box::new(io::empty()))),
+            writer: Some(Box::new(io::sink())),
+            sandbox: None,
+            strategy: Some(strategy),
+        }
+    }
+
+    /// Send a signal to the sandbox's whole process group
+    fn signal(&self, sig: i32) {
+        if let Some(sandbox) = &self.sandbox {
+            let pid = sandbox.child.id().unwrap_or_default() as i32;
+            unsafe {
+                kill(-pid, sig);
+            }
+        }
+    }
+
+    /// Stop the whole process group, e.g. while the opponent is thinking
+    pub fn pause(&self) {
+        self.signal(19);
+    }
+
+    /// Resume the process group after [BotProcess::pause]
+    pub fn resume(&self) {
+        self.signal(18);
+    }
+
+    pub fn is_alive(&mut self) -> bool {
+        match &mut self.sandbox {
+            Some(sandbox) => matches!(sandbox.child.try_wait(), Ok(None)),
+            None => self.writer.is_some(),
+        }
+    }
+
+    /// Start a fresh stderr log, so that a match only sees what the bot wrote during it
+    pub async fn begin_match(&mut self, start_time: Instant) -> Result<(), io::Error> {
+        if let Some(sandbox) = &self.sandbox {
+            let mut stderr_log = sandbox.stderr_log.lock().await;
+            stderr_log.file = tokio::fs::File::create(sandbox.dir.path().join("logs")).await?;
+            stderr_log.start_time = start_time;
+        }
+        Ok(())
+    }
+
+    /// The stderr written by the bot since the last call to [BotProcess::begin_match].
+    /// Remote bots keep their own logs.
+    pub async fn read_logs(&self) -> Result<Vec<u8>, io::Error> {
+        match &self.sandbox {
+            Some(sandbox) => {
+                let mut stderr_log = sandbox.stderr_log.lock().await;
+                stderr_log.file.flush().await?;
+                tokio::fs::read(sandbox.dir.path().join("logs")).await
+            }
+            None => Ok(vec![]),
+        }
+    }
+
+    async fn write_line(&mut self, line: &str) -> Result<(), io::Error> {
+        let writer = self.writer.as_mut().ok_or(io::Error::new(
+            io::ErrorKind::BrokenPipe,
+            "Bot has no stdin",
+        ))?;
+        writer.write_all(format!("{}\n", line).as_bytes()).await?;
+        writer.flush().await
+    }
+
+    /// Tell a warm bot that a new match is starting.
+    /// The bot must answer `READY` once it has cleared its state.
+    pub async fn reset(&mut self) -> Result<(), anyhow::Error> {
+        self.write_line(&EngineCommunication::NewMatch.render_for_bot(PlayerPosition::SmallBlind))
+            .await?;
+        let reader = self
+            .reader
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("Bot has no stdout"))?;
+        let mut line = String::new();
+        tokio::time::timeout(RESET_TIMEOUT, reader.read_line(&mut line)).await??;
+        if line.trim() != "READY" {
+            return Err(anyhow::anyhow!(
+                "Expected READY after NEWMATCH, got {:?}",
+                line.trim()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Kill the bot and wait for the rest of its stderr to be logged.
+    /// Remote bots are disconnected instead.
+    pub async fn stop(&mut self) {
+        self.resume();
+        self.reader = None;
+        self.writer = None;
+        let Some(sandbox) = &mut self.sandbox else {
+            return;
+        };
+        if let Err(e) = sandbox.child.kill().await {
+            log::error!("Failed to kill bot {}: {}", self.bot, e);
+        }
+        for handle in sandbox.log_handles.iter_mut() {
+            if let Err(e) = handle.await {
+                log::error!("Failed to finish writing logs for bot {}: {}", self.bot, e);
+            }
+        }
+    }
+
+    /// Remove the bot's files. The bot must be stopped first.
+    pub async fn remove(self) -> Result<(), anyhow::Error> {
+        let Some(sandbox) = self.sandbox else {
+            return Ok(());
+        };
+        sandbox.dir.remove().await?;
+        Ok(())
+    }
 }
 
-pub async fn run_game(
-    defender: i32,
-    challenger: i32,
+/// Start a bot for a game, reusing a warm process if the pool has one
+async fn acquire(
+    participant: Participant,
+    warm: Option<BotProcess>,
+    bot_bucket: &str,
     s3_client: &aws_sdk_s3::Client,
-    task_id: &String,
-    rounds: usize,
-) -> Result<GameResult, anyhow::Error> {
-    // create tmp directory
-    // doesn't have the same id as the task
-    let game_id = format!("{:x}", rand::thread_rng().gen::<u32>());
-    let tmp_dir = Path::new("/tmp").join(&game_id);
-
-    log::debug!("Playing {} against {}", defender, challenger);
-    log::info!("Running game {} with local id {}", task_id, game_id);
-    let bot_bucket = std::env::var("COMPILED_BOT_S3_BUCKET")?;
-    log::debug!("Bot bucket: {}", bot_bucket);
+) -> Result<BotProcess, anyhow::Error> {
+    match (participant, warm) {
+        (Participant::House(bot), _) => Ok(BotProcess::house(bot)),
+        (Participant::Bot(bot), Some(process)) => {
+            log::debug!("Reusing warm process for bot {}", bot);
+            Ok(process)
+        }
+        (Participant::Bot(bot), None) => download_and_run(bot, bot_bucket, s3_client).await,
+    }
+}
 
-    // download bots from s3
-    log::debug!("Making bot directories");
-    let defender_path = tmp_dir.join("defender");
-    fs::create_dir_all(&defender_path.clone()).await?;
-    let challenger_path = tmp_dir.join("challenger");
-    fs::create_dir_all(&challenger_path.clone()).await?;
-    log::debug!("Downloading bots from aws");
-    let (mut defender, mut challenger) = try_join!(
-        download_and_run(
-            defender.to_string(),
-            defender_path.clone(),
-            &bot_bucket,
-            s3_client
-        ),
-        download_and_run(
-            challenger.to_string(),
-            challenger_path.clone(),
-            &bot_bucket,
-            s3_client
-        )
-    )?;
+/// Take a warm process for the participant out of the pool, if it has one
+async fn checkout(participant: Participant, pool: &mut WarmPool) -> Option<BotProcess> {
+    match participant {
+        Participant::Bot(bot) => pool.checkout(bot).await,
+        Participant::House(_) => None,
+    }
+}
 
-    let def_stderr = defender
-        .stderr
-        .take()
-        .expect("Could not unwrap defender's stderr");
-    let chall_stderr = challenger
-        .stderr
-        .take()
-        .expect("Could not unwrap challenger's stderr");
+/// Collect a bot's logs after a game, then either return it to the pool
+/// or shut it down
+async fn release(
+    mut process: BotProcess,
+    reusable: bool,
+    pool: &mut WarmPool,
+) -> Result<Vec<u8>, anyhow::Error> {
+    if reusable && process.warm_start && process.is_alive() {
+        let log = process.read_logs().await?;
+        pool.checkin(process).await;
+        Ok(log)
+    } else {
+        process.stop().await;
+        let log = process.read_logs().await?;
+        process.remove().await?;
+        Ok(log)
+    }
+}
 
+/// Play a match between two started bots, writing the public log and the
+/// game record into `tmp_dir`. The first hands are dealt from `decks`, if given.
+pub(crate) async fn play_match(
+    defender: &mut BotProcess,
+    challenger: &mut BotProcess,
+    game_id: &str,
+    tmp_dir: &Path,
+    config: GameConfig,
+    decks: Vec<Vec<Card>>,
+) -> Result<Result<shared::GameStatus, GameError>, anyhow::Error> {
     let start_time = Instant::now();
-    let def_handle = tokio::spawn(write_std_err(def_stderr, defender_path.clone(), start_time));
-    let chall_handle = tokio::spawn(write_std_err(
-        chall_stderr,
-        challenger_path.clone(),
-        start_time,
-    ));
-
-    // Do other non-blocking tasks here...
+    defender.begin_match(start_time).await?;
+    challenger.begin_match(start_time).await?;
 
-    // Await the completion of the task if needed
     let mut game = Game::new(
         defender,
         challenger,
-        game_id.clone(),
-        Duration::from_secs(10),
+        game_id.to_string(),
+        config,
         tokio::fs::File::create(tmp_dir.join("logs")).await?,
         start_time,
         tokio::fs::File::create(tmp_dir.join("game_record")).await?,
-    );
+    )
+    .with_decks(decks);
 
-    let status = game.play(rounds).await;
+    let status = game.play().await;
     game.drop().await?;
-    // TODO: issues reading the logs probably shouldn't cause an internal error
-    def_handle.await;
-    chall_handle.await;
-    let defender_log = tokio::fs::read(tmp_dir.join("defender/logs")).await?;
-    let challenger_log = tokio::fs::read(tmp_dir.join("challenger/logs")).await?;
-    let public_log = tokio::fs::read(tmp_dir.join("logs")).await?;
-    let game_record = tokio::fs::read(tmp_dir.join("game_record")).await?;
-    Command::new("umount")
-        .arg("-l")
-        .arg(format!("{}", challenger_path.display()))
-        .stdout(Stdio::null())
-        .status()
-        .await?;
+    Ok(status)
+}
 
-    Command::new("umount")
-        .arg("-l")
-        .arg(format!("{}", defender_path.display()))
-        .stdout(Stdio::null())
-        .status()
-        .await?;
+/// Whether the defender and the challenger can be reused after a match.
+/// A bot that caused the game to fail is never reused.
+fn reusable(status: &Result<shared::GameStatus, GameError>) -> (bool, bool) {
+    match status {
+        Ok(_) => (true, true),
+        Err(GameError::InternalError) => (false, false),
+        Err(
+            GameError::RunTimeError(which_bot)
+            | GameError::TimeoutError(which_bot)
+            | GameError::MemoryError(which_bot)
+            | GameError::InvalidActionError(which_bot),
+        ) => (
+            *which_bot != WhichBot::Defender,
+            *which_bot != WhichBot::Challenger,
+        ),
+    }
+}
 
-    fs::remove_dir_all(tmp_dir).await?;
+/// The result of a game whose bots couldn't both be started. A bot that never
+/// connected to the engine loses, anything else is an internal error. A bot
+/// that did start is cleaned up when it is dropped.
+fn not_started(
+    defender: Option<anyhow::Error>,
+    challenger: Option<anyhow::Error>,
+) -> Result<GameResult, anyhow::Error> {
+    let mut result = GameResult {
+        status: Ok(shared::GameStatus::ScoreChanged(0, 0)),
+        defender_log: vec![],
+        challenger_log: vec![],
+        public_log: vec![],
+        game_record: vec![],
+    };
+    // If neither connected, the defender is blamed
+    for (which_bot, error) in [
+        (WhichBot::Challenger, challenger),
+        (WhichBot::Defender, defender),
+    ] {
+        let Some(error) = error else {
+            continue;
+        };
+        let NotConnected(log) = error.downcast::<NotConnected>()?;
+        match which_bot {
+            WhichBot::Defender => result.defender_log = log,
+            WhichBot::Challenger => result.challenger_log = log,
+        }
+        result.status = Err(GameError::RunTimeError(which_bot));
+        result.public_log = format!(
+            "[0] System > Ending because {} did not connect to the engine\n",
+            which_bot
+        )
+        .into_bytes();
+    }
+    Ok(result)
+}
+
+pub async fn run_game(
+    defender: Participant,
+    challenger: Participant,
+    s3_client: &aws_sdk_s3::Client,
+    task_id: &String,
+    config: GameConfig,
+    pool: &mut WarmPool,
+) -> Result<GameResult, anyhow::Error> {
+    // create tmp directory
+    // doesn't have the same id as the task
+    let game_id = format!("{:x}", rand::thread_rng().gen::<u32>());
+
+    log::debug!("Playing {:?} against {:?}", defender, challenger);
+    log::info!("Running game {} with local id {}", task_id, game_id);
+    config.validate()?;
+    let bot_bucket = std::env::var("COMPILED_BOT_S3_BUCKET")?;
+    log::debug!("Bot bucket: {}", bot_bucket);
+
+    // Bots and directories left behind by an early return are cleaned up when
+    // they are dropped
+    let tmp_dir = TmpDir::create(game_id.clone()).await?;
+    let warm_defender = checkout(defender, pool).await;
+    let warm_challenger = checkout(challenger, pool).await;
+    log::debug!("Downloading bots from aws");
+    let (mut defender, mut challenger) = match join!(
+        acquire(defender, warm_defender, &bot_bucket, s3_client),
+        acquire(challenger, warm_challenger, &bot_bucket, s3_client)
+    ) {
+        (Ok(defender), Ok(challenger)) => (defender, challenger),
+        (defender, challenger) => return not_started(defender.err(), challenger.err()),
+    };
+
+    let status = play_match(
+        &mut defender,
+        &mut challenger,
+        &game_id,
+        tmp_dir.path(),
+        config,
+        vec![],
+    )
+    .await?;
+    let (defender_reusable, challenger_reusable) = reusable(&status);
+    // TODO: issues reading the logs probably shouldn't cause an internal error
+    let defender_log = release(defender, defender_reusable, pool).await?;
+    let challenger_log = release(challenger, challenger_reusable, pool).await?;
+    let public_log = tokio::fs::read(tmp_dir.path().join("logs")).await?;
+    let game_record = tokio::fs::read(tmp_dir.path().join("game_record")).await?;
+
+    tmp_dir.remove().await?;
     Ok(GameResult {
         status,
         defender_log,
@@ -274,9 +708,60 @@ pub async fn run_game(
     })
 }
 
-pub struct Game {
-    defender: tokio::process::Child,
-    challenger: tokio::process::Child,
+/// Play an unrated match between a team's remote bot, connected to the
+/// website under `session`, and another team's active bot. The remote bot is
+/// always the challenger, and is sent the rules of the match if it asked for
+/// them with `game_config`.
+pub async fn run_remote_game(
+    opponent: i32,
+    session: &str,
+    s3_client: &aws_sdk_s3::Client,
+    config: GameConfig,
+    game_config: bool,
+    pool: &mut WarmPool,
+) -> Result<Result<shared::GameStatus, GameError>, anyhow::Error> {
+    config.validate()?;
+    let game_id = format!("{:x}", rand::thread_rng().gen::<u32>());
+    log::info!(
+        "Running remote game {} against {} with local id {}",
+        session,
+        opponent,
+        game_id
+    );
+    let bot_bucket = std::env::var("COMPILED_BOT_S3_BUCKET")?;
+
+    let tmp_dir = TmpDir::create(game_id.clone()).await?;
+    let warm_defender = pool.checkout(opponent).await;
+    let (mut defender, (mut challenger, finished)) = try_join!(
+        acquire(Participant::Bot(opponent), warm_defender, &bot_bucket, s3_client),
+        crate::remote::connect(session)
+    )?;
+    challenger.game_config = game_config;
+
+    let status = play_match(
+        &mut defender,
+        &mut challenger,
+        &game_id,
+        tmp_dir.path(),
+        config,
+        vec![],
+    )
+    .await
+        .unwrap_or_else(|e| {
+            log::error!("Remote game failed: {:?}", e);
+            Err(GameError::InternalError)
+        });
+    challenger.stop().await;
+    // The remote side learns how the game went when the relay is closed
+    finished.send(serde_json::to_string(&status)?).ok();
+    release(defender, reusable(&status).0, pool).await?;
+    tmp_dir.remove().await?;
+    Ok(status)
+}
+
+pub struct Game<'a> {
+    defender: &'a mut BotProcess,
+    challenger: &'a mut BotProcess,
     stacks: [u32; 2],
     initial_stacks: [u32; 2],
     sb: WhichBot,
@@ -289,35 +774,28 @@ pub struct Game {
     start_time: Instant,
     // I suck at this :'(
     cleaned_up: bool,
-    limit: u32,
+    config: GameConfig,
+    // Dealt before falling back to shuffled decks
+    decks: VecDeque<Vec<Card>>,
 }
 
-impl Game {
+impl<'a> Game<'a> {
     pub fn new(
-        defender: tokio::process::Child,
-        challenger: tokio::process::Child,
+        defender: &'a mut BotProcess,
+        challenger: &'a mut BotProcess,
         id: String,
-        timeout: Duration,
+        config: GameConfig,
         logs: tokio::fs::File,
         start_time: Instant,
         game_record: tokio::fs::File,
     ) -> Self {
-        let starting_stack_size = env::var("STARTING_STACK_SIZE")
-            .unwrap_or_else(|_| "500".into())
-            .parse::<u32>()
-            .unwrap_or(500);
-
-        let max_bet_size = env::var("MAX_BET_SIZE")
-            .unwrap_or_else(|_| "100".into())
-            .parse::<u32>()
-            .unwrap_or(50);
-
+        let timeout = Duration::from_millis(config.clock_ms.into());
         Self {
             defender,
             challenger,
-            stacks: [starting_stack_size, starting_stack_size],
-            initial_stacks: [starting_stack_size, starting_stack_size],
-            limit: max_bet_size,
+            stacks: [config.stack, config.stack],
+            initial_stacks: [config.stack, config.stack],
+            config,
             sb: WhichBot::Defender,
             defender_timeout: timeout,
             challenger_timeout: timeout,
@@ -326,9 +804,17 @@ impl Game {
             game_record,
             start_time,
             cleaned_up: false,
+            decks: VecDeque::new(),
         }
     }
 
+    /// Deal the first hands from the given decks instead of shuffling.
+    /// Cards are dealt from the end of a deck, see [GameState::new].
+    pub fn with_decks(mut self, decks: Vec<Vec<Card>>) -> Self {
+        self.decks = decks.into();
+        self
+    }
+
     async fn write_bot(
         &mut self,
         which_bot: WhichBot,
@@ -338,11 +824,11 @@ impl Game {
         self.write_log(format!("{} < {}", which_bot, message.clone()))
             .await?;
         let bot = match which_bot {
-            WhichBot::Defender => &mut self.defender,
-            WhichBot::Challenger => &mut self.challenger,
+            WhichBot::Defender => &mut *self.defender,
+            WhichBot::Challenger => &mut *self.challenger,
         };
-        if let Some(ref mut stdin) = bot.stdin {
-            stdin
+        if let Some(ref mut writer) = bot.writer {
+            writer
                 .write_all(format!("{}\n", message).as_bytes())
                 .await
                 .map_err(|_| {
@@ -350,7 +836,7 @@ impl Game {
                     GameError::RunTimeError(which_bot)
                 })?;
 
-            stdin.flush().await.map_err(|_| {
+            writer.flush().await.map_err(|_| {
                 log::error!("Error writing to bot");
                 GameError::RunTimeError(which_bot)
             })?;
@@ -437,18 +923,22 @@ impl Game {
 
     async fn play_round(
         &mut self,
-        defender_reader: &mut BufReader<ChildStdout>,
-        challenger_reader: &mut BufReader<ChildStdout>,
+        defender_reader: &mut BotReader,
+        challenger_reader: &mut BotReader,
         state_id: &mut i32,
     ) -> Result<GameState, shared::GameError> {
-        let mut rng = thread_rng();
-        let mut state = shared::poker::game::GameState::new(
+        let deck = self
+            .decks
+            .pop_front()
+            .unwrap_or_else(|| GameState::get_shuffled_deck(&mut thread_rng()));
+        let mut state = shared::poker::game::GameState::with_blinds(
             match self.sb {
                 WhichBot::Defender => [self.stacks[0], self.stacks[1]],
                 WhichBot::Challenger => [self.stacks[1], self.stacks[0]],
             },
-            GameState::get_shuffled_deck(&mut rng),
-            self.limit
+            deck,
+            self.config.limit,
+            [self.config.small_blind, self.config.big_blind],
         );
 
         //log::debug!("Game state: {:?}. ", state);
@@ -492,52 +982,20 @@ impl Game {
             let whose_turn: WhichBot =
                 self.get_bot_from_position(state.whose_turn().ok_or(GameError::InternalError)?);
 
-            let (target_reader, opponent_gid) = match whose_turn {
-                WhichBot::Defender => (
-                    &mut *defender_reader,
-                    self.challenger.id().unwrap_or_default(),
-                ),
-                WhichBot::Challenger => (
-                    &mut *challenger_reader,
-                    self.defender.id().unwrap_or_default(),
-                ),
+            let target_reader = match whose_turn {
+                WhichBot::Defender => &mut *defender_reader,
+                WhichBot::Challenger => &mut *challenger_reader,
             };
 
-            unsafe {
-                let status = kill(-(opponent_gid as i32), 19);
+            let position = self.get_position_from_bot(whose_turn);
+            let bot = match whose_turn {
+                WhichBot::Defender => &mut *self.defender,
+                WhichBot::Challenger => &mut *self.challenger,
+            };
+            let line = match bot.strategy.as_mut() {
+                Some(strategy) => render_action(&strategy.act(&state, position)),
+                None => self.read_action(whose_turn, &state, target_reader).await?,
             };
-            // write current game state to the bots stream
-            //log::debug!("Writing current state.");
-            self.write_bot(whose_turn, &EngineCommunication::get_betting_state(&state))
-                .await
-                .map_err(|_| {
-                    unsafe { kill(-(opponent_gid as i32), 18) };
-                    log::info!("Failed to write current state to bot {:?}.", whose_turn);
-                    GameError::RunTimeError(whose_turn)
-                })?;
-
-            //log::debug!("Reading action from {:?}.", whose_turn);
-            let mut line: String = Default::default();
-            let time_before_action = Instant::now();
-            let t = tokio::time::timeout(match whose_turn {
-                WhichBot::Defender => self.defender_timeout,
-                WhichBot::Challenger => self.challenger_timeout,
-            }, target_reader.read_line(&mut line))
-                .await
-                .map_err(|_| shared::GameError::TimeoutError(whose_turn))?
-                .map_err(|_| shared::GameError::RunTimeError(whose_turn))?;
-
-            let time_after_action = Instant::now();
-
-            // update time left
-            match whose_turn {
-                WhichBot::Defender => {
-                    self.defender_timeout -= time_after_action - time_before_action;
-                }
-                WhichBot::Challenger => {
-                    self.challenger_timeout -= time_after_action - time_before_action;
-                }
-            }
 
             self.write_log(format!("{} > {}", whose_turn, line.trim()))
                 .await?;
@@ -548,9 +1006,7 @@ impl Game {
                 .post_action(action.clone())
                 .map_err(|_| shared::GameError::InvalidActionError(whose_turn.clone()))?;
 
-            unsafe {
-                kill(-(opponent_gid as i32), 18);
-            };
+            self.get_bot(whose_turn.other()).resume();
             self.save_round(
                 &state,
                 *state_id,
@@ -564,24 +1020,89 @@ impl Game {
         Ok(state)
     }
 
+    /// Send the betting state to the bot whose turn it is and read its action,
+    /// pausing the other bot while it thinks
+    async fn read_action(
+        &mut self,
+        whose_turn: WhichBot,
+        state: &GameState,
+        target_reader: &mut BotReader,
+    ) -> Result<String, GameError> {
+        self.get_bot(whose_turn.other()).pause();
+        // write current game state to the bots stream
+        //log::debug!("Writing current state.");
+        if self
+            .write_bot(whose_turn, &EngineCommunication::get_betting_state(state))
+            .await
+            .is_err()
+        {
+            self.get_bot(whose_turn.other()).resume();
+            log::info!("Failed to write current state to bot {:?}.", whose_turn);
+            Err(GameError::RunTimeError(whose_turn))?;
+        }
+
+        //log::debug!("Reading action from {:?}.", whose_turn);
+        let mut line: String = Default::default();
+        let time_before_action = Instant::now();
+        let t = tokio::time::timeout(match whose_turn {
+            WhichBot::Defender => self.defender_timeout,
+            WhichBot::Challenger => self.challenger_timeout,
+        }, target_reader.read_line(&mut line))
+            .await
+            .map_err(|_| shared::GameError::TimeoutError(whose_turn))?
+            .map_err(|_| shared::GameError::RunTimeError(whose_turn))?;
+
+        let time_after_action = Instant::now();
+
+        // update time left
+        match whose_turn {
+            WhichBot::Defender => {
+                self.defender_timeout -= time_after_action - time_before_action;
+            }
+            WhichBot::Challenger => {
+                self.challenger_timeout -= time_after_action - time_before_action;
+            }
+        }
+        Ok(line)
+    }
+
     /// Play a game of poker, returning a [shared::GameResult]
-    pub async fn play(&mut self, rounds: usize) -> Result<shared::GameStatus, GameError> {
-        log::debug!("Playing game {} with {} rounds", self.id, rounds);
-        let mut defender_reader = BufReader::new(
-            self.defender
-                .stdout
-                .take()
-                .ok_or(GameError::RunTimeError(WhichBot::Defender))?,
-        );
-        let mut challenger_reader = BufReader::new(
-            self.challenger
-                .stdout
-                .take()
-                .ok_or(GameError::RunTimeError(WhichBot::Challenger))?,
-        );
+    pub async fn play(&mut self) -> Result<shared::GameStatus, GameError> {
+        log::debug!("Playing game {} with {:?}", self.id, self.config);
+        let mut defender_reader = self
+            .defender
+            .reader
+            .take()
+            .ok_or(GameError::RunTimeError(WhichBot::Defender))?;
+        let mut challenger_reader = self
+            .challenger
+            .reader
+            .take()
+            .ok_or(GameError::RunTimeError(WhichBot::Challenger))?;
+
+        let status = self
+            .play_rounds(&mut defender_reader, &mut challenger_reader)
+            .await;
+        // hand the readers back so that warm bots can be reused
+        self.defender.reader = Some(defender_reader);
+        self.challenger.reader = Some(challenger_reader);
+        status
+    }
 
+    async fn play_rounds(
+        &mut self,
+        defender_reader: &mut BotReader,
+        challenger_reader: &mut BotReader,
+    ) -> Result<shared::GameStatus, GameError> {
         log::info!("Clients connected for {}", self.id);
         let mut state_id: i32 = 0;
+        for which_bot in [WhichBot::Defender, WhichBot::Challenger] {
+            if self.get_bot(which_bot).game_config {
+                self.write_bot(which_bot, &EngineCommunication::Config(self.config))
+                    .await?;
+            }
+        }
+        let rounds = self.config.hands;
         for i in 0..rounds {
             if self.stacks[0] == 0 || self.stacks[1] == 0 {
                 self.write_log(format!("System > Ending because a bot has an empty stack"))
@@ -592,7 +1113,7 @@ impl Game {
                 .await?;
             //log::debug!("Playing round. Current stacks: {:?}.", self.stacks);
             match self
-                .play_round(&mut defender_reader, &mut challenger_reader, &mut state_id)
+                .play_round(defender_reader, challenger_reader, &mut state_id)
                 .await
             {
                 Err(e) => {
@@ -636,28 +1157,115 @@ impl Game {
         }
     }
 
-    pub async fn drop(&mut self) -> Result<(), anyhow::Error> {
-        if let Some(id) = self.defender.id() {
-            unsafe {
-                kill(-(id as i32), 18);
-            }
-        }
-        if let Some(id) = self.challenger.id() {
-            unsafe {
-                kill(-(id as i32), 18);
-            }
+    fn get_bot(&self, which_bot: WhichBot) -> &BotProcess {
+        match which_bot {
+            WhichBot::Defender => self.defender,
+            WhichBot::Challenger => self.challenger,
         }
+    }
 
-        self.defender.kill().await?;
-        self.challenger.kill().await?;
+    /// Resume both bots. Stopping them is left to the caller,
+    /// since warm bots are kept alive after the game.
+    pub async fn drop(&mut self) -> Result<(), anyhow::Error> {
+        self.defender.resume();
+        self.challenger.resume();
         self.cleaned_up = true;
         Ok(())
     }
 }
-impl Drop for Game {
+impl Drop for Game<'_> {
     fn drop(&mut self) {
         if !self.cleaned_up {
             panic!("Game dropped without manually calling drop")
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answering(answer: &'static [u8]) -> BotProcess {
+        BotProcess::from_pipes(Box::new(answer), Box::new(io::sink()))
+    }
+
+    #[tokio::test]
+    async fn warm_bots_must_answer_ready() {
+        assert!(answering(b"READY\n").reset().await.is_ok());
+        assert!(answering(b"CALL\n").reset().await.is_err());
+        assert!(answering(b"").reset().await.is_err());
+    }
+
+    #[test]
+    fn bots_that_fail_a_game_are_not_reused() {
+        assert_eq!(reusable(&Ok(shared::GameStatus::ScoreChanged(5, -5))), (true, true));
+        assert_eq!(reusable(&Err(GameError::InternalError)), (false, false));
+        assert_eq!(
+            reusable(&Err(GameError::TimeoutError(WhichBot::Defender))),
+            (false, true)
+        );
+        assert_eq!(
+            reusable(&Err(GameError::InvalidActionError(WhichBot::Challenger))),
+            (true, false)
+        );
+    }
+
+    #[test]
+    fn bots_that_never_connect_lose() {
+        let result = not_started(None, Some(NotConnected(b"oops".to_vec()).into())).unwrap();
+        assert!(matches!(
+            result.status,
+            Err(GameError::RunTimeError(WhichBot::Challenger))
+        ));
+        assert_eq!(result.challenger_log, b"oops");
+        assert!(not_started(Some(anyhow::anyhow!("S3 is down")), None).is_err());
+    }
+
+    #[tokio::test]
+    async fn plays_a_match_between_two_started_bots() {
+        let tmp_dir = TmpDir::create(format!("test-{:x}", thread_rng().gen::<u32>()))
+            .await
+            .unwrap();
+        let mut defender = BotProcess::house(HouseBot::CallingStation);
+        let mut challenger = BotProcess::house(HouseBot::Random);
+        let config = GameConfig {
+            hands: 20,
+            ..Default::default()
+        };
+        let status = play_match(
+            &mut defender,
+            &mut challenger,
+            "test",
+            tmp_dir.path(),
+            config,
+            vec![],
+        )
+        .await
+        .unwrap();
+        let Ok(shared::GameStatus::ScoreChanged(defender_score, challenger_score)) = status else {
+            panic!("Unexpected status {:?}", status);
+        };
+        assert_eq!(defender_score + challenger_score, 0);
+        assert!(!fs::read(tmp_dir.path().join("game_record")).await.unwrap().is_empty());
+        let path = tmp_dir.path().to_path_buf();
+        tmp_dir.remove().await.unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn tmp_dirs_are_removed_when_dropped() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let tmp_dir = runtime
+            .block_on(TmpDir::create(format!("test-{:x}", thread_rng().gen::<u32>())))
+            .unwrap();
+        let path = tmp_dir.path().to_path_buf();
+        std::fs::write(path.join("logs"), "left behind").unwrap();
+        drop(runtime);
+        // Outside a runtime the directory is removed straight away
+        drop(tmp_dir);
+        assert!(!path.exists());
+    }
+}
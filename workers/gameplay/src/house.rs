@@ -0,0 +1,232 @@
+//! House bots are compiled into the gameplay worker and play against the
+//! engine's [GameState] directly, so they need no sandbox. They are baseline
+//! opponents: a bot that can't beat them has a bug.
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use shared::poker::game::{Action, GameState, PlayerPosition, Round};
+use shared::poker::hands::{hand_eval, Card};
+use shared::HouseBot;
+
+/// How a house bot plays
+pub trait Strategy: Send {
+    /// Choose an action for `position`, which is the player to act.
+    /// Strategies must only look at their own hole cards.
+    fn act(&mut self, state: &GameState, position: PlayerPosition) -> Action;
+}
+
+pub fn strategy(bot: HouseBot) -> Box<dyn Strategy> {
+    match bot {
+        HouseBot::Random => Box::new(RandomStrategy(StdRng::from_entropy())),
+        HouseBot::CallingStation => Box::new(CallingStation),
+        HouseBot::TightAggressive => Box::new(TightAggressive),
+        HouseBot::Equity => Box::new(EquityStrategy {
+            rng: StdRng::from_entropy(),
+            samples: 300,
+        }),
+    }
+}
+
+/// How much `position` has to add to call
+fn to_call(state: &GameState, position: PlayerPosition) -> u32 {
+    state
+        .target_push
+        .saturating_sub(state.player_states[position as usize].pushed)
+}
+
+fn pot(state: &GameState) -> u32 {
+    state.player_states.iter().map(|player| player.pushed).sum()
+}
+
+/// Check if possible, fold otherwise
+fn check_or_fold(state: &GameState, position: PlayerPosition) -> Action {
+    if to_call(state, position) == 0 {
+        Action::Raise(0)
+    } else {
+        Action::Fold
+    }
+}
+
+struct RandomStrategy(StdRng);
+
+impl Strategy for RandomStrategy {
+    fn act(&mut self, state: &GameState, position: PlayerPosition) -> Action {
+        let stack = state.player_states[position as usize].stack;
+        match self.0.gen_range(0..3) {
+            0 => check_or_fold(state, position),
+            1 => Action::Raise(0),
+            _ => Action::Raise(self.0.gen_range(1..=stack.max(1))),
+        }
+    }
+}
+
+struct CallingStation;
+
+impl Strategy for CallingStation {
+    fn act(&mut self, _state: &GameState, _position: PlayerPosition) -> Action {
+        Action::Raise(0)
+    }
+}
+
+/// Starting hands raised preflop, in the usual notation: `s` for suited, `o` for offsuit
+const RAISE_CHART: &[&str] = &[
+    "AA", "KK", "QQ", "JJ", "TT", "AKs", "AQs", "AJs", "KQs", "AKo", "AQo",
+];
+/// Starting hands called preflop
+const CALL_CHART: &[&str] = &[
+    "99", "88", "77", "66", "55", "44", "33", "22", "ATs", "A9s", "A8s", "A7s", "A6s", "A5s",
+    "A4s", "A3s", "A2s", "KJs", "KTs", "QJs", "QTs", "JTs", "T9s", "98s", "AJo", "ATo", "KQo",
+    "KJo", "QJo",
+];
+
+/// The chart name of a starting hand, e.g. `AKs` or `72o`
+fn starting_hand(cards: &[Card; 2]) -> String {
+    let rank = |card: &Card| if card.value == 1 { 14 } else { card.value };
+    let (high, low) = if rank(&cards[0]) >= rank(&cards[1]) {
+        (cards[0], cards[1])
+    } else {
+        (cards[1], cards[0])
+    };
+    let name = |card: &Card| card.to_string()[..1].to_string();
+    if high.value == low.value {
+        format!("{}{}", name(&high), name(&low))
+    } else if high.suite == low.suite {
+        format!("{}{}s", name(&high), name(&low))
+    } else {
+        format!("{}{}o", name(&high), name(&low))
+    }
+}
+
+/// Raises the hands in [RAISE_CHART] and calls the hands in [CALL_CHART]
+/// preflop. After the flop it bets half the pot with a pair or better and
+/// gives up otherwise.
+struct TightAggressive;
+
+impl Strategy for TightAggressive {
+    fn act(&mut self, state: &GameState, position: PlayerPosition) -> Action {
+        let player = &state.player_states[position as usize];
+        if state.round == Round::PreFlop {
+            let hand = starting_hand(&player.hole_cards.0);
+            return if RAISE_CHART.contains(&hand.as_str()) {
+                Action::Raise(state.target_push * 2)
+            } else if CALL_CHART.contains(&hand.as_str()) {
+                Action::Raise(0)
+            } else {
+                check_or_fold(state, position)
+            };
+        }
+        let mut cards = state.community_cards.0.clone();
+        cards.extend(player.hole_cards.0);
+        let best = hand_eval::best5(&cards);
+        let hand_eval::HandValue((category, counts, _)) = hand_eval::hand_value(&best.cards);
+        // Fewer than five ranks means at least a pair. A hand that doesn't
+        // use a hole card is the board's, and the opponent has it too.
+        let made = (category > 0 || counts.len() < 5)
+            && best.cards.iter().any(|card| player.hole_cards.0.contains(card));
+        if made {
+            Action::Raise(pot(state) / 2)
+        } else {
+            check_or_fold(state, position)
+        }
+    }
+}
+
+/// Estimates its equity against a random hand by sampling the rest of the
+/// board, then raises strong hands and calls when the pot odds are good enough
+struct EquityStrategy {
+    rng: StdRng,
+    samples: usize,
+}
+
+impl EquityStrategy {
+    fn equity(&mut self, hole_cards: &[Card; 2], board: &[Card]) -> f64 {
+        let unseen = GameState::get_deck()
+            .into_iter()
+            .filter(|card| !hole_cards.contains(card) && !board.contains(card))
+            .collect::<Vec<_>>();
+        let mut won = 0.0;
+        for _ in 0..self.samples {
+            let drawn = unseen
+                .choose_multiple(&mut self.rng, 2 + 5 - board.len())
+                .copied()
+                .collect::<Vec<_>>();
+            let mut full_board = board.to_vec();
+            full_board.extend(&drawn[2..]);
+            let mut own = full_board.clone();
+            own.extend(hole_cards);
+            let mut other = full_board;
+            other.extend(&drawn[..2]);
+            won += match hand_eval::best5(&own).cmp(&hand_eval::best5(&other)) {
+                std::cmp::Ordering::Greater => 1.0,
+                std::cmp::Ordering::Equal => 0.5,
+                std::cmp::Ordering::Less => 0.0,
+            };
+        }
+        won / self.samples as f64
+    }
+}
+
+impl Strategy for EquityStrategy {
+    fn act(&mut self, state: &GameState, position: PlayerPosition) -> Action {
+        let equity = self.equity(
+            &state.player_states[position as usize].hole_cards.0,
+            &state.community_cards.0,
+        );
+        let to_call = to_call(state, position);
+        let pot_odds = to_call as f64 / (pot(state) + to_call) as f64;
+        if equity > 0.7 {
+            Action::Raise(pot(state))
+        } else if equity >= pot_odds {
+            Action::Raise(0)
+        } else {
+            check_or_fold(state, position)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(sb: [&str; 2], bb: [&str; 2]) -> GameState {
+        let mut deck = GameState::get_deck();
+        let hole_cards = [bb[1], bb[0], sb[1], sb[0]].map(|card| card.parse::<Card>().unwrap());
+        deck.retain(|card| !hole_cards.contains(card));
+        deck.extend(hole_cards);
+        GameState::new([500, 500], deck, 100)
+    }
+
+    #[test]
+    fn names_starting_hands() {
+        let cards = |a: &str, b: &str| [a.parse().unwrap(), b.parse().unwrap()];
+        assert_eq!(starting_hand(&cards("Kd", "As")), "AKo");
+        assert_eq!(starting_hand(&cards("7h", "2h")), "72s");
+        assert_eq!(starting_hand(&cards("Tc", "Td")), "TT");
+    }
+
+    #[test]
+    fn tight_aggressive_follows_the_chart() {
+        let mut bot = TightAggressive;
+        let aces = state(["As", "Ad"], ["7c", "2d"]);
+        assert_eq!(bot.act(&aces, PlayerPosition::SmallBlind), Action::Raise(4));
+        let trash = state(["7c", "2d"], ["As", "Ad"]);
+        assert_eq!(bot.act(&trash, PlayerPosition::SmallBlind), Action::Fold);
+    }
+
+    #[test]
+    fn equity_bot_raises_aces_and_folds_trash() {
+        let mut bot = EquityStrategy {
+            rng: StdRng::seed_from_u64(0),
+            samples: 500,
+        };
+        let aces = state(["As", "Ad"], ["7c", "2d"]);
+        assert!(matches!(
+            bot.act(&aces, PlayerPosition::SmallBlind),
+            Action::Raise(amount) if amount > 0
+        ));
+        // Facing a big raise with 7-2
+        let raised = state(["As", "Ad"], ["7c", "2d"])
+            .post_action(Action::Raise(60))
+            .unwrap();
+        assert_eq!(bot.act(&raised, PlayerPosition::BigBlind), Action::Fold);
+    }
+}
@@ -1,2 +1,5 @@
 pub mod bots;
-pub mod communication;
+pub mod house;
+pub mod pool;
+pub mod remote;
+pub mod validation;
@@ -1,7 +1,10 @@
-use std::path::PathBuf;
-
-use gameplay::bots::run_game;
-use shared::{GameError, GameStatus, GameStatusMessage, GameTask};
+use gameplay::bots::{run_game, run_remote_game};
+use gameplay::pool::WarmPool;
+use gameplay::validation::run_test_game;
+use shared::{
+    BuildResultMessage, BuildStatus, GameError, GameStatus, GameStatusMessage, GameTask,
+    QueuedGame, ScenarioResult,
+};
 
 #[tokio::main]
 async fn main() {
@@ -13,25 +16,34 @@ async fn main() {
     let sqs = shared::sqs_client(&config).await;
     let s3 = shared::s3_client(&config).await;
     let game_results_queue = std::env::var("GAME_RESULTS_QUEUE_URL").unwrap();
+    let build_results_queue = std::env::var("BUILD_RESULTS_QUEUE_URL").unwrap();
     let reqwest_client = reqwest::Client::new();
+    let pool = tokio::sync::Mutex::new(WarmPool::from_env());
 
     shared::sqs::listen_on_queue(
         std::env::var("NEW_GAMES_QUEUE_URL").unwrap(),
         &sqs,
         |message: GameTask| async {
             log::info!("Received message: {:?}", message);
-            let result = match message.clone() {
-                GameTask::Game {
-                    defender,
-                    challenger,
-                    id,
-                    rounds,
-                    game_record_presigned,
-                    public_logs_presigned,
-                    defender_logs_presigned,
-                    challenger_logs_presigned,
-                } => {
-                    let result = run_game(defender, challenger, &s3, &id, rounds).await;
+            let mut pool = pool.lock().await;
+            let id = match &message {
+                GameTask::Game(game) => game.id.clone(),
+                GameTask::TestGame { bot, .. } => bot.to_string(),
+                GameTask::RemoteGame { session, .. } => session.clone(),
+            };
+            let result = match message {
+                GameTask::Game(game) => {
+                    let QueuedGame {
+                        defender,
+                        challenger,
+                        id,
+                        config,
+                        game_record_presigned,
+                        public_logs_presigned,
+                        defender_logs_presigned,
+                        challenger_logs_presigned,
+                    } = *game;
+                    let result = run_game(defender, challenger, &s3, &id, config, &mut pool).await;
 
                     match result {
                         Err(e) => {
@@ -69,12 +81,54 @@ async fn main() {
                         }
                     }
                 }
+                GameTask::RemoteGame {
+                    opponent,
+                    session,
+                    config,
+                    game_config,
+                } => {
+                    // Remote games aren't recorded, the team sees the result over the relay
+                    if let Err(e) =
+                        run_remote_game(opponent, &session, &s3, config, game_config, &mut pool)
+                            .await
+                    {
+                        log::error!("Remote game failed: {:?}", e);
+                    }
+                    return true;
+                }
                 GameTask::TestGame { bot, log_presigned } => {
-                    let mut path = PathBuf::default();
-                    if let Err(_) = run_game(bot, bot, &s3, &bot.to_string(), 5).await {
-                        Ok(GameStatus::TestGameFailed)
+                    let playing = BuildResultMessage::new(bot, BuildStatus::PlayingTestGame, None);
+                    if sqs
+                        .send_message()
+                        .queue_url(&build_results_queue)
+                        .message_body(serde_json::to_string(&playing).unwrap())
+                        .send()
+                        .await
+                        .is_err()
+                    {
+                        log::error!("Failed to report that bot {} is playing its test game", bot);
+                    }
+                    let result = match run_test_game(bot, &s3).await {
+                        Ok(result) => result,
+                        Err(e) => {
+                            // Not the bot's fault, so the test game is played again
+                            log::error!("Failed to run the test game of bot {}: {:?}", bot, e);
+                            return false;
+                        }
+                    };
+                    if let Err(e) = reqwest_client
+                        .put(log_presigned.url)
+                        .headers(log_presigned.headers.into())
+                        .body(result.log)
+                        .send()
+                        .await
+                    {
+                        log::error!("Error uploading test game log: {:?}", e);
+                    }
+                    if result.scenarios.iter().all(ScenarioResult::passed) {
+                        Ok(GameStatus::TestGameSucceeded(result.scenarios))
                     } else {
-                        Ok(GameStatus::TestGameSucceeded)
+                        Ok(GameStatus::TestGameFailed(result.scenarios))
                     }
                 }
             };
@@ -82,10 +136,7 @@ async fn main() {
                 .queue_url(&game_results_queue)
                 .message_body(
                     serde_json::to_string::<GameStatusMessage>(&GameStatusMessage {
-                        id: match message {
-                            GameTask::Game { id, .. } => id,
-                            GameTask::TestGame { bot, .. } => bot.to_string(),
-                        },
+                        id,
                         result,
                     })
                     .unwrap(),
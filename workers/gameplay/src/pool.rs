@@ -0,0 +1,77 @@
+use std::collections::VecDeque;
+
+use crate::bots::BotProcess;
+
+/// Bots that declared `warm_start` in their bot.json are kept running
+/// between matches, so that they don't pay their start-up cost every game.
+/// Idle bots are paused, and must answer `NEWMATCH` with `READY` before
+/// being reused. Bots that fail the reset are shut down and started cold.
+pub struct WarmPool {
+    capacity: usize,
+    // least recently used first
+    idle: VecDeque<BotProcess>,
+}
+
+impl WarmPool {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            idle: VecDeque::new(),
+        }
+    }
+
+    /// Reads the number of idle bots to keep from `WARM_POOL_SIZE`
+    pub fn from_env() -> Self {
+        Self::new(
+            std::env::var("WARM_POOL_SIZE")
+                .unwrap_or_else(|_| "4".into())
+                .parse::<usize>()
+                .unwrap_or(4),
+        )
+    }
+
+    /// Take an idle process for the bot out of the pool, ready for a new match
+    pub async fn checkout(&mut self, bot: i32) -> Option<BotProcess> {
+        let index = self.idle.iter().position(|p| p.bot == bot)?;
+        let mut process = self.idle.remove(index)?;
+        if !process.is_alive() {
+            log::info!("Warm process for bot {} exited while idle", bot);
+            Self::shut_down(process).await;
+            return None;
+        }
+        process.resume();
+        match process.reset().await {
+            Ok(()) => Some(process),
+            Err(e) => {
+                log::info!("Warm process for bot {} failed to reset: {}", bot, e);
+                Self::shut_down(process).await;
+                None
+            }
+        }
+    }
+
+    /// Keep a bot around for its next match, evicting the least recently used
+    /// bots if the pool is full
+    pub async fn checkin(&mut self, process: BotProcess) {
+        if self.capacity == 0 || !process.warm_start {
+            Self::shut_down(process).await;
+            return;
+        }
+        process.pause();
+        self.idle.push_back(process);
+        while self.idle.len() > self.capacity {
+            if let Some(evicted) = self.idle.pop_front() {
+                log::debug!("Evicting warm process for bot {}", evicted.bot);
+                Self::shut_down(evicted).await;
+            }
+        }
+    }
+
+    async fn shut_down(mut process: BotProcess) {
+        process.stop().await;
+        let bot = process.bot;
+        if let Err(e) = process.remove().await {
+            log::error!("Failed to clean up warm process for bot {}: {}", bot, e);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+//! Remote bots play from a team's own machine. The team connects a WebSocket
+//! to the website, which relays it to the engine. Each text message is one
+//! line of the usual protocol.
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, DuplexStream};
+use tokio::sync::oneshot;
+use tokio_tungstenite::tungstenite::protocol::{frame::coding::CloseCode, CloseFrame};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::bots::BotProcess;
+
+/// Connect to the website's relay for a remote bot session.
+/// The returned sender takes the reason the relay is closed with once the
+/// game is over, so the team can see the result.
+pub async fn connect(session: &str) -> Result<(BotProcess, oneshot::Sender<String>), anyhow::Error> {
+    let url = format!(
+        "{}?session={}",
+        std::env::var("REMOTE_BOT_RELAY_URL")?,
+        session
+    );
+    let (relay, _) = tokio_tungstenite::connect_async(url).await?;
+    log::debug!("Connected to relay for session {}", session);
+
+    let (engine_side, relay_side) = tokio::io::duplex(4096);
+    let (finished_tx, finished_rx) = oneshot::channel();
+    tokio::spawn(async move {
+        if let Err(e) = bridge(relay, relay_side, finished_rx).await {
+            log::warn!("Relay closed with an error: {}", e);
+        }
+    });
+    let (reader, writer) = tokio::io::split(engine_side);
+    Ok((
+        BotProcess::from_pipes(Box::new(reader), Box::new(writer)),
+        finished_tx,
+    ))
+}
+
+/// Copy lines between the engine's end of the pipe and the relay
+async fn bridge<S>(
+    relay: tokio_tungstenite::WebSocketStream<S>,
+    pipe: DuplexStream,
+    finished: oneshot::Receiver<String>,
+) -> Result<(), anyhow::Error>
+where
+    S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+{
+    let (mut relay_tx, mut relay_rx) = relay.split();
+    let (pipe_rx, mut pipe_tx) = tokio::io::split(pipe);
+    let mut lines = BufReader::new(pipe_rx).lines();
+    loop {
+        tokio::select! {
+            line = lines.next_line() => match line? {
+                Some(line) => relay_tx.send(Message::Text(line)).await?,
+                // The engine is done with the bot
+                None => break,
+            },
+            message = relay_rx.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    for line in text.lines() {
+                        pipe_tx.write_all(format!("{}\n", line).as_bytes()).await?;
+                    }
+                    pipe_tx.flush().await?;
+                }
+                Some(Ok(Message::Close(_))) | None => {
+                    // Closing the pipe makes the engine see a crashed bot
+                    pipe_tx.shutdown().await?;
+                    return Ok(());
+                }
+                Some(Ok(_)) => {}
+                Some(Err(e)) => Err(e)?,
+            },
+        }
+    }
+    let reason = finished.await.unwrap_or_default();
+    relay_tx
+        .send(Message::Close(Some(CloseFrame {
+            code: CloseCode::Normal,
+            reason: reason.into(),
+        })))
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,353 @@
+//! The test game a bot plays after it is built, before it can be made active.
+//! The bot plays a few short matches against reference opponents built into
+//! the engine, some of them on scripted decks, so that it sees folds, all-ins,
+//! showdowns and split pots at least once.
+
+use std::path::Path;
+
+use rand::{seq::SliceRandom, Rng};
+use shared::poker::game::{Action, EndReason, GameState, GameStateSQL, PlayerPosition};
+use shared::poker::hands::Card;
+use shared::{GameConfig, GameError, ScenarioResult, WhichBot};
+use tokio::fs;
+
+use crate::bots::{download_and_run, play_match, BotProcess, NotConnected, TmpDir};
+use crate::house::Strategy;
+
+/// An opponent built into the engine, played like a house bot
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReferenceOpponent {
+    AlwaysFold,
+    AlwaysAllIn,
+    /// Calls or raises a random amount, half of the time each
+    RandomRaiser,
+}
+
+impl Strategy for ReferenceOpponent {
+    fn act(&mut self, state: &GameState, position: PlayerPosition) -> Action {
+        let stack = state.player_states[position as usize].stack;
+        let mut rng = rand::thread_rng();
+        match self {
+            ReferenceOpponent::AlwaysFold => Action::Fold,
+            // Raises are capped at the smaller stack and the bet limit
+            ReferenceOpponent::AlwaysAllIn => Action::Raise(stack),
+            ReferenceOpponent::RandomRaiser => {
+                if rng.gen_bool(0.5) {
+                    Action::Raise(rng.gen_range(1..=stack.max(1)))
+                } else {
+                    Action::Raise(0)
+                }
+            }
+        }
+    }
+}
+
+/// How a hand can end that a scenario is meant to show the bot
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Target {
+    Fold,
+    AllIn,
+    Showdown,
+    SplitPot,
+}
+
+impl Target {
+    /// Whether a hand of a game record got there
+    fn reached(self, hand: &[GameStateSQL]) -> bool {
+        let end = hand.last().and_then(|state| state.end_reason.as_ref());
+        match self {
+            Target::Fold => matches!(end, Some(EndReason::LastToAct(_))),
+            // Stacks only change once the hand is over
+            Target::AllIn => {
+                matches!(end, Some(EndReason::WonShowdown(_) | EndReason::Tie))
+                    && hand.iter().any(|state| {
+                        state.end_reason.is_none()
+                            && (state.defender_pushed == state.defender_stack
+                                || state.challenger_pushed == state.challenger_stack)
+                    })
+            }
+            Target::Showdown => matches!(end, Some(EndReason::WonShowdown(_) | EndReason::Tie)),
+            Target::SplitPot => matches!(end, Some(EndReason::Tie)),
+        }
+    }
+
+    /// Whether any hand of a game record got there
+    fn reached_in(self, record: &[GameStateSQL]) -> bool {
+        record
+            .split_inclusive(|state| state.end_reason.is_some())
+            .any(|hand| self.reached(hand))
+    }
+}
+
+/// One match of the test game
+struct Scenario {
+    name: &'static str,
+    opponent: ReferenceOpponent,
+    config: GameConfig,
+    /// Dealt before any shuffled decks
+    decks: Vec<Vec<Card>>,
+    /// Reached unless the bot folds its way out of it
+    target: Target,
+}
+
+fn cards<const N: usize>(cards: [&str; N]) -> [Card; N] {
+    cards.map(|card| card.parse().expect("Invalid card in scripted deck"))
+}
+
+/// A deck that deals the given hole cards and board, with the rest shuffled
+fn scripted_deck(sb: [&str; 2], bb: [&str; 2], board: [&str; 5]) -> Vec<Card> {
+    let (sb, bb, board) = (cards(sb), cards(bb), cards(board));
+    let dealt = [
+        board[4], board[3], board[2], board[1], board[0], bb[1], bb[0], sb[1], sb[0],
+    ];
+    let mut deck = GameState::get_deck()
+        .into_iter()
+        .filter(|card| !dealt.contains(card))
+        .collect::<Vec<_>>();
+    deck.shuffle(&mut rand::thread_rng());
+    // GameState deals from the end of the deck
+    deck.extend(dealt);
+    deck
+}
+
+fn scenarios() -> Vec<Scenario> {
+    // Both players play the board
+    let split = |sb, bb| scripted_deck(sb, bb, ["As", "Ks", "Qs", "Js", "Ts"]);
+    let showdown = |sb, bb| scripted_deck(sb, bb, ["Ac", "8d", "8h", "3c", "Jd"]);
+    vec![
+        Scenario {
+            name: "always-fold opponent",
+            opponent: ReferenceOpponent::AlwaysFold,
+            config: GameConfig {
+                hands: 10,
+                ..Default::default()
+            },
+            decks: vec![],
+            target: Target::Fold,
+        },
+        Scenario {
+            name: "always-all-in opponent",
+            opponent: ReferenceOpponent::AlwaysAllIn,
+            // Going all in is only possible with stacks within the bet limit
+            config: GameConfig {
+                hands: 10,
+                stack: 50,
+                ..Default::default()
+            },
+            decks: vec![
+                showdown(["Ah", "Ad"], ["Kc", "Kd"]),
+                showdown(["Ah", "Ad"], ["Kc", "Kd"]),
+            ],
+            target: Target::AllIn,
+        },
+        Scenario {
+            name: "random raiser, showdowns",
+            opponent: ReferenceOpponent::RandomRaiser,
+            config: GameConfig {
+                hands: 20,
+                ..Default::default()
+            },
+            decks: vec![
+                showdown(["8s", "8c"], ["Kc", "Kd"]),
+                showdown(["8s", "8c"], ["Kc", "Kd"]),
+                showdown(["As", "Qd"], ["Ah", "Qc"]),
+                showdown(["As", "Qd"], ["Ah", "Qc"]),
+            ],
+            target: Target::Showdown,
+        },
+        Scenario {
+            name: "random raiser, split pots",
+            opponent: ReferenceOpponent::RandomRaiser,
+            config: GameConfig {
+                hands: 4,
+                ..Default::default()
+            },
+            decks: vec![
+                split(["2c", "3d"], ["4h", "5c"]),
+                split(["2c", "3d"], ["4h", "5c"]),
+                split(["9h", "9d"], ["2h", "7c"]),
+                split(["9h", "9d"], ["2h", "7c"]),
+            ],
+            target: Target::SplitPot,
+        },
+    ]
+}
+
+/// The report uploaded as the test game log, and how each scenario went
+pub struct TestGameResult {
+    pub scenarios: Vec<ScenarioResult>,
+    pub log: Vec<u8>,
+}
+
+/// Play every scenario of the test game with a freshly started copy of the bot.
+/// Fails if the engine couldn't run a scenario, which isn't the bot's fault.
+pub async fn run_test_game(
+    bot: i32,
+    s3_client: &aws_sdk_s3::Client,
+) -> Result<TestGameResult, anyhow::Error> {
+    let mut results = vec![];
+    let mut details = String::new();
+    for (i, scenario) in scenarios().into_iter().enumerate() {
+        log::info!("Bot {} playing test game scenario {}", bot, scenario.name);
+        details.push_str(&format!(
+            "\n=== Scenario {}: {} ({} hands) ===\n",
+            i + 1,
+            scenario.name,
+            scenario.config.hands
+        ));
+        let error = play_scenario(bot, &scenario, s3_client, &mut details).await?;
+        results.push(ScenarioResult {
+            name: scenario.name.to_string(),
+            error,
+        });
+    }
+
+    let mut log = String::from("Test game\n");
+    for result in &results {
+        match &result.error {
+            None => log.push_str(&format!("PASSED {}\n", result.name)),
+            Some(e) => log.push_str(&format!("FAILED {}: {:?}\n", result.name, e)),
+        }
+    }
+    log.push_str(&details);
+    Ok(TestGameResult {
+        scenarios: results,
+        log: log.into_bytes(),
+    })
+}
+
+/// Play one scenario, appending the engine's and the bot's logs to `details`.
+/// Returns why the bot failed the scenario, if it did.
+async fn play_scenario(
+    bot: i32,
+    scenario: &Scenario,
+    s3_client: &aws_sdk_s3::Client,
+    details: &mut String,
+) -> Result<Option<GameError>, anyhow::Error> {
+    let game_id = format!("{:x}", rand::thread_rng().gen::<u32>());
+    let bot_bucket = std::env::var("COMPILED_BOT_S3_BUCKET")?;
+    let tmp_dir = TmpDir::create(game_id.clone()).await?;
+
+    let mut defender = match download_and_run(bot, &bot_bucket, s3_client).await {
+        Ok(defender) => defender,
+        Err(e) => {
+            details.push_str(&format!("FAILED: the bot could not be started: {}\n", e));
+            if let Some(NotConnected(log)) = e.downcast_ref() {
+                details.push_str("--- bot stderr ---\n");
+                details.push_str(&String::from_utf8_lossy(log));
+            }
+            tmp_dir.remove().await?;
+            return Ok(Some(GameError::RunTimeError(WhichBot::Defender)));
+        }
+    };
+    let mut challenger = BotProcess::with_strategy(Box::new(scenario.opponent));
+    let status = play_match(
+        &mut defender,
+        &mut challenger,
+        &game_id,
+        tmp_dir.path(),
+        scenario.config,
+        scenario.decks.clone(),
+    )
+    .await;
+    challenger.stop().await;
+    defender.stop().await;
+    let bot_log = defender.read_logs().await?;
+    defender.remove().await?;
+    let engine_log = fs::read(tmp_dir.path().join("logs")).await?;
+    let record = read_record(tmp_dir.path()).await?;
+    tmp_dir.remove().await?;
+
+    let error = status?.err();
+    match &error {
+        None => details.push_str("PASSED\n"),
+        Some(e) => details.push_str(&format!("FAILED: {:?}\n", e)),
+    }
+    if !scenario.target.reached_in(&record) {
+        details.push_str(&format!(
+            "The bot folded before seeing a hand end with {:?}\n",
+            scenario.target
+        ));
+    }
+    details.push_str("--- engine log (the bot is the defender) ---\n");
+    details.push_str(&String::from_utf8_lossy(&engine_log));
+    details.push_str("--- bot stderr ---\n");
+    details.push_str(&String::from_utf8_lossy(&bot_log));
+    Ok(error)
+}
+
+/// The game states the engine recorded in `dir`, in order
+async fn read_record(dir: &Path) -> Result<Vec<GameStateSQL>, anyhow::Error> {
+    fs::read_to_string(dir.join("game_record"))
+        .await?
+        .lines()
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use shared::HouseBot;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn scenarios_reach_their_targets_against_a_calling_station() {
+        for scenario in scenarios() {
+            assert_eq!(scenario.config.validate(), Ok(()), "{}", scenario.name);
+            let tmp_dir = TmpDir::create(format!("test-{:x}", rand::thread_rng().gen::<u32>()))
+                .await
+                .unwrap();
+            let mut bot = BotProcess::house(HouseBot::CallingStation);
+            let mut opponent = BotProcess::with_strategy(Box::new(scenario.opponent));
+            let status = play_match(
+                &mut bot,
+                &mut opponent,
+                "test",
+                tmp_dir.path(),
+                scenario.config,
+                scenario.decks.clone(),
+            )
+            .await
+            .unwrap();
+            assert!(status.is_ok(), "{}: {:?}", scenario.name, status);
+            let record = read_record(tmp_dir.path()).await.unwrap();
+            tmp_dir.remove().await.unwrap();
+            assert!(
+                scenario.target.reached_in(&record),
+                "{} never reached {:?}",
+                scenario.name,
+                scenario.target
+            );
+        }
+    }
+
+    #[test]
+    fn scripted_decks_deal_the_given_cards() {
+        let deck = scripted_deck(["Ah", "Ad"], ["Kc", "Kd"], ["Ac", "8d", "8h", "3c", "Jd"]);
+        assert_eq!(deck.len(), 52);
+        let mut state = GameState::new([500, 500], deck, 100);
+        assert_eq!(state.player_states[0].hole_cards.0, cards(["Ah", "Ad"]));
+        assert_eq!(state.player_states[1].hole_cards.0, cards(["Kc", "Kd"]));
+        while state.whose_turn().is_some() {
+            state = state.post_action(Action::Raise(0)).unwrap();
+        }
+        assert_eq!(state.community_cards.0, cards(["Ac", "8d", "8h", "3c", "Jd"]));
+        assert!(matches!(
+            state.end_reason,
+            Some(EndReason::WonShowdown(PlayerPosition::SmallBlind))
+        ));
+    }
+
+    #[test]
+    fn reference_opponents_act_as_named() {
+        let state = GameState::new([500, 500], GameState::get_deck(), 100);
+        let sb = PlayerPosition::SmallBlind;
+        assert_eq!(ReferenceOpponent::AlwaysAllIn.act(&state, sb), Action::Raise(500));
+        assert_eq!(ReferenceOpponent::AlwaysFold.act(&state, sb), Action::Fold);
+        for _ in 0..100 {
+            let action = ReferenceOpponent::RandomRaiser.act(&state, sb);
+            assert!(state.clone().post_action(action).is_ok());
+        }
+    }
+}
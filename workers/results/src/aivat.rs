@@ -0,0 +1,246 @@
+//! Luck-adjusted scores for finished games, in the spirit of AIVAT
+//! (<https://arxiv.org/abs/1612.06915>). Most of the variance in a match comes
+//! from the cards, so every time cards are dealt we subtract how much the deal
+//! changed the defender's expected share of the pot. Each correction averages
+//! to zero over all possible deals, so the adjusted score is still an unbiased
+//! estimate of how much better the defender played, with far less variance.
+//! Corrections for the bots' own decisions would need their strategies, so
+//! only the deals are adjusted.
+
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
+
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use shared::{
+    poker::{
+        game::{GameState, GameStateSQL},
+        hands::{hand_eval, Card, Suite},
+    },
+    GameConfig,
+};
+
+use crate::analysis::defender_hand_results;
+
+/// Boards sampled for preflop equities, which can't be enumerated quickly
+const PREFLOP_SAMPLES: usize = 3000;
+/// The most preflop equities kept in [PREFLOP_EQUITIES] before it is emptied
+const PREFLOP_CACHE_SIZE: usize = 100_000;
+
+/// Preflop equities by both players' hole cards, since the same matchups come
+/// up again and again and each one takes thousands of showdowns to sample
+static PREFLOP_EQUITIES: OnceLock<Mutex<HashMap<[u8; 4], f64>>> = OnceLock::new();
+
+/// The defender's chance of winning at showdown, counting ties as half, over
+/// every way the board can be completed (sampled preflop)
+pub fn equity(defender: &[Card; 2], challenger: &[Card; 2], board: &[Card]) -> f64 {
+    if !board.is_empty() {
+        return board_equity(defender, challenger, board);
+    }
+    let key = preflop_key(defender, challenger);
+    let cache = PREFLOP_EQUITIES.get_or_init(Default::default);
+    if let Some(equity) = cache.lock().unwrap_or_else(|e| e.into_inner()).get(&key) {
+        return *equity;
+    }
+    let equity = board_equity(defender, challenger, board);
+    let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+    if cache.len() >= PREFLOP_CACHE_SIZE {
+        cache.clear();
+    }
+    cache.insert(key, equity);
+    equity
+}
+
+/// Both players' hole cards, in an order that doesn't change their equity
+fn preflop_key(defender: &[Card; 2], challenger: &[Card; 2]) -> [u8; 4] {
+    let index = |card: &Card| {
+        let suite = match card.suite {
+            Suite::Clubs => 0,
+            Suite::Spades => 1,
+            Suite::Hearts => 2,
+            Suite::Diamonds => 3,
+        };
+        card.value as u8 * 4 + suite
+    };
+    let (d0, d1) = (index(&defender[0]), index(&defender[1]));
+    let (c0, c1) = (index(&challenger[0]), index(&challenger[1]));
+    [d0.min(d1), d0.max(d1), c0.min(c1), c0.max(c1)]
+}
+
+fn board_equity(defender: &[Card; 2], challenger: &[Card; 2], board: &[Card]) -> f64 {
+    let unseen = GameState::get_deck()
+        .into_iter()
+        .filter(|card| !defender.contains(card) && !challenger.contains(card))
+        .filter(|card| !board.contains(card))
+        .collect::<Vec<_>>();
+    // Both hands in one buffer each, filled in place for every runout
+    let mut own = [defender[0]; 7];
+    let mut other = [challenger[0]; 7];
+    own[..board.len()].copy_from_slice(board);
+    other[..board.len()].copy_from_slice(board);
+    own[5..].copy_from_slice(defender);
+    other[5..].copy_from_slice(challenger);
+    let mut showdown = |runout: &[Card]| {
+        own[board.len()..5].copy_from_slice(runout);
+        other[board.len()..5].copy_from_slice(runout);
+        match hand_eval::best5(&own).cmp(&hand_eval::best5(&other)) {
+            std::cmp::Ordering::Greater => 1.0,
+            std::cmp::Ordering::Equal => 0.5,
+            std::cmp::Ordering::Less => 0.0,
+        }
+    };
+    match 5 - board.len() {
+        0 => showdown(&[]),
+        1 => unseen.iter().map(|card| showdown(&[*card])).sum::<f64>() / unseen.len() as f64,
+        2 => {
+            let mut total = 0.0;
+            let mut runouts = 0;
+            for i in 0..unseen.len() {
+                for j in i + 1..unseen.len() {
+                    total += showdown(&[unseen[i], unseen[j]]);
+                    runouts += 1;
+                }
+            }
+            total / runouts as f64
+        }
+        missing => {
+            // The same hands always get the same estimate
+            let mut rng = StdRng::seed_from_u64(0);
+            let mut runout = Vec::with_capacity(missing);
+            let mut total = 0.0;
+            for _ in 0..PREFLOP_SAMPLES {
+                runout.clear();
+                runout.extend(unseen.choose_multiple(&mut rng, missing));
+                total += showdown(&runout);
+            }
+            total / PREFLOP_SAMPLES as f64
+        }
+    }
+}
+
+/// The defender's luck-adjusted result in each hand of a game, from its
+/// states in order
+pub fn adjusted_hand_values(states: &[GameStateSQL], config: &GameConfig) -> Vec<f64> {
+    // The pot when the hole cards are dealt
+    let blinds = (config.small_blind + config.big_blind) as f64;
+    let hands = states
+        .split_inclusive(|state| state.end_reason.is_some())
+        .filter(|hand| hand.last().is_some_and(|state| state.end_reason.is_some()))
+        .collect::<Vec<_>>();
+    let end_stacks = hands
+        .iter()
+        .map(|hand| {
+            let end = hand.last().expect("Hands aren't empty");
+            (end.defender_stack, end.challenger_stack)
+        })
+        .collect::<Vec<_>>();
+    defender_hand_results(&end_stacks)
+        .into_iter()
+        .zip(hands)
+        .map(|(result, hand)| result as f64 - luck(hand, blinds))
+        .collect()
+}
+
+/// How many chips the deals in a hand were worth to the defender
+fn luck(hand: &[GameStateSQL], blinds: f64) -> f64 {
+    let defender = &hand[0].defender_hand.0;
+    let challenger = &hand[0].challenger_hand.0;
+    // The hole cards: before them, both players have even chances
+    let mut board_len = 0;
+    let mut previous = equity(defender, challenger, &[]);
+    let mut luck = (previous - 0.5) * blinds;
+    for state in hand {
+        let board = &state.community_cards.0;
+        if board.len() != board_len {
+            board_len = board.len();
+            let current = equity(defender, challenger, board);
+            let pot = (state.defender_pushed + state.challenger_pushed) as f64;
+            luck += (current - previous) * pot;
+            previous = current;
+        }
+    }
+    luck
+}
+
+/// The defender's luck-adjusted score for a whole game
+pub fn adjusted_score(states: &[GameStateSQL], config: &GameConfig) -> f64 {
+    adjusted_hand_values(states, config).iter().sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use shared::{
+        poker::game::{Action, CommunityCards, EndReason, HoleCards, PlayerPosition},
+        WhichBot,
+    };
+
+    use super::*;
+
+    fn cards<const N: usize>(cards: [&str; N]) -> [Card; N] {
+        cards.map(|card| card.parse().unwrap())
+    }
+
+    #[test]
+    fn equities() {
+        let aces = cards(["Ah", "Ad"]);
+        let kings = cards(["Kc", "Kd"]);
+        let preflop = equity(&aces, &kings, &[]);
+        assert!((preflop - 0.82).abs() < 0.03, "{}", preflop);
+        // Kings win with one of the two kings left, unless an ace comes too
+        let flop = equity(&aces, &kings, &cards(["2c", "7d", "9s"]));
+        assert!((flop - (1.0 - 83.0 / 990.0)).abs() < 0.000001, "{}", flop);
+        assert_eq!(
+            equity(&aces, &kings, &cards(["2c", "7d", "9s", "Jh", "Ks"])),
+            0.0
+        );
+    }
+
+    #[test]
+    fn preflop_equities_are_cached_by_hole_cards() {
+        let aces = cards(["Ah", "Ad"]);
+        let kings = cards(["Kc", "Kd"]);
+        let key = preflop_key(&aces, &kings);
+        assert_eq!(key, preflop_key(&cards(["Ad", "Ah"]), &cards(["Kd", "Kc"])));
+        assert_ne!(key, preflop_key(&kings, &aces));
+        let preflop = equity(&aces, &kings, &[]);
+        let cached = PREFLOP_EQUITIES.get().unwrap().lock().unwrap()[&key];
+        assert_eq!(cached, preflop);
+        assert_eq!(equity(&cards(["Ad", "Ah"]), &kings, &[]), preflop);
+    }
+
+    fn state(pushed: i32, stacks: [i32; 2], board: &[&str], end: bool) -> GameStateSQL {
+        GameStateSQL {
+            game_id: "game".to_string(),
+            step: 0,
+            challenger_stack: stacks[1],
+            defender_stack: stacks[0],
+            challenger_pushed: pushed,
+            defender_pushed: pushed,
+            challenger_hand: HoleCards(cards(["Kc", "Kd"])),
+            defender_hand: HoleCards(cards(["Ah", "Ad"])),
+            community_cards: CommunityCards(
+                board.iter().map(|card| card.parse().unwrap()).collect(),
+            ),
+            sb: WhichBot::Defender,
+            action_time: 0,
+            whose_turn: None,
+            action_val: Action::Raise(0),
+            end_reason: end.then_some(EndReason::WonShowdown(PlayerPosition::SmallBlind)),
+        }
+    }
+
+    #[test]
+    fn aces_winning_an_all_in_is_mostly_luck() {
+        // Aces against kings, all-in before the flop, and the aces hold
+        let states = [
+            state(100, [500, 500], &[], false),
+            state(100, [600, 400], &["2c", "7d", "9s", "Jh", "3c"], true),
+        ];
+        let adjusted = adjusted_hand_values(&states, &GameConfig::default());
+        assert_eq!(adjusted.len(), 1);
+        // Getting all-in with aces is worth about 0.82 * 200 - 100 = 64 chips,
+        // not the 100 they happened to win
+        assert!((adjusted[0] - 64.0).abs() < 10.0, "{:?}", adjusted);
+    }
+}
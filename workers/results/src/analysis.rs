@@ -0,0 +1,248 @@
+//! Head-to-head comparisons between two bots, from the hands they have played
+//! against each other. A single match is too noisy to say which bot is better,
+//! so every hand is treated as a sample of how many chips a bot wins on average.
+
+use diesel::prelude::*;
+use serde::Serialize;
+use shared::{
+    db::{models::GameResult, schema},
+    GameConfig,
+};
+
+/// How `bot` did against `opponent`, in milli big blinds per hand
+#[derive(Debug, Clone, Serialize)]
+pub struct HeadToHead {
+    pub bot: i32,
+    pub opponent: i32,
+    /// Finished games between the two bots
+    pub games: usize,
+    /// Games that `bot` finished with more chips
+    pub games_won: usize,
+    pub hands: usize,
+    pub mbb_per_hand: f64,
+    pub standard_error: f64,
+    /// The confidence level of the interval and the significance test, e.g. 0.95
+    pub confidence: f64,
+    pub confidence_interval: [f64; 2],
+    pub z_score: f64,
+    /// Two-sided p-value of the hypothesis that neither bot is better
+    pub p_value: f64,
+    pub significant: bool,
+}
+
+impl HeadToHead {
+    /// Compare two bots from the big blinds `bot` won in each hand
+    pub fn from_hands(
+        bot: i32,
+        opponent: i32,
+        hands: &[f64],
+        games: usize,
+        games_won: usize,
+        confidence: f64,
+    ) -> Self {
+        let mbb = hands
+            .iter()
+            .map(|big_blinds| big_blinds * 1000.0)
+            .collect::<Vec<_>>();
+        let n = mbb.len() as f64;
+        let mean = if mbb.is_empty() {
+            0.0
+        } else {
+            mbb.iter().sum::<f64>() / n
+        };
+        let variance = if mbb.len() < 2 {
+            0.0
+        } else {
+            mbb.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0)
+        };
+        let standard_error = (variance / n.max(1.0)).sqrt();
+        let critical = inverse_normal_cdf(0.5 + confidence / 2.0);
+        let z_score = if standard_error > 0.0 {
+            mean / standard_error
+        } else {
+            0.0
+        };
+        let p_value = 2.0 * (1.0 - normal_cdf(z_score.abs()));
+        HeadToHead {
+            bot,
+            opponent,
+            games,
+            games_won,
+            hands: mbb.len(),
+            mbb_per_hand: mean,
+            standard_error,
+            confidence,
+            confidence_interval: [
+                mean - critical * standard_error,
+                mean + critical * standard_error,
+            ],
+            z_score,
+            p_value,
+            significant: standard_error > 0.0 && p_value < 1.0 - confidence,
+        }
+    }
+}
+
+/// The chips the defender won in each hand of a game, from the stacks at the
+/// end of every hand. Both bots start a game with the same stack.
+pub fn defender_hand_results(end_stacks: &[(i32, i32)]) -> Vec<i32> {
+    let Some((defender, challenger)) = end_stacks.first() else {
+        return vec![];
+    };
+    let mut previous = (defender + challenger) / 2;
+    end_stacks
+        .iter()
+        .map(|(defender, _)| {
+            let result = defender - previous;
+            previous = *defender;
+            result
+        })
+        .collect()
+}
+
+/// Compare `bot` with `opponent` over every game they have played against each other
+pub fn head_to_head(
+    conn: &mut PgConnection,
+    bot: i32,
+    opponent: i32,
+    confidence: f64,
+) -> Result<HeadToHead, diesel::result::Error> {
+    compare(conn, bot, opponent, None, confidence)
+}
+
+/// Compare `bot` with `opponent` over the finished games among `games`
+pub fn head_to_head_in_games(
+    conn: &mut PgConnection,
+    bot: i32,
+    opponent: i32,
+    games: &[String],
+    confidence: f64,
+) -> Result<HeadToHead, diesel::result::Error> {
+    compare(conn, bot, opponent, Some(games), confidence)
+}
+
+fn compare(
+    conn: &mut PgConnection,
+    bot: i32,
+    opponent: i32,
+    only_games: Option<&[String]>,
+    confidence: f64,
+) -> Result<HeadToHead, diesel::result::Error> {
+    use schema::{game_results, game_states, games};
+    let mut query = games::table
+        .inner_join(game_results::table)
+        .filter(
+            (games::defender.eq(bot).and(games::challenger.eq(opponent)))
+                .or(games::defender.eq(opponent).and(games::challenger.eq(bot))),
+        )
+        .into_boxed();
+    if let Some(only_games) = only_games {
+        query = query.filter(games::id.eq_any(only_games));
+    }
+    let results: Vec<(i32, GameConfig, GameResult)> = query
+        .select((games::defender, games::config, GameResult::as_select()))
+        .load(conn)?;
+    let games_won = results
+        .iter()
+        .filter(|(defender, _, result)| {
+            if *defender == bot {
+                result.defender_score > result.challenger_score
+            } else {
+                result.challenger_score > result.defender_score
+            }
+        })
+        .count();
+
+    // The last state of every hand has an end reason
+    let end_states: Vec<(String, i32, i32)> = game_states::table
+        .filter(game_states::game_id.eq_any(results.iter().map(|(_, _, result)| &result.id)))
+        .filter(game_states::end_reason.is_not_null())
+        .order_by((game_states::game_id, game_states::step))
+        .select((
+            game_states::game_id,
+            game_states::defender_stack,
+            game_states::challenger_stack,
+        ))
+        .load(conn)?;
+    let mut hands = vec![];
+    for (defender, config, result) in &results {
+        let stacks = end_states
+            .iter()
+            .filter(|(game, _, _)| *game == result.id)
+            .map(|(_, defender_stack, challenger_stack)| (*defender_stack, *challenger_stack))
+            .collect::<Vec<_>>();
+        let sign = if *defender == bot { 1.0 } else { -1.0 };
+        hands.extend(
+            defender_hand_results(&stacks)
+                .into_iter()
+                .map(|chips| sign * chips as f64 / config.big_blind as f64),
+        );
+    }
+    Ok(HeadToHead::from_hands(
+        bot,
+        opponent,
+        &hands,
+        results.len(),
+        games_won,
+        confidence,
+    ))
+}
+
+/// The standard normal CDF, from the error function approximation in
+/// Abramowitz and Stegun 7.1.26
+fn normal_cdf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs() / std::f64::consts::SQRT_2);
+    let erf = 1.0
+        - t * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))))
+            * (-x * x / 2.0).exp();
+    if x >= 0.0 {
+        (1.0 + erf) / 2.0
+    } else {
+        (1.0 - erf) / 2.0
+    }
+}
+
+/// The x for which [normal_cdf] is `p`, by bisection
+fn inverse_normal_cdf(p: f64) -> f64 {
+    let (mut low, mut high) = (-10.0, 10.0);
+    for _ in 0..100 {
+        let mid = (low + high) / 2.0;
+        if normal_cdf(mid) < p {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    (low + high) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hand_results_from_stacks() {
+        // 500 each to start: the defender wins 2, loses 10, then wins 508
+        let stacks = [(502, 498), (492, 508), (1000, 0)];
+        assert_eq!(defender_hand_results(&stacks), vec![2, -10, 508]);
+    }
+
+    #[test]
+    fn significance() {
+        assert!((inverse_normal_cdf(0.975) - 1.96).abs() < 0.001);
+        // Winning a big blind every other hand is clearly better
+        let hands = (0..1000)
+            .map(|i| if i % 2 == 0 { 1.0 } else { 0.0 })
+            .collect::<Vec<_>>();
+        let better = HeadToHead::from_hands(1, 2, &hands, 1, 1, 0.95);
+        assert_eq!(better.mbb_per_hand, 500.0);
+        assert!(better.significant && better.confidence_interval[0] > 0.0);
+        // Trading big blinds is not
+        let even = (0..1000)
+            .map(|i| if i % 2 == 0 { 1.0 } else { -1.0 })
+            .collect::<Vec<_>>();
+        let even = HeadToHead::from_hands(1, 2, &even, 1, 0, 0.95);
+        assert!(!even.significant && even.p_value > 0.9);
+    }
+}
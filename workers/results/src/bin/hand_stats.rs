@@ -0,0 +1,54 @@
+//! Counts the hand stats of games that don't have them yet, e.g. games played
+//! before they were added:
+//!
+//! ```sh
+//! cargo run --bin hand_stats -- [--all]
+//! ```
+//!
+//! With `--all`, every game is counted again.
+
+use diesel::prelude::*;
+use results::hand_stats;
+use shared::{
+    db::{
+        conn::DB_CONNECTION,
+        schema::{bot_stats, game_results, game_states, games},
+    },
+    poker::game::GameStateSQL,
+    GameConfig,
+};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init();
+    dotenvy::dotenv().ok();
+    let all = std::env::args().skip(1).any(|arg| arg == "--all");
+    let conn = &mut DB_CONNECTION.get()?;
+
+    let mut query = game_results::table
+        .inner_join(games::table)
+        .select((game_results::id, games::config))
+        .into_boxed();
+    if !all {
+        query = query.filter(diesel::dsl::not(diesel::dsl::exists(
+            bot_stats::table.filter(bot_stats::game.eq(game_results::id)),
+        )));
+    }
+    let games = query.load::<(String, GameConfig)>(conn)?;
+    log::info!("Counting {} games", games.len());
+    let mut counted = 0;
+    for (game, config) in games {
+        let states = game_states::table
+            .filter(game_states::game_id.eq(&game))
+            .order_by(game_states::step)
+            .select(GameStateSQL::as_select())
+            .load(conn)?;
+        // Games that never got their details saved can't be counted
+        if states.is_empty() {
+            continue;
+        }
+        hand_stats::save_hand_stats(conn, &game, &states, &config)?;
+        counted += 1;
+    }
+    log::info!("Counted {} games", counted);
+    Ok(())
+}
@@ -0,0 +1,102 @@
+//! Recomputes every team's and bot's rating from the game history into the
+//! shadow rating tables, and prints them next to the live ratings:
+//!
+//! ```sh
+//! cargo run --bin rerate -- [--system glicko2|elo] [--k-factor 12] [--tau 0.5] [--adjusted] [--period 600]
+//! ```
+//!
+//! `--adjusted` rates games by their luck-adjusted score. `--period` is the
+//! length of a Glicko-2 rating period in seconds, 0 for none, and defaults to
+//! the period matchmaking uses. `--compare` prints the shadow ratings that are
+//! already there, and `--promote` makes them the live ratings.
+
+use diesel::prelude::*;
+use results::{
+    rating::{RatingParams, RatingSystem},
+    rerate::{self, RerateOptions},
+};
+use shared::db::conn::DB_CONNECTION;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init();
+    dotenvy::dotenv().ok();
+    let conn = &mut DB_CONNECTION.get()?;
+
+    let mut options = RerateOptions {
+        system: RatingSystem::from_env(),
+        params: RatingParams::default(),
+        adjusted: std::env::var("RATING_SCORE").as_deref() == Ok("adjusted"),
+        rating_period: Some(default_rating_period()),
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--compare" => return compare(conn),
+            "--promote" => {
+                let games = rerate::promote(conn)?;
+                println!("Promoted the shadow ratings, rated from {} games", games);
+                return Ok(());
+            }
+            "--system" => {
+                let name = value()?;
+                options.system = RatingSystem::from_name(&name)
+                    .ok_or(format!("Unknown rating system {}", name))?;
+            }
+            "--k-factor" => options.params.k_factor = positive(&arg, &value()?)? as f32,
+            "--tau" => options.params.tau = positive(&arg, &value()?)?,
+            "--adjusted" => options.adjusted = true,
+            "--period" => {
+                let period: i64 = value()?.parse()?;
+                options.rating_period = (period > 0).then_some(period);
+            }
+            _ => return Err(format!("Unknown argument {}", arg).into()),
+        }
+    }
+
+    log::info!("Recomputing ratings with {:?}", options);
+    let (history, checksum) = rerate::load_history_snapshot(conn, options.adjusted)?;
+    let recomputed = rerate::recompute(&history, &options);
+    rerate::save_shadow(conn, &recomputed, &checksum)?;
+    println!("Recomputed ratings from {} games", history.len());
+    compare(conn)
+}
+
+/// The value of `arg`, which has to be a number more than 0
+fn positive(arg: &str, value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(parsed) if parsed.is_finite() && parsed > 0.0 => Ok(parsed),
+        _ => Err(format!("{} must be a number more than 0, not {}", arg, value)),
+    }
+}
+
+/// The length of a rating period in matchmaking, in seconds
+fn default_rating_period() -> i64 {
+    let rounds = std::env::var("RATING_PERIOD_ROUNDS")
+        .ok()
+        .and_then(|rounds| rounds.parse::<i64>().ok())
+        .unwrap_or(10)
+        .max(1);
+    let interval = std::env::var("MATCHMAKING_INTERVAL")
+        .ok()
+        .and_then(|interval| interval.parse::<i64>().ok())
+        .unwrap_or(60000);
+    rounds * interval / 1000
+}
+
+fn compare(conn: &mut PgConnection) -> Result<(), Box<dyn std::error::Error>> {
+    println!(
+        "{:<24} {:>8} {:>8} {:>8}",
+        "team", "live", "shadow", "change"
+    );
+    for (team, shadow) in rerate::compare(conn)? {
+        println!(
+            "{:<24} {:>8.1} {:>8.1} {:>+8.1}",
+            team.name,
+            team.rating,
+            shadow.rating,
+            shadow.rating - team.rating
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,59 @@
+//! Computes luck-adjusted scores for games that don't have one yet, e.g. games
+//! played before adjusted scores were added:
+//!
+//! ```sh
+//! cargo run --bin rescore -- [--all]
+//! ```
+//!
+//! With `--all`, every game is scored again.
+
+use diesel::prelude::*;
+use results::aivat;
+use shared::{
+    db::{
+        conn::DB_CONNECTION,
+        schema::{game_results, game_states, games},
+    },
+    poker::game::GameStateSQL,
+    GameConfig,
+};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init();
+    dotenvy::dotenv().ok();
+    let all = std::env::args().skip(1).any(|arg| arg == "--all");
+    let conn = &mut DB_CONNECTION.get()?;
+
+    // Forfeits are rated by their chips, so they aren't scored
+    let mut query = game_results::table
+        .filter(game_results::error_type.is_null())
+        .select(game_results::id)
+        .into_boxed();
+    if !all {
+        query = query.filter(game_results::adjusted_score.is_null());
+    }
+    let games = query.load::<String>(conn)?;
+    log::info!("Scoring {} games", games.len());
+    let mut scored = 0;
+    for game in games {
+        let states = game_states::table
+            .filter(game_states::game_id.eq(&game))
+            .order_by(game_states::step)
+            .select(GameStateSQL::as_select())
+            .load(conn)?;
+        // Games that never got their details saved can't be scored
+        if states.is_empty() {
+            continue;
+        }
+        let config = games::table
+            .find(&game)
+            .select(games::config)
+            .first::<GameConfig>(conn)?;
+        diesel::update(game_results::table.find(&game))
+            .set(game_results::adjusted_score.eq(aivat::adjusted_score(&states, &config) as f32))
+            .execute(conn)?;
+        scored += 1;
+    }
+    log::info!("Scored {} games", scored);
+    Ok(())
+}
@@ -1,12 +1,10 @@
-use diesel::prelude::*;
 use shared::{
-    db::models::{Bot, Team},
+    db::{dao::bots::BotsDao, models::NewBotStatusEvent},
     BuildResultMessage, BuildStatus, GameTask,
 };
 
 /// Handle a build result message.
-/// This function should update the database with the build result
-/// and send a notification to the events queue.
+/// This function records the new status in the bot's status history.
 ///
 /// If the build result is successful (BuildStatus::BuildSucceeded), it should also queue a test game
 /// for the bot.
@@ -15,14 +13,16 @@ pub async fn handle_build_result(
     sqs: &aws_sdk_sqs::Client,
     log_presigned: shared::PresignedRequest,
 ) -> Result<(), ()> {
-    use shared::db::schema::bots;
     let conn = &mut (*shared::db::conn::DB_CONNECTION.get().map_err(|_| ())?);
-    // update bot with build result
-    diesel::update(bots::table)
-        .filter(bots::dsl::id.eq(result.bot))
-        .set(bots::dsl::build_status.eq::<i32>(result.status.clone() as i32))
-        .execute(conn)
-        .map_err(|_| ())?;
+    conn.record_bot_status(NewBotStatusEvent {
+        bot: result.bot,
+        status: result.status,
+        error: result.error.clone(),
+        created: result
+            .timestamp
+            .unwrap_or_else(|| chrono::offset::Utc::now().timestamp()),
+    })
+    .map_err(|e| log::error!("Failed to record status of bot {}: {}", result.bot, e))?;
     log::info!(
         "Updated bot {} with build result {:?}",
         result.bot,
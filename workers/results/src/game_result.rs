@@ -5,18 +5,22 @@ use log::error;
 use shared::{
     db::{
         self,
+        dao::{bots::BotsDao, ratings::RatingsDao},
         models::{self, Bot, Game, NewBot, Team},
         schema::{
-            game_results,
+            bot_status_history, game_results,
             game_states::{self, defender_hand},
             teams,
         },
     },
     poker::game::GameStateSQL,
-    GameError, GameStatus, GameStatusMessage, WhichBot,
+    GameConfig, GameError, GameStatus, GameStatusMessage, WhichBot,
 };
 
-use crate::rating::get_rating_change;
+use crate::{
+    aivat, hand_stats,
+    rating::{lock_ratings, rated_score, RatingSystem},
+};
 
 pub fn sb_to_team(sb: WhichBot) -> [usize; 2] {
     match sb {
@@ -26,7 +30,13 @@ pub fn sb_to_team(sb: WhichBot) -> [usize; 2] {
 }
 
 pub async fn save_game_details<T: AsRef<str>>(id: T) -> Result<(), ()> {
-    log::info!("Saving game details for {}", id.as_ref());
+    let states = load_game_record(&id).await?;
+    save_game_states(&id, states)
+        .map_err(|e| log::error!("Failed to save game details for {}: {}", id.as_ref(), e))
+}
+
+/// Every state of a game, from the record uploaded by the gameplay worker
+pub async fn load_game_record<T: AsRef<str>>(id: T) -> Result<Vec<GameStateSQL>, ()> {
     let id_str = id.as_ref();
     let config = shared::aws_config().await;
     let s3 = shared::s3_client(&config).await;
@@ -44,38 +54,51 @@ pub async fn save_game_details<T: AsRef<str>>(id: T) -> Result<(), ()> {
         .await
         .map_err(|e| log::error!("Failed to collect game states: {}", e))?;
     let vec = body.to_vec();
-    let lines = vec.split(|b| *b == 0xA);
-    let conn = &mut (*shared::db::conn::DB_CONNECTION)
-        .get()
-        .map_err(|e| log::error!("Failled to open connection to SLQ: {}", e))?;
-    for line in lines {
-        let mut game_state: GameStateSQL = serde_json::from_slice(line)
-            .map_err(|e| log::error!("Failed to convert json to GameStateSQL: {}", e))?;
-        game_state.game_id = id_str.into();
+    vec.split(|b| *b == 0xA)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let mut game_state: GameStateSQL = serde_json::from_slice(line)
+                .map_err(|e| log::error!("Failed to convert json to GameStateSQL: {}", e))?;
+            game_state.game_id = id_str.into();
+            Ok(game_state)
+        })
+        .collect()
+}
+
+pub fn save_game_states<T: AsRef<str>>(
+    id: T,
+    states: Vec<GameStateSQL>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    log::info!("Saving game details for {}", id.as_ref());
+    let conn = &mut (*shared::db::conn::DB_CONNECTION).get()?;
+    for game_state in states {
         diesel::insert_into(db::schema::game_states::dsl::game_states)
             .values(game_state)
-            .execute(conn)
-            .map_err(|err| log::error!("Failed to save GameStateSQL to SQL: {}", err))?;
+            .execute(conn)?;
     }
     Ok(())
 }
 
 pub async fn handle_game_result(status: GameStatusMessage) -> Result<(), ()> {
-    let starting_stack_size = std::env::var("STARTING_STACK_SIZE")
-        .unwrap_or("500".to_string())
-        .parse::<i32>()
-        .unwrap_or(500);
-
     use shared::db::schema::{bots, games};
     let db_conn = &mut (*shared::db::conn::DB_CONNECTION.get().map_err(|_| ())?);
     let GameStatusMessage { id, result } = status;
+    // Games queued by hand aren't stored, and were played with the defaults
+    let config = games::table
+        .find(&id)
+        .select(games::config)
+        .first::<GameConfig>(db_conn)
+        .optional()
+        .map_err(|e| log::error!("Failed to load the config of game {}: {}", id, e))?
+        .unwrap_or_default();
+    let starting_stack_size = config.stack as i32;
     let error_type = result.clone().err();
     let (defender_score, challenger_score) = match result.clone() {
         Ok(GameStatus::ScoreChanged(defender_score, challenger_score)) => {
             (defender_score, challenger_score)
         }
-        Ok(GameStatus::TestGameSucceeded) => (0, 0),
-        Ok(GameStatus::TestGameFailed) => (0, 0),
+        Ok(GameStatus::TestGameSucceeded(_)) => (0, 0),
+        Ok(GameStatus::TestGameFailed(_)) => (0, 0),
         Err(e) => match e {
             GameError::InternalError => (starting_stack_size, starting_stack_size),
             GameError::InvalidActionError(which_bot) => match which_bot {
@@ -97,20 +120,44 @@ pub async fn handle_game_result(status: GameStatusMessage) -> Result<(), ()> {
         },
     };
     let (mut defender_rating_change, mut challenger_rating_change) = (0f32, 0f32);
+    let record = load_game_record(&id).await;
+    // Luck-adjusted scores only make sense for games that were played to the end
+    let (adjusted_score, record) = match (&result, record) {
+        (Ok(GameStatus::ScoreChanged(_, _)), Ok(states)) if !states.is_empty() => {
+            // Sampling equities takes a while, so it mustn't hold up the runtime
+            let (adjusted_score, states) = tokio::task::spawn_blocking(move || {
+                (aivat::adjusted_score(&states, &config) as f32, states)
+            })
+            .await
+            .map_err(|e| log::error!("Failed to score game {}: {}", id, e))?;
+            (Some(adjusted_score), Ok(states))
+        }
+        (_, record) => (None, record),
+    };
 
     // transaction
     db_conn
         .transaction(|db_conn| {
             match result {
                 Ok(GameStatus::ScoreChanged(_, _)) | Err(_) => {
-                    let game: Game =
-                        games::table.find(&id).first::<Game>(db_conn).map_err(|e| {
-                            log::error!("Failed to find game with id {}", id);
-                            e
-                        })?;
+                    // Voids and rerates mustn't change ratings while this game is rated
+                    lock_ratings(db_conn)?;
+                    // Games queued by hand aren't stored, so there is nothing to rate
+                    let Some(game) = games::table
+                        .find(&id)
+                        .first::<Game>(db_conn)
+                        .optional()?
+                    else {
+                        log::warn!("No game with id {}, not rating it", id);
+                        return Ok(());
+                    };
                     // calculate the bots ratings
-                    let score = (starting_stack_size as f32 + defender_score as f32)
-                        / (2.0f32 * starting_stack_size as f32);
+                    let score = rated_score(
+                        defender_score,
+                        adjusted_score,
+                        error_type.as_ref(),
+                        starting_stack_size,
+                    );
                     log::info!(
                         "Score: {}, defender score {}, challenger score {}, starting stack size {}",
                         score,
@@ -136,28 +183,55 @@ pub async fn handle_game_result(status: GameStatusMessage) -> Result<(), ()> {
                         )
                         .first::<(Bot, Team)>(db_conn)?;
 
-                    // Don't rate games that had an internal error
-                    (defender_rating_change, challenger_rating_change) = get_rating_change(
-                        defender_team.rating,
+                    let rating_system = RatingSystem::from_env();
+                    let (mut defender_rating, mut challenger_rating) = rating_system.rate(
+                        (&defender_team).into(),
+                        (&challenger_team).into(),
                         score,
-                        challenger_team.rating,
-                        1.0 - score,
                     );
-                    // don't update rating for internal errors
-                    match error_type {
-                        Some(GameError::InternalError) => {
-                            (defender_rating_change, challenger_rating_change) = (0.0, 0.0);
-                        }
-                        _ => {}
+                    let (mut defender_bot_rating, mut challenger_bot_rating) =
+                        rating_system.rate((&defender_bot).into(), (&challenger_bot).into(), score);
+                    let mut games_played = 1;
+                    // don't update rating for unrated games or internal errors
+                    if !game.rated || matches!(error_type, Some(GameError::InternalError)) {
+                        (defender_rating, challenger_rating) =
+                            ((&defender_team).into(), (&challenger_team).into());
+                        (defender_bot_rating, challenger_bot_rating) =
+                            ((&defender_bot).into(), (&challenger_bot).into());
+                        games_played = 0;
                     }
+                    defender_rating_change = defender_rating.rating - defender_team.rating;
+                    challenger_rating_change = challenger_rating.rating - challenger_team.rating;
 
                     // Update rating
                     let defender: Team = diesel::update(teams::table.find(defender_team.id))
-                        .set(teams::dsl::rating.eq(teams::dsl::rating + defender_rating_change))
+                        .set((
+                            teams::dsl::rating.eq(teams::dsl::rating + defender_rating_change),
+                            teams::dsl::rating_deviation.eq(defender_rating.deviation),
+                            teams::dsl::rating_volatility.eq(defender_rating.volatility),
+                        ))
                         .get_result::<Team>(db_conn)?;
                     let challenger: Team = diesel::update(teams::table.find(challenger_team.id))
-                        .set(teams::dsl::rating.eq(teams::dsl::rating + challenger_rating_change))
+                        .set((
+                            teams::dsl::rating.eq(teams::dsl::rating + challenger_rating_change),
+                            teams::dsl::rating_deviation.eq(challenger_rating.deviation),
+                            teams::dsl::rating_volatility.eq(challenger_rating.volatility),
+                        ))
                         .get_result::<Team>(db_conn)?;
+                    for (bot, rating) in [
+                        (&defender_bot, defender_bot_rating),
+                        (&challenger_bot, challenger_bot_rating),
+                    ] {
+                        diesel::update(bots::table.find(bot.id))
+                            .set((
+                                bots::dsl::rating
+                                    .eq(bots::dsl::rating + (rating.rating - bot.rating)),
+                                bots::dsl::rating_deviation.eq(rating.deviation),
+                                bots::dsl::rating_volatility.eq(rating.volatility),
+                                bots::dsl::games_played.eq(bots::dsl::games_played + games_played),
+                            ))
+                            .execute(db_conn)?;
+                    }
                     log::debug!(
                         "Defender (+{}): {:?}, challenger (+{}): {:?}",
                         defender_rating_change,
@@ -175,6 +249,21 @@ pub async fn handle_game_result(status: GameStatusMessage) -> Result<(), ()> {
                         error_type: error_type.clone(),
                         challenger_rating: challenger.rating,
                         defender_rating: defender.rating,
+                        adjusted_score,
+                        defender_deviation_before: Some(defender_team.rating_deviation),
+                        defender_volatility_before: Some(defender_team.rating_volatility),
+                        challenger_deviation_before: Some(challenger_team.rating_deviation),
+                        challenger_volatility_before: Some(challenger_team.rating_volatility),
+                        defender_bot_rating_change: Some(
+                            defender_bot_rating.rating - defender_bot.rating,
+                        ),
+                        challenger_bot_rating_change: Some(
+                            challenger_bot_rating.rating - challenger_bot.rating,
+                        ),
+                        defender_bot_deviation_before: Some(defender_bot.rating_deviation),
+                        defender_bot_volatility_before: Some(defender_bot.rating_volatility),
+                        challenger_bot_deviation_before: Some(challenger_bot.rating_deviation),
+                        challenger_bot_volatility_before: Some(challenger_bot.rating_volatility),
                     };
                     diesel::insert_into(game_results::dsl::game_results)
                         .values(&new_result)
@@ -183,8 +272,31 @@ pub async fn handle_game_result(status: GameStatusMessage) -> Result<(), ()> {
                         .set(&new_result)
                         .execute(db_conn)?;
                     log::debug!("Inserted game result for {}", id.clone());
+
+                    if !game.rated {
+                        return Ok(());
+                    }
+                    let now = chrono::offset::Utc::now().timestamp();
+                    db_conn.record_ratings(&[
+                        models::NewRatingHistoryEntry {
+                            team: defender.id,
+                            bot: Some(defender_bot.id),
+                            game: Some(id.clone()),
+                            rating: defender.rating,
+                            rating_deviation: Some(defender.rating_deviation),
+                            created: now,
+                        },
+                        models::NewRatingHistoryEntry {
+                            team: challenger.id,
+                            bot: Some(challenger_bot.id),
+                            game: Some(id.clone()),
+                            rating: challenger.rating,
+                            rating_deviation: Some(challenger.rating_deviation),
+                            created: now,
+                        },
+                    ])?;
                 }
-                Ok(GameStatus::TestGameSucceeded) => {
+                Ok(GameStatus::TestGameSucceeded(_)) => {
                     // set the active bot for the team if they don't have one
                     let (bot, team): (Bot, Team) = shared::db::schema::bots::dsl::bots
                         .find(
@@ -203,43 +315,64 @@ pub async fn handle_game_result(status: GameStatusMessage) -> Result<(), ()> {
                         })?;
                     log::debug!("Bot: {:?}, team: {:?}", bot, team);
 
-                    // set the active bot for the team if they don't have one
-                    //if team.active_bot.is_none() {
-                    diesel::update(shared::db::schema::teams::dsl::teams)
-                        .filter(shared::db::schema::teams::dsl::id.eq(team.id))
-                        .set(shared::db::schema::teams::dsl::active_bot.eq(bot.id))
-                        .execute(db_conn)?;
-                    //}
-                    diesel::update(bots::dsl::bots)
-                        .filter(
-                            bots::dsl::id.eq(id
-                                .parse::<i32>()
-                                .map_err(|_| diesel::result::Error::RollbackTransaction)?),
-                        )
-                        .set(
-                            bots::dsl::build_status
-                                .eq(shared::BuildStatus::TestGameSucceeded as i32),
-                        )
-                        .execute(db_conn)?;
+                    // Only a bot's first build makes it the team's active bot.
+                    // Admins build bots again, which mustn't replace the bot
+                    // the team chose or bring back a deleted one.
+                    let builds = bot_status_history::table
+                        .filter(bot_status_history::bot.eq(bot.id))
+                        .filter(bot_status_history::status.eq(shared::BuildStatus::Queued))
+                        .count()
+                        .get_result::<i64>(db_conn)?;
+                    if builds <= 1 && bot.deleted_at.is_none() {
+                        diesel::update(shared::db::schema::teams::dsl::teams)
+                            .filter(shared::db::schema::teams::dsl::id.eq(team.id))
+                            .set(shared::db::schema::teams::dsl::active_bot.eq(bot.id))
+                            .execute(db_conn)?;
+                    }
+                    db_conn.record_bot_status(models::NewBotStatusEvent {
+                        bot: bot.id,
+                        status: shared::BuildStatus::TestGameSucceeded,
+                        error: None,
+                        created: chrono::offset::Utc::now().timestamp(),
+                    })?;
                 }
-                Ok(GameStatus::TestGameFailed) => {
-                    diesel::update(bots::dsl::bots)
-                        .filter(
-                            bots::dsl::id.eq(id
-                                .parse::<i32>()
-                                .map_err(|_| diesel::result::Error::RollbackTransaction)?),
-                        )
-                        .set(bots::dsl::build_status.eq(shared::BuildStatus::TestGameFailed as i32))
-                        .execute(db_conn)?;
+                Ok(GameStatus::TestGameFailed(scenarios)) => {
+                    // The full report is in the test game log
+                    let failed = scenarios
+                        .iter()
+                        .filter_map(|scenario| {
+                            let error = scenario.error.as_ref()?;
+                            Some(format!("{}: {:?}", scenario.name, error))
+                        })
+                        .collect::<Vec<_>>();
+                    db_conn.record_bot_status(models::NewBotStatusEvent {
+                        bot: id
+                            .parse::<i32>()
+                            .map_err(|_| diesel::result::Error::RollbackTransaction)?,
+                        status: shared::BuildStatus::TestGameFailed,
+                        // Results sent before scenarios were reported have none
+                        error: (!failed.is_empty())
+                            .then(|| format!("Failed scenarios: {}", failed.join(", "))),
+                        created: chrono::offset::Utc::now().timestamp(),
+                    })?;
                 }
             };
             Ok::<(), diesel::result::Error>(())
         })
         .map_err(|_| ())?;
 
+    // Stats are optional too, and only kept for games in the database
+    if let Ok(states) = &record {
+        if let Err(e) = hand_stats::save_hand_stats(db_conn, &id, states, &config) {
+            log::error!("Failed to save hand stats for {}: {}", id, e);
+        }
+    }
+
     // Don't fail if we can't save the game details
-    if let Err(_) = save_game_details(id.clone()).await {
-        log::error!("Failed to save game details for {}", id);
+    if let Ok(states) = record {
+        if let Err(e) = save_game_states(&id, states) {
+            log::error!("Failed to save game details for {}: {}", id, e);
+        }
     }
 
     Ok(())
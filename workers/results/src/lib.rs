@@ -1,4 +1,11 @@
+pub mod aivat;
+pub mod analysis;
+#[cfg(feature = "worker")]
 pub mod build_result;
+#[cfg(feature = "worker")]
 pub mod game_result;
+#[cfg(feature = "worker")]
 pub mod matchmaking;
-pub mod rating;
+pub mod rerate;
+
+pub use shared::{hand_stats, rating, tournament, void};
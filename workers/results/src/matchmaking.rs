@@ -1,19 +1,38 @@
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 
-use diesel::{ExpressionMethods, JoinOnDsl, NullableExpressionMethods, QueryDsl, RunQueryDsl};
-use rand::Rng;
+use aws_sdk_sqs::types::QueueAttributeName;
+use diesel::{
+    sql_types::Float4, Connection, ExpressionMethods, JoinOnDsl, NullableExpressionMethods,
+    PgConnection, QueryDsl, RunQueryDsl,
+};
+use rand::{seq::SliceRandom, Rng};
 use shared::db::{
     conn::DB_CONNECTION,
-    dao::games::GamesDao,
-    models::{Bot, Team},
+    dao::games::{GameQueue, GamesDao},
+    models::Team,
     schema,
 };
 
+use crate::rating::{self, RatingSystem};
+
 pub async fn matchmake(s3_client: &aws_sdk_s3::Client, sqs_client: &aws_sdk_sqs::Client) {
     log::info!("starting matchmaking");
     let mut player_count = None;
+    let rating_period_rounds = std::env::var("RATING_PERIOD_ROUNDS")
+        .unwrap_or("10".to_string())
+        .parse::<u64>()
+        .unwrap_or(10)
+        .max(1);
+    let mut round = 0u64;
     loop {
         log::info!("matchmaking");
+        if RatingSystem::from_env() == RatingSystem::Glicko2 && round % rating_period_rounds == 0 {
+            if let Err(e) = start_rating_period() {
+                log::error!("Error starting a rating period: {:?}", e);
+            }
+        }
+        round += 1;
         // load all teams
         match matchmake_round(&s3_client, &sqs_client).await {
             Err(e) => {
@@ -36,6 +55,26 @@ pub async fn matchmake(s3_client: &aws_sdk_s3::Client, sqs_client: &aws_sdk_sqs:
     }
 }
 
+/// Every team's and bot's rating gets less certain at the start of a Glicko-2
+/// rating period, so teams that stop playing can move quickly when they come back
+pub fn start_rating_period() -> Result<(), Box<dyn Error>> {
+    let db_conn = &mut (*DB_CONNECTION).get()?;
+    let deviation = rating::start_rating_period_sql();
+    db_conn.transaction(|db_conn| {
+        // A game rated at the same time would write back the deviation it read
+        // before, and the increase would be lost
+        rating::lock_ratings(db_conn)?;
+        diesel::update(schema::teams::table.filter(schema::teams::dsl::deleted_at.is_null()))
+            .set(schema::teams::dsl::rating_deviation.eq(diesel::dsl::sql::<Float4>(&deviation)))
+            .execute(db_conn)?;
+        diesel::update(schema::bots::table.filter(schema::bots::dsl::deleted_at.is_null()))
+            .set(schema::bots::dsl::rating_deviation.eq(diesel::dsl::sql::<Float4>(&deviation)))
+            .execute(db_conn)?;
+        Ok::<_, diesel::result::Error>(())
+    })?;
+    Ok(())
+}
+
 pub async fn matchmake_round(
     s3_client: &aws_sdk_s3::Client,
     sqs_client: &aws_sdk_sqs::Client,
@@ -43,32 +82,64 @@ pub async fn matchmake_round(
     let db_conn = &mut (*DB_CONNECTION).get().unwrap();
     let teams: Vec<Team> = schema::teams::table
         .filter(schema::teams::dsl::deleted_at.is_null())
+        .filter(schema::teams::dsl::active_bot.is_not_null())
         .order_by(schema::teams::dsl::rating)
         .load::<Team>(db_conn)?;
-
-    let mut rng = rand::thread_rng();
-    if teams.len() == 1 {
-        return Ok(1);
+    if teams.len() < 2 {
+        return Ok(teams.len());
     }
-    for i in 0..teams.len() {
-        log::debug!("Matchmaking for team {}", teams[i].name);
-        // pick another team to play against, randomly within 5 indices
-        let mut other = rng.gen_range(i.saturating_sub(5)..(i + 6).min(teams.len()));
-        while other == i {
-            // don't play against yourself
-            other = rng.gen_range(i.saturating_sub(5).max(0)..(i + 6).min(teams.len()));
-        }
-        let other = &teams[other];
-        let this = &teams[i];
+
+    let window = std::env::var("MATCHMAKING_WINDOW")
+        .unwrap_or("3600".to_string())
+        .parse::<i64>()
+        .unwrap_or(3600);
+    let spread = std::env::var("MATCHMAKING_RATING_SPREAD")
+        .unwrap_or("5".to_string())
+        .parse::<usize>()
+        .unwrap_or(5);
+    let recent = recent_games(db_conn, chrono::Utc::now().timestamp() - window)?;
+    let queued = queue_depth(sqs_client).await;
+    let budget = game_budget(
+        queued,
+        recent.running,
+        env_limit("MATCHMAKING_MAX_QUEUED", 20),
+        env_limit("MATCHMAKING_MAX_RUNNING", 100),
+    );
+    let ids = teams.iter().map(|team| team.id).collect::<Vec<_>>();
+    let mut pairs = pair_teams(
+        &ids,
+        &recent.pairings,
+        &recent.busy,
+        spread,
+        &mut rand::thread_rng(),
+    );
+    // The teams that have played least come first, so they keep their games
+    pairs.truncate(budget);
+    log::info!(
+        "Pairing {} of {} teams, {} have running games. {} games unfinished, {:?} queued, room for {}",
+        pairs.len() * 2,
+        teams.len(),
+        recent.busy.len(),
+        recent.running,
+        queued,
+        budget
+    );
+
+    for (defender, challenger) in pairs {
+        let find = |id: i32| teams.iter().find(|team| team.id == id).unwrap();
+        let (other, this) = (find(defender), find(challenger));
+        log::debug!("Matchmaking for team {}", this.name);
         match db_conn
             .create_game(
                 other,
                 this,
                 true,
-                &*std::env::var("GAME_LOGS_S3_BUCKET").unwrap(),
-                &*std::env::var("NEW_GAMES_QUEUE_URL").unwrap(),
-                &sqs_client,
-                &s3_client,
+                &GameQueue {
+                    game_logs_s3_bucket: &std::env::var("GAME_LOGS_S3_BUCKET").unwrap(),
+                    new_games_sqs_queue: &std::env::var("NEW_GAMES_QUEUE_URL").unwrap(),
+                    sqs_client,
+                    s3_client,
+                },
             )
             .await
         {
@@ -77,14 +148,265 @@ pub async fn matchmake_round(
             }
             Err(err) => {
                 log::info!(
-                    "Failed to create game between {} and {}",
+                    "Failed to create game between {} and {}: {}",
                     this.name,
-                    other.name
+                    other.name,
+                    err
                 );
             }
         }
     }
 
-    log::info!("teams: {:?}", teams);
     Ok(teams.len())
 }
+
+fn env_limit(var: &str, default: usize) -> usize {
+    std::env::var(var)
+        .ok()
+        .and_then(|limit| limit.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Roughly how many games are waiting in the new games queue, if SQS says
+async fn queue_depth(sqs_client: &aws_sdk_sqs::Client) -> Option<usize> {
+    let attributes = sqs_client
+        .get_queue_attributes()
+        .queue_url(std::env::var("NEW_GAMES_QUEUE_URL").unwrap())
+        .attribute_names(QueueAttributeName::ApproximateNumberOfMessages)
+        .send()
+        .await
+        .map_err(|e| log::warn!("Could not read the new games queue depth: {}", e))
+        .ok()?;
+    attributes
+        .attributes()?
+        .get(&QueueAttributeName::ApproximateNumberOfMessages)?
+        .parse()
+        .ok()
+}
+
+/// How many games can be created this round without going over
+/// `max_queued` games waiting for a worker or `max_running` unfinished games.
+/// Created games count as running from the moment they are queued.
+pub fn game_budget(
+    queued: Option<usize>,
+    running: usize,
+    max_queued: usize,
+    max_running: usize,
+) -> usize {
+    let budget = max_running.saturating_sub(running);
+    match queued {
+        Some(queued) => budget.min(max_queued.saturating_sub(queued)),
+        None => budget,
+    }
+}
+
+/// Games created since the start of the matchmaking window
+struct RecentGames {
+    /// The teams in every game, oldest first
+    pairings: Vec<(i32, i32)>,
+    /// Teams that are still playing one of them
+    busy: HashSet<i32>,
+    /// How many of them haven't finished
+    running: usize,
+}
+
+/// Games that have been running for longer than the window are assumed to be
+/// lost, so they don't hold anyone back.
+fn recent_games(
+    db_conn: &mut PgConnection,
+    since: i64,
+) -> Result<RecentGames, diesel::result::Error> {
+    use schema::{bots, games};
+    let games: Vec<(i32, i32, bool)> = games::table
+        .filter(games::created.ge(since))
+        .order_by((games::created.asc(), games::id.asc()))
+        .select((games::defender, games::challenger, games::running))
+        .load(db_conn)?;
+    let bot_teams: HashMap<i32, i32> = bots::table
+        .filter(
+            bots::id.eq_any(
+                games
+                    .iter()
+                    .flat_map(|(defender, challenger, _)| [*defender, *challenger])
+                    .collect::<Vec<_>>(),
+            ),
+        )
+        .select((bots::id, bots::team))
+        .load::<(i32, i32)>(db_conn)?
+        .into_iter()
+        .collect();
+
+    let mut recent = RecentGames {
+        pairings: vec![],
+        busy: HashSet::new(),
+        running: games.iter().filter(|(_, _, running)| *running).count(),
+    };
+    for (defender, challenger, running) in games {
+        let (Some(defender), Some(challenger)) =
+            (bot_teams.get(&defender), bot_teams.get(&challenger))
+        else {
+            continue;
+        };
+        recent.pairings.push((*defender, *challenger));
+        if running {
+            recent.busy.extend([*defender, *challenger]);
+        }
+    }
+    Ok(recent)
+}
+
+/// Choose this round's games, as (defender, challenger) pairs of team ids.
+/// `teams` is in rating order and `recent` holds the pairings in the current
+/// window, oldest first.
+///
+/// Teams that are still playing sit the round out, and everyone else plays at
+/// most one game, so the game counts stay even. The teams that have played
+/// the fewest games choose first. Each picks the opponent it has played least
+/// in the window among those within `spread` places of it in the ratings,
+/// and only plays the same team twice in a row when there is nobody else.
+pub fn pair_teams<R: Rng>(
+    teams: &[i32],
+    recent: &[(i32, i32)],
+    busy: &HashSet<i32>,
+    spread: usize,
+    rng: &mut R,
+) -> Vec<(i32, i32)> {
+    let mut games = HashMap::<i32, usize>::new();
+    let mut pairings = HashMap::<(i32, i32), usize>::new();
+    let mut last_opponent = HashMap::new();
+    for &(defender, challenger) in recent {
+        *games.entry(defender).or_default() += 1;
+        *games.entry(challenger).or_default() += 1;
+        *pairings
+            .entry((defender.min(challenger), defender.max(challenger)))
+            .or_default() += 1;
+        last_opponent.insert(defender, challenger);
+        last_opponent.insert(challenger, defender);
+    }
+    let rank = teams
+        .iter()
+        .enumerate()
+        .map(|(i, team)| (*team, i))
+        .collect::<HashMap<_, _>>();
+
+    let mut waiting = teams
+        .iter()
+        .copied()
+        .filter(|team| !busy.contains(team))
+        .collect::<Vec<_>>();
+    // Teams with the same number of games go in a random order
+    waiting.shuffle(rng);
+    waiting.sort_by_key(|team| games.get(team).copied().unwrap_or(0));
+
+    let mut paired = HashSet::new();
+    let mut pairs = vec![];
+    for &team in &waiting {
+        if paired.contains(&team) {
+            continue;
+        }
+        let opponent = waiting
+            .iter()
+            .copied()
+            .filter(|other| *other != team && !paired.contains(other))
+            .min_by_key(|other| {
+                let distance = rank[&team].abs_diff(rank[other]);
+                (
+                    last_opponent.get(&team) == Some(other),
+                    distance > spread,
+                    pairings
+                        .get(&(team.min(*other), team.max(*other)))
+                        .copied()
+                        .unwrap_or(0),
+                    distance,
+                )
+            });
+        if let Some(opponent) = opponent {
+            paired.extend([team, opponent]);
+            pairs.push((opponent, team));
+        }
+    }
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn pairs_everyone_once_and_skips_busy_teams() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let busy = HashSet::from([3]);
+        let pairs = pair_teams(&[1, 2, 3, 4, 5], &[(2, 3)], &busy, 5, &mut rng);
+        let mut playing = pairs
+            .iter()
+            .flat_map(|(defender, challenger)| [*defender, *challenger])
+            .collect::<Vec<_>>();
+        playing.sort();
+        assert_eq!(playing, vec![1, 2, 4, 5]);
+        // No immediate rematches when there is somebody else to play
+        let pairs = pair_teams(
+            &[1, 2, 3, 4],
+            &[(1, 2), (3, 4)],
+            &HashSet::new(),
+            5,
+            &mut rng,
+        );
+        assert!(pairs
+            .iter()
+            .all(|pair| ![(1, 2), (2, 1), (3, 4), (4, 3)].contains(pair)));
+        // Unless there isn't
+        assert_eq!(
+            pair_teams(&[1, 2], &[(1, 2)], &HashSet::new(), 5, &mut rng).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn budget_keeps_within_limits() {
+        assert_eq!(game_budget(Some(5), 30, 20, 100), 15);
+        assert_eq!(game_budget(Some(5), 90, 20, 100), 10);
+        // A backlog stops new games altogether
+        assert_eq!(game_budget(Some(25), 30, 20, 100), 0);
+        assert_eq!(game_budget(None, 120, 20, 100), 0);
+        assert_eq!(game_budget(None, 30, 20, 100), 70);
+    }
+
+    #[test]
+    fn spreads_games_evenly() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let teams = (0..7).collect::<Vec<_>>();
+        let mut recent = vec![];
+        for round in 0..70 {
+            // One team is always still playing from the round before
+            let busy = HashSet::from([round % 7]);
+            recent.extend(pair_teams(&teams, &recent, &busy, 2, &mut rng));
+        }
+        let games = teams
+            .iter()
+            .map(|team| {
+                recent
+                    .iter()
+                    .filter(|(defender, challenger)| defender == team || challenger == team)
+                    .count()
+            })
+            .collect::<Vec<_>>();
+        assert!(
+            games.iter().max().unwrap() - games.iter().min().unwrap() <= 1,
+            "{:?}",
+            games
+        );
+        // Every team meets every team within the rating spread
+        for a in 0..7 {
+            for b in a + 1..(a + 3).min(7) {
+                assert!(
+                    recent.contains(&(a, b)) || recent.contains(&(b, a)),
+                    "{} {}",
+                    a,
+                    b
+                );
+            }
+        }
+    }
+}
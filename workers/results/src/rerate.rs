@@ -0,0 +1,514 @@
+//! Recomputes every rating from the game history under a chosen rating system
+//! and constants, into the shadow rating tables. Once they look right they can
+//! be promoted to the live ratings in one transaction.
+
+use std::collections::{HashMap, HashSet};
+
+use diesel::prelude::*;
+use shared::{
+    db::{
+        dao::ratings::RatingsDao,
+        models::{
+            GameResult, NewRatingHistoryEntry, ShadowBotRating, ShadowGameRating, ShadowTeamRating,
+            Team,
+        },
+        schema::{
+            bots, game_results, games, rating_history, shadow_bot_ratings, shadow_game_ratings,
+            shadow_ratings_checksum, shadow_team_ratings, teams,
+        },
+        schema_aliases::{challenger_bots, defender_bots},
+    },
+    GameConfig, GameError,
+};
+
+use crate::rating::{self, lock_ratings, normalized_score, Rating, RatingParams, RatingSystem};
+
+#[derive(Debug, Clone, Copy)]
+pub struct RerateOptions {
+    pub system: RatingSystem,
+    pub params: RatingParams,
+    /// Rate games by their luck-adjusted score instead of the chips won, when
+    /// they have one
+    pub adjusted: bool,
+    /// Seconds in a Glicko-2 rating period, if deviations should grow between
+    /// periods like they do in matchmaking
+    pub rating_period: Option<i64>,
+}
+
+/// A finished rated game, as it is replayed
+#[derive(Debug, Clone)]
+pub struct PlayedGame {
+    pub id: String,
+    pub finished: i64,
+    pub defender_team: i32,
+    pub challenger_team: i32,
+    pub defender_bot: i32,
+    pub challenger_bot: i32,
+    /// The defender's score between 0 and 1
+    pub score: f32,
+}
+
+#[derive(Debug, Default)]
+pub struct Recomputed {
+    pub teams: HashMap<i32, Rating>,
+    /// Bot ratings with the number of rated games they played
+    pub bots: HashMap<i32, (Rating, i32)>,
+    pub games: Vec<ShadowGameRating>,
+}
+
+/// Every rated game that counts towards the ratings, oldest first. Voided
+/// games and internal errors are left out, like they are live.
+pub fn load_history(
+    conn: &mut PgConnection,
+    adjusted: bool,
+) -> Result<Vec<PlayedGame>, diesel::result::Error> {
+    Ok(game_results::table
+        .inner_join(games::table)
+        .inner_join(defender_bots.on(games::defender.eq(defender_bots.field(bots::id))))
+        .inner_join(challenger_bots.on(games::challenger.eq(challenger_bots.field(bots::id))))
+        .filter(games::rated.eq(true))
+        .filter(game_results::voided.eq(false))
+        .order_by(game_results::finish_order.asc())
+        .select((
+            GameResult::as_select(),
+            games::config,
+            defender_bots.field(bots::team),
+            challenger_bots.field(bots::team),
+            games::defender,
+            games::challenger,
+        ))
+        .load::<(GameResult, GameConfig, i32, i32, i32, i32)>(conn)?
+        .into_iter()
+        .filter(|(result, ..)| !matches!(result.error_type, Some(GameError::InternalError)))
+        .map(
+            |(result, config, defender_team, challenger_team, defender_bot, challenger_bot)| {
+                // Forfeits are rated by their chips, like they are live
+                let score = match result.adjusted_score {
+                    Some(adjusted_score) if adjusted && result.error_type.is_none() => {
+                        adjusted_score
+                    }
+                    _ => result.defender_score as f32,
+                };
+                PlayedGame {
+                    id: result.id,
+                    finished: result.updated_at,
+                    defender_team,
+                    challenger_team,
+                    defender_bot,
+                    challenger_bot,
+                    score: normalized_score(score, config.stack as i32),
+                }
+            },
+        )
+        .collect())
+}
+
+/// A checksum of the results of every rated game that hasn't been voided, in
+/// the order they finished. It changes whenever a game finishes or is voided,
+/// or a result is scored again.
+pub fn history_checksum(conn: &mut PgConnection) -> Result<String, diesel::result::Error> {
+    diesel::select(diesel::dsl::sql::<diesel::sql_types::Text>(
+        "(SELECT md5(COALESCE(string_agg(
+            concat_ws(':', r.id, r.defender_score, r.challenger_score,
+                COALESCE(r.adjusted_score::TEXT, '-'), COALESCE(r.error_type, '-')),
+            ',' ORDER BY r.finish_order), ''))
+        FROM game_results r INNER JOIN games g ON g.id = r.id
+        WHERE g.rated AND NOT r.voided)",
+    ))
+    .get_result(conn)
+}
+
+/// [load_history] and the [history_checksum] of the same results
+pub fn load_history_snapshot(
+    conn: &mut PgConnection,
+    adjusted: bool,
+) -> Result<(Vec<PlayedGame>, String), diesel::result::Error> {
+    conn.build_transaction()
+        .repeatable_read()
+        .read_only()
+        .run(|conn| Ok((load_history(conn, adjusted)?, history_checksum(conn)?)))
+}
+
+/// Rate `games` in order, starting every team and bot from scratch
+pub fn recompute(games: &[PlayedGame], options: &RerateOptions) -> Recomputed {
+    let mut recomputed = Recomputed::default();
+    let mut period = None;
+    for game in games {
+        if let (RatingSystem::Glicko2, Some(length)) = (options.system, options.rating_period) {
+            let current = game.finished / length.max(1);
+            if period.is_some_and(|period| period != current) {
+                for rating in recomputed.teams.values_mut() {
+                    *rating = rating::start_rating_period(*rating);
+                }
+                for (rating, _) in recomputed.bots.values_mut() {
+                    *rating = rating::start_rating_period(*rating);
+                }
+            }
+            period = Some(current);
+        }
+
+        let defender = *recomputed.teams.entry(game.defender_team).or_default();
+        let challenger = *recomputed.teams.entry(game.challenger_team).or_default();
+        let (new_defender, new_challenger) =
+            options
+                .system
+                .rate_with(&options.params, defender, challenger, game.score);
+        recomputed.teams.insert(game.defender_team, new_defender);
+        recomputed
+            .teams
+            .insert(game.challenger_team, new_challenger);
+
+        let (defender_bot, _) = *recomputed.bots.entry(game.defender_bot).or_default();
+        let (challenger_bot, _) = *recomputed.bots.entry(game.challenger_bot).or_default();
+        let (new_defender_bot, new_challenger_bot) =
+            options
+                .system
+                .rate_with(&options.params, defender_bot, challenger_bot, game.score);
+        for (bot, rating) in [
+            (game.defender_bot, new_defender_bot),
+            (game.challenger_bot, new_challenger_bot),
+        ] {
+            let entry = recomputed.bots.get_mut(&bot).unwrap();
+            *entry = (rating, entry.1 + 1);
+        }
+        recomputed.games.push(ShadowGameRating {
+            game: game.id.clone(),
+            defender_rating_change: new_defender.rating - defender.rating,
+            challenger_rating_change: new_challenger.rating - challenger.rating,
+            defender_rating: new_defender.rating,
+            challenger_rating: new_challenger.rating,
+            defender_rating_deviation: new_defender.deviation,
+            challenger_rating_deviation: new_challenger.deviation,
+            defender_deviation_before: defender.deviation,
+            defender_volatility_before: defender.volatility,
+            challenger_deviation_before: challenger.deviation,
+            challenger_volatility_before: challenger.volatility,
+            defender_bot_rating_change: new_defender_bot.rating - defender_bot.rating,
+            challenger_bot_rating_change: new_challenger_bot.rating - challenger_bot.rating,
+            defender_bot_deviation_before: defender_bot.deviation,
+            defender_bot_volatility_before: defender_bot.volatility,
+            challenger_bot_deviation_before: challenger_bot.deviation,
+            challenger_bot_volatility_before: challenger_bot.volatility,
+        });
+    }
+    recomputed
+}
+
+/// Replace the shadow ratings with `recomputed`, from the results with the
+/// [history_checksum] `checksum`. Teams and bots that never played a rated
+/// game get the starting rating.
+pub fn save_shadow(
+    conn: &mut PgConnection,
+    recomputed: &Recomputed,
+    checksum: &str,
+) -> Result<(), diesel::result::Error> {
+    conn.transaction(|conn| {
+        clear_shadow(conn)?;
+        diesel::insert_into(shadow_ratings_checksum::table)
+            .values(shadow_ratings_checksum::checksum.eq(checksum))
+            .execute(conn)?;
+
+        let team_ids = teams::table.select(teams::id).load::<i32>(conn)?;
+        let team_ratings = team_ids
+            .into_iter()
+            .map(|team| {
+                let rating = recomputed.teams.get(&team).copied().unwrap_or_default();
+                ShadowTeamRating {
+                    team,
+                    rating: rating.rating,
+                    rating_deviation: rating.deviation,
+                    rating_volatility: rating.volatility,
+                }
+            })
+            .collect::<Vec<_>>();
+        let bot_ids = bots::table.select(bots::id).load::<i32>(conn)?;
+        let bot_ratings = bot_ids
+            .into_iter()
+            .map(|bot| {
+                let (rating, games_played) = recomputed.bots.get(&bot).copied().unwrap_or_default();
+                ShadowBotRating {
+                    bot,
+                    rating: rating.rating,
+                    rating_deviation: rating.deviation,
+                    rating_volatility: rating.volatility,
+                    games_played,
+                }
+            })
+            .collect::<Vec<_>>();
+        // Postgres allows at most 65535 bind parameters in a query
+        for chunk in team_ratings.chunks(1000) {
+            diesel::insert_into(shadow_team_ratings::table)
+                .values(chunk)
+                .execute(conn)?;
+        }
+        for chunk in bot_ratings.chunks(1000) {
+            diesel::insert_into(shadow_bot_ratings::table)
+                .values(chunk)
+                .execute(conn)?;
+        }
+        for chunk in recomputed.games.chunks(1000) {
+            diesel::insert_into(shadow_game_ratings::table)
+                .values(chunk)
+                .execute(conn)?;
+        }
+        Ok(())
+    })
+}
+
+/// Every team with its live and shadow rating, best shadow rating first
+pub fn compare(
+    conn: &mut PgConnection,
+) -> Result<Vec<(Team, ShadowTeamRating)>, diesel::result::Error> {
+    teams::table
+        .inner_join(shadow_team_ratings::table)
+        .filter(teams::deleted_at.is_null())
+        .order_by(shadow_team_ratings::rating.desc())
+        .select((Team::as_select(), ShadowTeamRating::as_select()))
+        .load(conn)
+}
+
+#[derive(Debug)]
+pub enum PromoteError {
+    /// Games were finished or voided since the shadow ratings were computed
+    Stale {
+        missing: usize,
+        extra: usize,
+    },
+    /// Results of the games were scored again since
+    Changed,
+    Database(diesel::result::Error),
+}
+
+impl std::fmt::Display for PromoteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PromoteError::Stale { missing, extra } => write!(
+                f,
+                "The shadow ratings are out of date: {} rated games aren't in them and {} have been voided since. Recompute them first.",
+                missing, extra
+            ),
+            PromoteError::Changed => write!(
+                f,
+                "The shadow ratings are out of date: the results of some games have changed since. Recompute them first."
+            ),
+            PromoteError::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for PromoteError {}
+
+impl From<diesel::result::Error> for PromoteError {
+    fn from(e: diesel::result::Error) -> Self {
+        PromoteError::Database(e)
+    }
+}
+
+/// Replace the live ratings of every team and bot, every game's rating changes
+/// and the rating history of games with the shadow ratings, then clear them.
+/// It holds the ratings lock, so no game can be rated or voided in between.
+pub fn promote(conn: &mut PgConnection) -> Result<usize, PromoteError> {
+    conn.transaction(|conn| {
+        lock_ratings(conn)?;
+        let checksum = shadow_ratings_checksum::table
+            .select(shadow_ratings_checksum::checksum)
+            .first::<String>(conn)
+            .optional()?;
+        let shadow_games = shadow_game_ratings::table
+            .select(ShadowGameRating::as_select())
+            .load(conn)?;
+        let rated = load_history(conn, false)?
+            .into_iter()
+            .map(|game| game.id)
+            .collect::<HashSet<_>>();
+        let shadowed = shadow_games
+            .iter()
+            .map(|game| game.game.clone())
+            .collect::<HashSet<_>>();
+        if rated != shadowed {
+            return Err(PromoteError::Stale {
+                missing: rated.difference(&shadowed).count(),
+                extra: shadowed.difference(&rated).count(),
+            });
+        }
+        if checksum != Some(history_checksum(conn)?) {
+            return Err(PromoteError::Changed);
+        }
+
+        for game in &shadow_games {
+            diesel::update(game_results::table.find(&game.game))
+                .set((
+                    game_results::defender_rating_change.eq(game.defender_rating_change),
+                    game_results::challenger_rating_change.eq(game.challenger_rating_change),
+                    game_results::defender_rating.eq(game.defender_rating),
+                    game_results::challenger_rating.eq(game.challenger_rating),
+                    game_results::defender_deviation_before.eq(Some(game.defender_deviation_before)),
+                    game_results::defender_volatility_before
+                        .eq(Some(game.defender_volatility_before)),
+                    game_results::challenger_deviation_before
+                        .eq(Some(game.challenger_deviation_before)),
+                    game_results::challenger_volatility_before
+                        .eq(Some(game.challenger_volatility_before)),
+                    game_results::defender_bot_rating_change
+                        .eq(Some(game.defender_bot_rating_change)),
+                    game_results::challenger_bot_rating_change
+                        .eq(Some(game.challenger_bot_rating_change)),
+                    game_results::defender_bot_deviation_before
+                        .eq(Some(game.defender_bot_deviation_before)),
+                    game_results::defender_bot_volatility_before
+                        .eq(Some(game.defender_bot_volatility_before)),
+                    game_results::challenger_bot_deviation_before
+                        .eq(Some(game.challenger_bot_deviation_before)),
+                    game_results::challenger_bot_volatility_before
+                        .eq(Some(game.challenger_bot_volatility_before)),
+                ))
+                .execute(conn)?;
+            let (defender, challenger) = games::table
+                .find(&game.game)
+                .inner_join(defender_bots.on(games::defender.eq(defender_bots.field(bots::id))))
+                .inner_join(
+                    challenger_bots.on(games::challenger.eq(challenger_bots.field(bots::id))),
+                )
+                .select((
+                    defender_bots.field(bots::team),
+                    challenger_bots.field(bots::team),
+                ))
+                .first::<(i32, i32)>(conn)?;
+            for (team, rating, deviation) in [
+                (
+                    defender,
+                    game.defender_rating,
+                    game.defender_rating_deviation,
+                ),
+                (
+                    challenger,
+                    game.challenger_rating,
+                    game.challenger_rating_deviation,
+                ),
+            ] {
+                diesel::update(rating_history::table)
+                    .filter(rating_history::game.eq(&game.game))
+                    .filter(rating_history::team.eq(team))
+                    .set((
+                        rating_history::rating.eq(rating),
+                        rating_history::rating_deviation.eq(Some(deviation)),
+                    ))
+                    .execute(conn)?;
+            }
+        }
+
+        let now = chrono::offset::Utc::now().timestamp();
+        let team_ratings = shadow_team_ratings::table
+            .select(ShadowTeamRating::as_select())
+            .load(conn)?;
+        let mut history = vec![];
+        for shadow in &team_ratings {
+            let team: Team = diesel::update(teams::table.find(shadow.team))
+                .set((
+                    teams::rating.eq(shadow.rating),
+                    teams::rating_deviation.eq(shadow.rating_deviation),
+                    teams::rating_volatility.eq(shadow.rating_volatility),
+                ))
+                .get_result(conn)?;
+            history.push(NewRatingHistoryEntry {
+                team: team.id,
+                bot: None,
+                game: None,
+                rating: team.rating,
+                rating_deviation: Some(team.rating_deviation),
+                created: now,
+            });
+        }
+        conn.record_ratings(&history)?;
+        let bot_ratings = shadow_bot_ratings::table
+            .select(ShadowBotRating::as_select())
+            .load(conn)?;
+        for shadow in &bot_ratings {
+            diesel::update(bots::table.find(shadow.bot))
+                .set((
+                    bots::rating.eq(shadow.rating),
+                    bots::rating_deviation.eq(shadow.rating_deviation),
+                    bots::rating_volatility.eq(shadow.rating_volatility),
+                    bots::games_played.eq(shadow.games_played),
+                ))
+                .execute(conn)?;
+        }
+
+        clear_shadow(conn)?;
+        Ok(shadow_games.len())
+    })
+}
+
+fn clear_shadow(conn: &mut PgConnection) -> Result<(), diesel::result::Error> {
+    diesel::delete(shadow_ratings_checksum::table).execute(conn)?;
+    diesel::delete(shadow_game_ratings::table).execute(conn)?;
+    diesel::delete(shadow_bot_ratings::table).execute(conn)?;
+    diesel::delete(shadow_team_ratings::table).execute(conn)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(id: &str, finished: i64, defender: i32, challenger: i32, score: f32) -> PlayedGame {
+        PlayedGame {
+            id: id.to_string(),
+            finished,
+            defender_team: defender,
+            challenger_team: challenger,
+            defender_bot: defender * 10,
+            challenger_bot: challenger * 10,
+            score,
+        }
+    }
+
+    #[test]
+    fn recompute_replays_in_order() {
+        let options = RerateOptions {
+            system: RatingSystem::Elo,
+            params: RatingParams {
+                k_factor: 32.0,
+                ..Default::default()
+            },
+            adjusted: false,
+            rating_period: None,
+        };
+        let games = [game("a", 0, 1, 2, 1.0), game("b", 10, 2, 3, 0.5)];
+        let recomputed = recompute(&games, &options);
+
+        // Equal ratings, so the winner gets half the K-factor
+        assert_eq!(recomputed.games[0].defender_rating_change, 16.0);
+        assert_eq!(recomputed.teams[&1].rating, 1016.0);
+        // Team 2 lost the first game, so a draw against a new team gains rating
+        assert!(recomputed.games[1].defender_rating_change > 0.0);
+        assert_eq!(recomputed.bots[&20].1, 2);
+        assert_eq!(recomputed.bots[&30].0.rating, recomputed.teams[&3].rating);
+        // Each bot only played for its team, so it moved the same way
+        assert_eq!(
+            recomputed.games[1].defender_bot_rating_change,
+            recomputed.games[1].defender_rating_change
+        );
+        assert_eq!(
+            recomputed.games[0].defender_deviation_before,
+            Rating::default().deviation
+        );
+    }
+
+    #[test]
+    fn deviations_grow_between_rating_periods() {
+        let options = |rating_period| RerateOptions {
+            system: RatingSystem::Glicko2,
+            params: RatingParams::default(),
+            adjusted: false,
+            rating_period,
+        };
+        let games = [game("a", 0, 1, 2, 1.0), game("b", 100, 1, 2, 1.0)];
+        let continuous = recompute(&games, &options(None));
+        let periods = recompute(&games, &options(Some(50)));
+        assert_eq!(continuous.games[0], periods.games[0]);
+        assert!(
+            periods.games[1].defender_rating_change > continuous.games[1].defender_rating_change
+        );
+    }
+}